@@ -49,6 +49,7 @@ async fn demo_basic_arbitrage_strategy() -> Result<()> {
         gas_per_hop: 150_000,
         max_precomputed_paths: 500,
         enable_parallel_calculation: true,
+        ..ArbitrageConfig::default()
     };
     
     let mut engine = ArbitrageEngine::new(basic_config);
@@ -98,6 +99,7 @@ async fn demo_advanced_risk_management() -> Result<()> {
         gas_per_hop: 200_000,
         max_precomputed_paths: 2000,
         enable_parallel_calculation: true,
+        ..ArbitrageConfig::default()
     };
     
     let mut aggressive_engine = ArbitrageEngine::new(aggressive_config);
@@ -111,6 +113,7 @@ async fn demo_advanced_risk_management() -> Result<()> {
         gas_per_hop: 120_000,
         max_precomputed_paths: 1000,
         enable_parallel_calculation: true,
+        ..ArbitrageConfig::default()
     };
     
     let mut conservative_engine = ArbitrageEngine::new(conservative_config);
@@ -147,6 +150,7 @@ async fn demo_multi_path_optimization() -> Result<()> {
         gas_per_hop: 150_000,
         max_precomputed_paths: 3000,
         enable_parallel_calculation: true,
+        ..ArbitrageConfig::default()
     };
     
     let mut engine = ArbitrageEngine::new(config);
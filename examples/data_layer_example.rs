@@ -3,7 +3,7 @@
 /// This example demonstrates how to use the data synchronization layer
 /// according to the design document's architecture (方案A).
 
-use swap_path::data_sync::{DataSyncConfig, DataSyncServiceBuilder};
+use swap_path::data_sync::{DataSyncConfig, DataSyncServiceBuilder, RpcEndpoint};
 // use swap_path::logic::pools::PoolId; // Removed unused import
 // use alloy_primitives::Address;
 use eyre::Result;
@@ -50,7 +50,7 @@ async fn main() -> Result<()> {
             info!("Received market snapshot #{}: block={}, pools={}, timestamp={}", 
                   snapshot_count,
                   market_snapshot.block_number,
-                  market_snapshot.pool_reserves.len(),
+                  market_snapshot.pools_with_data_count(),
                   market_snapshot.timestamp);
             
             // Example: Process the market snapshot
@@ -61,6 +61,10 @@ async fn main() -> Result<()> {
                 let stats = data_service.get_stats().await;
                 info!("Service stats: monitored_pools={}", 
                       stats.monitored_pools_count);
+                for endpoint in &stats.endpoints {
+                    info!("  {} healthy={} latency={:?}ms errors={:.2} lag={}",
+                          endpoint.url, endpoint.healthy, endpoint.latency_ms, endpoint.error_rate, endpoint.block_lag);
+                }
             }
         }
         
@@ -90,7 +94,7 @@ async fn process_market_snapshot(snapshot: &swap_path::logic::types::MarketSnaps
     let mut total_liquidity = 0.0;
     let mut pool_count = 0;
     
-    for (pool_id, (reserve0, reserve1)) in &snapshot.pool_reserves {
+    for (pool_id, (reserve0, reserve1)) in snapshot.iter_pool_reserves() {
         pool_count += 1;
         
         // Simple liquidity calculation (assuming both tokens have similar value)
@@ -161,12 +165,18 @@ fn create_configs_for_different_environments() -> Result<()> {
         reconnect_delay_secs: 5,
         http_timeout_secs: 15,
         channel_buffer_size: 50,
+        ..DataSyncConfig::default()
     };
     
     // Production configuration
     let prod_config = DataSyncConfig {
         rpc_wss_url: "wss://prod-rpc.mantle.xyz".to_string(),
         rpc_http_url: "https://prod-rpc.mantle.xyz".to_string(),
+        // Fail over to the public endpoint when the primary one degrades
+        rpc_http_endpoints: vec![
+            RpcEndpoint::new("https://prod-rpc.mantle.xyz").with_weight(2.0),
+            RpcEndpoint::new("https://rpc.mantle.xyz"),
+        ],
         multicall_address: "0xcA11bde05977b3631167028862bE2a173976CA11".to_string(),
         max_pools_per_batch: 50, // Larger batches for efficiency
         ws_connection_timeout_secs: 10, // Faster timeout
@@ -174,6 +184,7 @@ fn create_configs_for_different_environments() -> Result<()> {
         reconnect_delay_secs: 2, // Faster reconnection
         http_timeout_secs: 5, // Tighter timeout
         channel_buffer_size: 200, // Larger buffer
+        ..DataSyncConfig::default()
     };
     
     info!("Dev config max batch size: {}", dev_config.max_pools_per_batch);
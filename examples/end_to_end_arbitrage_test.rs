@@ -185,6 +185,7 @@ async fn setup_data_layer(initial_pools: Vec<PoolWrapper>) -> Result<swap_path::
         reconnect_delay_secs: 1,
         http_timeout_secs: 5,
         channel_buffer_size: 100,
+        ..DataSyncConfig::default()
     };
     
     let service = DataSyncServiceBuilder::new()
@@ -209,6 +210,7 @@ async fn setup_arbitrage_engine(market: &Market) -> Result<ArbitrageEngine> {
         gas_per_hop: 150_000,
         max_precomputed_paths: 1000,
         enable_parallel_calculation: true,
+        ..ArbitrageConfig::default()
     };
     
     let mut engine = ArbitrageEngine::new(config);
@@ -326,7 +328,7 @@ fn create_market_snapshot(
     snapshot.set_enabled_pools(enabled_pools);
     snapshot.set_total_pools_count(5);
     
-    debug!("创建快照 - 区块: {}, 池子数: {}", block_number, snapshot.pool_reserves.len());
+    debug!("创建快照 - 区块: {}, 池子数: {}", block_number, snapshot.pools_with_data_count());
     
     snapshot
 }
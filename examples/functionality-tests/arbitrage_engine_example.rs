@@ -33,7 +33,7 @@ async fn main() -> Result<()> {
         .with_min_profit_threshold(1.0) // $1 minimum profit for demo
         .with_max_hops(4)
         .with_parallel_calculation(true)
-        .build();
+        .build()?;
     
     // Initialize with precomputed paths
     engine.initialize(&market.token_graph)?;
@@ -128,7 +128,7 @@ fn create_sample_market_snapshot() -> MarketSnapshot {
         snapshot.set_pool_reserves(PoolId::Address(pool_addr), reserve0, reserve1);
     }
     
-    println!("创建了包含 {} 个池储备的市场快照", snapshot.pool_reserves.len());
+    println!("创建了包含 {} 个池储备的市场快照", snapshot.pools_with_data_count());
     snapshot
 }
 
@@ -307,6 +307,7 @@ fn create_live_config() -> Result<DataSyncConfig> {
         reconnect_delay_secs: 3,
         http_timeout_secs: 30, // 历史数据查询可能需要更长时间
         channel_buffer_size: 100,
+        ..DataSyncConfig::default()
     };
     
     info!("配置详情:");
@@ -549,6 +550,7 @@ fn create_live_arbitrage_engine(market: &Market) -> Result<ArbitrageEngine> {
         gas_per_transaction: if max_hops <= 3 { 700_000_000 } else { 720_000_000 }, // 3跳约700M，4跳约720M
         max_precomputed_paths: 5000, // 增加路径数量以发现更多WMNT循环机会
         enable_parallel_calculation: true,
+        ..ArbitrageConfig::default()
     };
     
     info!("✅ 套利引擎配置:");
@@ -705,13 +707,13 @@ async fn get_real_pool_reserves_for_block(snapshot: &mut MarketSnapshot, block_n
             let mut success_count = 0;
             let mut failed_count = 0;
             
-            for (pool_id, reserves_opt) in results {
+            for (pool_id, reserves) in results {
                 if let PoolId::Address(address) = pool_id {
                     let default_name = "Unknown Pool".to_string();
                     let pool_name = name_map.get(&address).unwrap_or(&default_name);
                     
-                    match reserves_opt {
-                        Some((reserve0, reserve1)) => {
+                    match reserves {
+                        Ok((reserve0, reserve1)) => {
                             snapshot.set_pool_reserves(pool_id, reserve0, reserve1);
                             info!("✅ {} ({}): R0={:.6}, R1={:.6}", 
                                   pool_name, 
@@ -720,10 +722,11 @@ async fn get_real_pool_reserves_for_block(snapshot: &mut MarketSnapshot, block_n
                                   wei_to_ether_f64(reserve1));
                             success_count += 1;
                         }
-                        None => {
-                            warn!("❌ 获取池子 {} ({}) 储备失败", 
+                        Err(e) => {
+                            warn!("❌ 获取池子 {} ({}) 储备失败: {}", 
                                   pool_name, 
-                                  format!("0x{:x}", address));
+                                  format!("0x{:x}", address),
+                                  e);
                             failed_count += 1;
                         }
                     }
@@ -1172,10 +1175,10 @@ fn print_all_arbitrage_paths(_engine: &ArbitrageEngine, market: &Market) -> Resu
 
 /// 打印指定区块的所有池子储备情况
 fn print_block_pool_reserves(snapshot: &MarketSnapshot, block_number: u64) {
-    info!("💧 区块 {} 的真实池子储备情况 ({} 个池子):", block_number, snapshot.pool_reserves.len());
+    info!("💧 区块 {} 的真实池子储备情况 ({} 个池子):", block_number, snapshot.pools_with_data_count());
     
     // 按池子名称排序以便于阅读
-    let mut pools: Vec<_> = snapshot.pool_reserves.iter().collect();
+    let mut pools: Vec<_> = snapshot.iter_pool_reserves().collect();
     pools.sort_by_key(|(pool_id, _)| {
         let pool_address = match pool_id {
             PoolId::Address(addr) => format!("0x{:x}", addr),
@@ -4,22 +4,22 @@
 /// 发现并计算真实的套利机会，但不执行交易。
 
 use swap_path::data_sync::{DataSyncConfig, DataSyncServiceBuilder};
-use swap_path::logic::{ArbitrageEngine, ArbitrageOpportunity};
+use swap_path::logic::{ArbitrageEngine, ArbitrageOpportunity, ConfigWatcher, OpportunityEvent};
 use swap_path::logic::types::{ArbitrageConfig, MarketSnapshot};
 use swap_path::logic::pools::{MockPool};
 use swap_path::{PoolWrapper, Token};
+use swap_path::utils::FluxConfigLoaderSync;
 use swap_path::data_sync::markets::{Market, MarketConfigSection};
-use swap_path::logic::graph::SwapPathHash;
 use alloy_primitives::{Address, U256};
 use eyre::Result;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::mpsc;
 use tokio::time::sleep;
 use tracing::{info, warn, error, debug};
 use std::fs;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
-use std::collections::HashSet;
 
 
 // Mantle 主网配置
@@ -65,60 +65,7 @@ struct ArbitrageRecord {
     pool_addresses: String,
     hop_count: usize,
     execution_priority: String,
-}
-
-// 套利机会去重跟踪器
-#[derive(Debug)]
-struct ArbitrageOpportunityTracker {
-    /// 已处理的套利机会路径哈希集合
-    processed_opportunities: HashSet<SwapPathHash>,
-    /// 可选：设置最大缓存大小以避免内存无限增长
-    max_cache_size: usize,
-}
-
-impl ArbitrageOpportunityTracker {
-    fn new(max_cache_size: usize) -> Self {
-        Self {
-            processed_opportunities: HashSet::new(),
-            max_cache_size,
-        }
-    }
-
-    /// 检查套利机会是否已被处理，如果没有则标记为已处理
-    fn is_new_opportunity(&mut self, opportunity: &ArbitrageOpportunity) -> bool {
-        let hash = opportunity.path.swap_path_hash.clone();
-        
-        // 如果缓存太大，清理一半（简单的LRU策略）
-        if self.processed_opportunities.len() >= self.max_cache_size {
-            let keys_to_remove: Vec<_> = self.processed_opportunities
-                .iter()
-                .take(self.max_cache_size / 2)
-                .cloned()
-                .collect();
-            
-            for key in keys_to_remove {
-                self.processed_opportunities.remove(&key);
-            }
-            
-            debug!("清理套利机会缓存，当前大小: {}", self.processed_opportunities.len());
-        }
-
-        // 检查是否是新机会
-        self.processed_opportunities.insert(hash)
-    }
-
-    /// 过滤出新的套利机会
-    fn filter_new_opportunities(&mut self, opportunities: &[ArbitrageOpportunity]) -> Vec<ArbitrageOpportunity> {
-        opportunities.iter()
-            .filter(|opp| self.is_new_opportunity(opp))
-            .cloned()
-            .collect()
-    }
-
-    /// 获取统计信息
-    fn get_stats(&self) -> (usize, usize) {
-        (self.processed_opportunities.len(), self.max_cache_size)
-    }
+    hop_details: String,
 }
 
 #[tokio::main]
@@ -157,6 +104,8 @@ async fn main() -> Result<()> {
     
     // 创建套利引擎
     let mut arbitrage_engine = create_live_arbitrage_engine(&market)?;
+    // 引擎与数据层共用一个指标注册表，设置 METRICS_LISTEN_ADDR 后通过 /metrics 暴露
+    arbitrage_engine.set_metrics(data_service.metrics());
     
     // 启动实时监控
     run_live_monitoring(&mut data_service, &mut arbitrage_engine).await?;
@@ -221,6 +170,7 @@ fn create_live_config() -> Result<DataSyncConfig> {
         reconnect_delay_secs: 5,
         http_timeout_secs: 20,
         channel_buffer_size: 1000,
+        ..DataSyncConfig::default()
     };
     
     info!("配置详情:");
@@ -444,6 +394,7 @@ async fn record_arbitrage_opportunities_to_csv(
             "pool_addresses",
             "hop_count",
             "execution_priority",
+            "hop_details",
         ])?;
         writer.flush()?;
     }
@@ -501,6 +452,19 @@ fn create_arbitrage_record(
         })
         .collect();
     let pool_addresses_str = pool_addresses.join(",");
+
+    // 每跳明细: 输入 -> 输出, 手续费, 价格影响, 交易前后现货价格
+    let hop_details: Vec<String> = opportunity.hops.iter()
+        .map(|hop| format!(
+            "{:.6}->{:.6} fee={:.6} impact={:.4}% spot={:.6}->{:.6}",
+            wei_to_ether_f64(hop.amount_in),
+            wei_to_ether_f64(hop.amount_out),
+            wei_to_ether_f64(hop.fee_paid),
+            hop.price_impact * 100.0,
+            hop.spot_price_before,
+            hop.spot_price_after,
+        ))
+        .collect();
     
     // 计算ROI并确定执行优先级
     let roi = calculate_roi(opportunity);
@@ -526,6 +490,7 @@ fn create_arbitrage_record(
         pool_addresses: pool_addresses_str,
         hop_count: opportunity.path.tokens.len() - 1,
         execution_priority,
+        hop_details: hop_details.join(" | "),
     })
 }
 
@@ -570,7 +535,31 @@ fn create_test_pools_for_live_demo() -> Result<Vec<PoolWrapper>> {
 fn create_live_arbitrage_engine(market: &Market) -> Result<ArbitrageEngine> {
     info!("🧠 创建实时套利引擎...");
     
-    // 从 .env 文件读取配置，带默认值
+    // 优先从 ARBITRAGE_CONFIG_FILE 指向的 TOML 文件的 [arbitrage] 段读取配置
+    let config = match std::env::var("ARBITRAGE_CONFIG_FILE") {
+        Ok(file_name) => {
+            info!("从 {} 加载套利引擎配置", file_name);
+            ArbitrageConfig::load_section_from_file_sync(file_name)?
+        }
+        Err(_) => arbitrage_config_from_env(),
+    };
+    
+    info!("✅ 套利引擎配置:");
+    let min_profit_mnt = config.min_profit_threshold_mnt_wei.to_string().parse::<f64>().unwrap_or(0.0) / 1e18;
+    info!("  最小利润门槛: {:.6} MNT", min_profit_mnt);
+    info!("  最大跳数: {}", config.max_hops);
+    info!("  并行计算: {}", config.enable_parallel_calculation);
+    
+    let mut engine = ArbitrageEngine::new(config);
+    engine.initialize(&market.token_graph)?;
+    
+    info!("✅ 套利引擎初始化完成");
+    
+    Ok(engine)
+}
+
+/// 从 .env 文件读取配置，带默认值
+fn arbitrage_config_from_env() -> ArbitrageConfig {
     let min_profit_threshold = std::env::var("MIN_PROFIT_THRESHOLD_USD")
         .ok()
         .and_then(|s| s.parse().ok())
@@ -587,27 +576,15 @@ fn create_live_arbitrage_engine(market: &Market) -> Result<ArbitrageEngine> {
         .unwrap_or(20);
     
     // 生产环境的配置
-    let config = ArbitrageConfig {
+    ArbitrageConfig {
         min_profit_threshold_mnt_wei: U256::from_str_radix(&((min_profit_threshold / 1.1 * 1e18) as u64).to_string(), 10).unwrap(), // 转换为MNT Wei
         max_hops,
         gas_price_gwei: gas_price_gwei as f64,
         gas_per_transaction: 700_000_000,    // 700M gas per transaction
         max_precomputed_paths: 1000,     // 平衡内存使用和覆盖度
         enable_parallel_calculation: true,
-    };
-    
-    info!("✅ 套利引擎配置:");
-    let min_profit_mnt = config.min_profit_threshold_mnt_wei.to_string().parse::<f64>().unwrap_or(0.0) / 1e18;
-    info!("  最小利润门槛: {:.6} MNT", min_profit_mnt);
-    info!("  最大跳数: {}", config.max_hops);
-    info!("  并行计算: {}", config.enable_parallel_calculation);
-    
-    let mut engine = ArbitrageEngine::new(config);
-    engine.initialize(&market.token_graph)?;
-    
-    info!("✅ 套利引擎初始化完成");
-    
-    Ok(engine)
+        ..ArbitrageConfig::default()
+    }
 }
 
 /// 运行实时监控
@@ -643,20 +620,45 @@ async fn run_live_monitoring(
     
     info!("📡 开始监听区块数据...");
     
-    // 初始化套利机会去重跟踪器
-    let mut opportunity_tracker = ArbitrageOpportunityTracker::new(10000); // 最多缓存10000个已处理的机会
-    info!("✅ 套利机会去重系统已启用，缓存大小: {}", opportunity_tracker.max_cache_size);
+    // 套利引擎跨区块跟踪机会，只报告新出现、变化和消失的机会
+    info!("✅ 套利机会跟踪已启用");
     
     // 监控统计
     let mut blocks_processed = 0u64;
-    let mut total_opportunities = 0u64;
+    let mut total_opportunity_events = 0u64; // 出现、更新、过期事件总数，不是机会数
     let mut total_profit_mnt = 0.0f64;
-    let mut total_unique_opportunities = 0u64; // 新增：独特机会计数
+    let mut total_unique_opportunities = 0u64; // 只统计新出现的机会
     let start_time = std::time::Instant::now();
     
+    // 配置文件变更时热更新引擎配置，需要重新初始化的变更会被拒绝
+    let (config_tx, mut config_rx) = mpsc::channel(4);
+    let _config_watcher = std::env::var("ARBITRAGE_CONFIG_FILE")
+        .ok()
+        .map(|file_name| ConfigWatcher::new(file_name, arbitrage_engine.get_config().clone()).spawn(config_tx));
+    
+    // 链重组在新链头的快照之前发出，孤块上的机会先作废
+    let mut reorg_rx = data_service.subscribe_reorgs();
+    
     // 主监控循环
     loop {
         tokio::select! {
+            biased;
+            
+            Some(new_config) = config_rx.recv() => {
+                match arbitrage_engine.update_config(new_config) {
+                    Ok(()) => info!("✅ 套利引擎配置已热更新"),
+                    Err(e) => warn!("配置热更新失败: {}", e),
+                }
+            }
+            
+            Ok(reorg) = reorg_rx.recv() => {
+                match arbitrage_engine.handle_reorg(&reorg) {
+                    Ok(events) => warn!("⚠️ 链重组: {} 个孤块, {} 个套利机会作废", reorg.depth(), events.len()),
+                    Err(e) => warn!("处理链重组失败: {}", e),
+                }
+            }
+            
+
             // 处理新的市场数据
             market_data = market_data_rx.recv() => {
                 match market_data {
@@ -664,13 +666,18 @@ async fn run_live_monitoring(
                         blocks_processed += 1;
                         
                         // 分析套利机会
-                        match analyze_arbitrage_opportunities(arbitrage_engine, &snapshot).await {
-                            Ok(opportunities) => {
-                                if !opportunities.is_empty() {
-                                    total_opportunities += opportunities.len() as u64;
+                        match analyze_arbitrage_events(arbitrage_engine, &snapshot).await {
+                            Ok(events) => {
+                                if !events.is_empty() {
+                                    total_opportunity_events += events.len() as u64;
                                     
-                                    // 使用去重跟踪器过滤新的套利机会
-                                    let new_opportunities = opportunity_tracker.filter_new_opportunities(&opportunities);
+                                    // 只处理新出现的套利机会
+                                    let new_opportunities: Vec<ArbitrageOpportunity> = events.iter()
+                                        .filter_map(|event| match event {
+                                            OpportunityEvent::New(tracked) => Some(tracked.latest.clone()),
+                                            _ => None,
+                                        })
+                                        .collect();
                                     
                                     if !new_opportunities.is_empty() {
                                         total_unique_opportunities += new_opportunities.len() as u64;
@@ -682,8 +689,8 @@ async fn run_live_monitoring(
                                         // 只显示和记录新的套利机会
                                         display_arbitrage_opportunities(&snapshot, &new_opportunities);
                                     } else {
-                                        debug!("区块 {} - 发现 {} 个套利机会，但都是重复的", 
-                                              snapshot.block_number, opportunities.len());
+                                        debug!("区块 {} - {} 个套利机会事件，没有新机会", 
+                                              snapshot.block_number, events.len());
                                     }
                                 }
                                 
@@ -691,11 +698,11 @@ async fn run_live_monitoring(
                                 if blocks_processed % 10 == 0 {
                                     display_monitoring_stats_with_dedup(
                                         blocks_processed,
-                                        total_opportunities,
+                                        total_opportunity_events,
                                         total_unique_opportunities,
                                         total_profit_mnt,
                                         start_time.elapsed(),
-                                        &opportunity_tracker,
+                                        arbitrage_engine.tracked_opportunities_count(),
                                     );
                                 }
                             }
@@ -722,30 +729,30 @@ async fn run_live_monitoring(
     // 显示最终统计
     display_final_stats_with_dedup(
         blocks_processed, 
-        total_opportunities, 
+        total_opportunity_events, 
         total_unique_opportunities, 
         total_profit_mnt, 
         start_time.elapsed(),
-        &opportunity_tracker
+        arbitrage_engine.tracked_opportunities_count(),
     );
     
     Ok(())
 }
 
-/// 分析套利机会
-async fn analyze_arbitrage_opportunities(
+/// 分析套利机会，返回相对上一区块的变化
+async fn analyze_arbitrage_events(
     engine: &ArbitrageEngine,
     snapshot: &MarketSnapshot,
-) -> Result<Vec<swap_path::logic::ArbitrageOpportunity>> {
+) -> Result<Vec<OpportunityEvent>> {
     debug!("分析区块 {} 的套利机会", snapshot.block_number);
     
-    let opportunities = engine.process_market_snapshot(snapshot)?;
+    let events = engine.process_market_snapshot_events(snapshot)?;
     
-    if !opportunities.is_empty() {
-        debug!("区块 {} 发现 {} 个套利机会", snapshot.block_number, opportunities.len());
+    if !events.is_empty() {
+        debug!("区块 {} 产生 {} 个套利机会事件", snapshot.block_number, events.len());
     }
     
-    Ok(opportunities)
+    Ok(events)
 }
 
 /// 显示发现的新套利机会（已去重）
@@ -851,26 +858,27 @@ fn display_final_stats(
 /// 显示简化的监控统计信息
 fn display_monitoring_stats_with_dedup(
     blocks_processed: u64,
-    _total_opportunities: u64,
+    _total_opportunity_events: u64,
     unique_opportunities: u64,
     total_profit_mnt: f64,
     elapsed: Duration,
-    _tracker: &ArbitrageOpportunityTracker,
+    tracked_opportunities: usize,
 ) {
     info!("📊 监控统计 (已运行 {:?}):", elapsed);
     info!("  已处理区块: {}", blocks_processed);
     info!("  套利机会: {}", unique_opportunities);
+    info!("  跟踪中的机会: {}", tracked_opportunities);
     info!("  累计潜在利润: {:.6} MNT", total_profit_mnt);
 }
 
 /// 显示简化的最终统计
 fn display_final_stats_with_dedup(
     blocks_processed: u64,
-    _total_opportunities: u64,
+    _total_opportunity_events: u64,
     unique_opportunities: u64,
     total_profit_mnt: f64,
     total_elapsed: Duration,
-    _tracker: &ArbitrageOpportunityTracker,
+    _tracked_opportunities: usize,
 ) {
     info!("📋 最终统计报告:");
     info!("{}", "=".repeat(40));
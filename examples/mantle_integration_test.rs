@@ -80,6 +80,7 @@ async fn create_mantle_config() -> Result<DataSyncConfig> {
         reconnect_delay_secs: 3,
         http_timeout_secs: 15,
         channel_buffer_size: 1000,
+        ..DataSyncConfig::default()
     };
     
     info!("配置完成:");
@@ -227,6 +228,7 @@ async fn setup_real_data_sync(config: DataSyncConfig) -> Result<()> {
         gas_per_hop: 200_000, // 更保守的gas估算
         max_precomputed_paths: 10000,
         enable_parallel_calculation: true,
+        ..ArbitrageConfig::default()
     };
     
     // 创建市场
@@ -272,7 +274,7 @@ async fn run_short_monitoring_test(
                     Ok(Some(market_snapshot)) => {
                         info!("收到市场快照，区块: {}, 池子数: {}", 
                               market_snapshot.block_number, 
-                              market_snapshot.pool_reserves.len());
+                              market_snapshot.pools_with_data_count());
                         
                         // 如果引擎已初始化，尝试发现套利机会
                         if arbitrage_engine.is_initialized {
@@ -379,6 +381,7 @@ mod tests {
             reconnect_delay_secs: 1,
             http_timeout_secs: 1,
             channel_buffer_size: 10,
+            ..DataSyncConfig::default()
         };
         
         // 这应该失败但不会panic
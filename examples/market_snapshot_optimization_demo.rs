@@ -63,9 +63,9 @@ async fn create_enhanced_market_snapshot() -> Result<MarketSnapshot> {
     // 🆕 设置总池子数量
     snapshot.set_total_pools_count(10); // 假设市场总共有10个池子
     
-    info!("✅ 创建了包含 {} 个池子的市场快照", snapshot.pool_reserves.len());
+    info!("✅ 创建了包含 {} 个池子的市场快照", snapshot.pools_with_data_count());
     info!("📊 启用池子: {}, 总池子: {}", 
-          snapshot.enabled_pools.len(), 
+          snapshot.enabled_pools_count(), 
           snapshot.total_pools_count);
     
     Ok(snapshot)
@@ -97,10 +97,10 @@ async fn demonstrate_optimization_features(snapshot: &MarketSnapshot) -> Result<
     // 4. 市场统计信息
     info!("📊 市场统计:");
     info!("  总池子数: {}", snapshot.total_pools_count);
-    info!("  启用池子数: {}", snapshot.enabled_pools.len());
-    info!("  有储备量数据的池子: {}", snapshot.pool_reserves.len());
+    info!("  启用池子数: {}", snapshot.enabled_pools_count());
+    info!("  有储备量数据的池子: {}", snapshot.pools_with_data_count());
     info!("  数据完整性: {:.1}%", 
-          (enabled_with_data as f64 / snapshot.enabled_pools.len() as f64) * 100.0);
+          (enabled_with_data as f64 / snapshot.enabled_pools_count() as f64) * 100.0);
     
     Ok(())
 }
@@ -156,9 +156,9 @@ async fn validate_snapshot_consistency(snapshot: &MarketSnapshot) -> Result<()>
     // 检查所有有储备量数据的池子是否都在启用列表中
     let mut inconsistent_pools = Vec::new();
     
-    for pool_id in snapshot.pool_reserves.keys() {
-        if !snapshot.enabled_pools.contains(pool_id) {
-            inconsistent_pools.push(*pool_id);
+    for (pool_id, _) in snapshot.iter_pool_reserves() {
+        if !snapshot.is_pool_enabled(&pool_id) {
+            inconsistent_pools.push(pool_id);
         }
     }
     
@@ -172,9 +172,9 @@ async fn validate_snapshot_consistency(snapshot: &MarketSnapshot) -> Result<()>
     }
     
     // 检查启用池子的数据完整性
-    let missing_data_pools: Vec<_> = snapshot.enabled_pools
-        .iter()
-        .filter(|pool_id| !snapshot.pool_reserves.contains_key(pool_id))
+    let missing_data_pools: Vec<_> = snapshot.enabled_pools()
+        .into_iter()
+        .filter(|pool_id| snapshot.get_pool_reserves(pool_id).is_none())
         .collect();
     
     if !missing_data_pools.is_empty() {
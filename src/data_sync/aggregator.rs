@@ -1,22 +1,77 @@
 use crate::logic::types::MarketSnapshot;
-use crate::logic::pools::PoolId;
+use crate::logic::pools::{DensePoolIndex, PoolId};
+use crate::data_sync::failover::EndpointHealth;
 use crate::data_sync::multicall::MulticallManager;
+use crate::data_sync::sync_events::{SyncLogFetcher, has_sync_logs};
 use crate::data_sync::websocket::BlockHeader;
+use crate::utils::{Metric, MetricsRegistry, Stage};
 // use crate::data_sync::markets::{Market, MarketWithoutLock}; // Removed unused imports
 use alloy_primitives::U256;
 use eyre::Result;
+use futures::StreamExt;
 use std::collections::HashMap;
-use std::time::Instant;
-// use std::sync::Arc; // Removed unused import
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{info, warn, error, debug};
 
+/// Pools per multicall batch, adapted to the observed RPC latency
+///
+/// A batch slower than the target latency shrinks the size by a quarter, a batch faster than
+/// half of it grows the size by a quarter. A failed batch, e.g. a timeout or a response over
+/// the node's size limit, halves it. The size stays between `min` and `max`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdaptiveBatchSize {
+    current: usize,
+    min: usize,
+    max: usize,
+    target_latency: Duration,
+}
+
+impl AdaptiveBatchSize {
+    /// Start at `max`
+    pub fn new(min: usize, max: usize, target_latency: Duration) -> Self {
+        let max = max.max(1);
+        Self { current: max, min: min.clamp(1, max), max, target_latency }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn observe_success(&mut self, latency: Duration) {
+        if latency > self.target_latency {
+            self.current = (self.current - (self.current / 4).max(1)).max(self.min);
+        } else if latency < self.target_latency / 2 {
+            self.current = (self.current + (self.current / 4).max(1)).min(self.max);
+        }
+    }
+
+    pub fn observe_failure(&mut self) {
+        self.current = (self.current / 2).max(self.min);
+    }
+}
+
+/// State of event-driven reserve sync, see [DataAggregator::with_event_sync]
+struct EventSync {
+    fetcher: SyncLogFetcher,
+    full_resync_interval_blocks: u64,
+    last_full_resync: Option<u64>,
+    resync_requested: bool,
+}
+
 /// Data aggregator that combines pool data into market snapshots
 /// Reuses existing Market infrastructure for better code reuse
 pub struct DataAggregator {
     multicall_manager: MulticallManager,
     max_pools_per_batch: usize,
+    batch_size: AdaptiveBatchSize,
+    max_concurrent_batches: usize,
     // Track previous reserves to detect changes
     previous_reserves: HashMap<PoolId, (U256, U256)>,
+    // Sync logs applied to previous_reserves instead of polling every pool, when enabled
+    event_sync: Option<EventSync>,
+    last_block: Option<u64>,
+    metrics: Arc<MetricsRegistry>,
 }
 
 impl DataAggregator {
@@ -28,15 +83,67 @@ impl DataAggregator {
         Self {
             multicall_manager,
             max_pools_per_batch,
+            batch_size: AdaptiveBatchSize::new(max_pools_per_batch, max_pools_per_batch, Duration::MAX),
+            max_concurrent_batches: 1,
             previous_reserves: HashMap::new(),
+            event_sync: None,
+            last_block: None,
+            metrics: Arc::new(MetricsRegistry::new()),
         }
     }
     
+    /// Number of multicall batches in flight at the same time
+    pub fn with_max_concurrent_batches(mut self, max_concurrent_batches: usize) -> Self {
+        self.max_concurrent_batches = max_concurrent_batches.max(1);
+        self
+    }
+    
+    /// Adapt the batch size between `min_pools_per_batch` and `max_pools_per_batch` to keep
+    /// batches around `target_latency`, see [AdaptiveBatchSize]
+    pub fn with_adaptive_batch_size(mut self, min_pools_per_batch: usize, target_latency: Duration) -> Self {
+        self.batch_size = AdaptiveBatchSize::new(min_pools_per_batch, self.max_pools_per_batch, target_latency);
+        self
+    }
+    
+    /// Keep reserves up to date from the `Sync` logs of each block instead of polling every pool
+    ///
+    /// Only pools without a known state are queried by multicall. All monitored pools are
+    /// queried again every `full_resync_interval_blocks`, after missed blocks, when the logs of
+    /// a block cannot be fetched and after [DataAggregator::request_full_resync].
+    pub fn with_event_sync(mut self, fetcher: SyncLogFetcher, full_resync_interval_blocks: u64) -> Self {
+        self.event_sync = Some(EventSync {
+            fetcher,
+            full_resync_interval_blocks: full_resync_interval_blocks.max(1),
+            last_full_resync: None,
+            resync_requested: false,
+        });
+        self
+    }
+    
+    /// Query all monitored pools on the next block, e.g. after the block subscription was
+    /// re-established and logs may have been missed. No-op when polling.
+    pub fn request_full_resync(&mut self) {
+        if let Some(event_sync) = self.event_sync.as_mut() {
+            event_sync.resync_requested = true;
+        }
+    }
+    
+    /// Record batch latencies into a shared metrics registry
+    pub fn set_metrics(&mut self, metrics: Arc<MetricsRegistry>) {
+        self.metrics = metrics;
+    }
 
     
     /// Aggregate pool data for a new block into a market snapshot
     /// Includes detailed logging for performance monitoring and change detection
-    pub async fn aggregate_market_data(&mut self, block_header: &BlockHeader, monitored_pools: Vec<PoolId>, total_pools_count: Option<usize>) -> Result<MarketSnapshot> {
+    /// The snapshot is laid out by `pool_index`, normally the market's dense pool index
+    pub async fn aggregate_market_data(
+        &mut self,
+        block_header: &BlockHeader,
+        monitored_pools: Vec<PoolId>,
+        total_pools_count: Option<usize>,
+        pool_index: Arc<DensePoolIndex>,
+    ) -> Result<MarketSnapshot> {
         let start_time = Instant::now();
         
         let block_number = block_header.block_number()?;
@@ -44,11 +151,11 @@ impl DataAggregator {
         
         if monitored_pools.is_empty() {
             warn!("No pools to monitor");
-            return Ok(MarketSnapshot::new(block_number));
+            return Ok(MarketSnapshot::with_pool_index(block_number, pool_index));
         }
         
         // Create new market snapshot with pool context
-        let mut snapshot = MarketSnapshot::new(block_number);
+        let mut snapshot = MarketSnapshot::with_pool_index(block_number, pool_index);
         
         // Set enabled pools and total count for optimization (avoid repeated MarketWithoutLock queries)
         let enabled_pools_set = monitored_pools.iter().cloned().collect();
@@ -61,44 +168,94 @@ impl DataAggregator {
         let fetch_start = Instant::now();
         info!("Block {}: Starting reserves fetch for {} pools", block_number, monitored_pools.len());
         
+        // In event-driven sync only pools without a known state are queried, unless a full resync is due
+        let (pools_to_fetch, event_reserves, full_resync) = self.plan_reserve_fetch(block_header, block_number, &monitored_pools).await;
+        
         // Batch query pools
-        let pool_batches = self.split_pools_into_batches(&monitored_pools);
+        let batch_size = self.batch_size.current();
+        let pool_batches = if pools_to_fetch.is_empty() { Vec::new() } else { self.split_pools_into_batches(&pools_to_fetch) };
         let mut all_pool_data = HashMap::new();
         let mut total_successful = 0;
         let mut total_failed = 0;
+        let mut failed_batches = 0;
         
-        for (batch_idx, batch) in pool_batches.iter().enumerate() {
-            let batch_start = Instant::now();
-            
-            match self.multicall_manager.batch_get_reserves(batch, Some(block_number)).await {
+        // Issue the batches concurrently, results are merged in completion order
+        let mut batch_results = futures::stream::iter(pool_batches.into_iter().enumerate())
+            .map(|(batch_idx, batch)| {
+                let multicall_manager = self.multicall_manager.clone();
+                async move {
+                    let batch_start = Instant::now();
+                    let result = multicall_manager.batch_get_reserves(&batch, Some(block_number)).await;
+                    (batch_idx, batch, result, batch_start.elapsed())
+                }
+            })
+            .buffer_unordered(self.max_concurrent_batches);
+        
+        while let Some((batch_idx, batch, result, batch_elapsed)) = batch_results.next().await {
+            self.metrics.observe_duration(Stage::MulticallBatch, batch_elapsed);
+            match result {
                 Ok(batch_results) => {
-                    let batch_elapsed = batch_start.elapsed();
+                    self.batch_size.observe_success(batch_elapsed);
                     debug!("Block {}: Batch {} ({} pools) completed in {:?}", 
                            block_number, batch_idx + 1, batch.len(), batch_elapsed);
                     
-                    for (pool_id, reserves_opt) in batch_results {
-                        if reserves_opt.is_some() {
-                            total_successful += 1;
-                        } else {
-                            total_failed += 1;
+                    for (pool_id, reserves) in batch_results {
+                        match reserves {
+                            Ok(reserves) => {
+                                total_successful += 1;
+                                all_pool_data.insert(pool_id, Some(reserves));
+                            }
+                            Err(e) => {
+                                debug!("Block {}: Reserves of pool {:?} unavailable: {}", block_number, pool_id, e);
+                                total_failed += 1;
+                                all_pool_data.insert(pool_id, None);
+                            }
                         }
-                        all_pool_data.insert(pool_id, reserves_opt);
                     }
                 }
                 Err(e) => {
-                    let batch_elapsed = batch_start.elapsed();
+                    failed_batches += 1;
+                    self.batch_size.observe_failure();
+                    self.metrics.increment(Metric::StageFailuresTotal, Stage::MulticallBatch, 1);
                     error!("Block {}: Batch {} failed after {:?}: {}", 
                            block_number, batch_idx + 1, batch_elapsed, e);
                     
                     // Mark all pools in failed batch as failed
                     for pool_id in batch {
-                        all_pool_data.insert(*pool_id, None);
+                        all_pool_data.insert(pool_id, None);
                         total_failed += 1;
                     }
                 }
             }
         }
         
+        if self.batch_size.current() != batch_size {
+            debug!("Block {}: Batch size adjusted from {} to {} pools", block_number, batch_size, self.batch_size.current());
+        }
+        
+        if let Some(event_sync) = self.event_sync.as_mut() {
+            if full_resync && failed_batches == 0 {
+                event_sync.last_full_resync = Some(block_number);
+                event_sync.resync_requested = false;
+                self.metrics.increment(Metric::BlocksTotal, Stage::FullResync, 1);
+            } else if full_resync {
+                warn!("Block {}: Full reserve resync incomplete, {} batches failed", block_number, failed_batches);
+                self.metrics.increment(Metric::StageFailuresTotal, Stage::FullResync, 1);
+            } else {
+                // Pools without a Sync log keep their reserves
+                for pool_id in &monitored_pools {
+                    if all_pool_data.contains_key(pool_id) {
+                        continue;
+                    }
+                    if let Some(reserves) = event_reserves.get(pool_id).or_else(|| self.previous_reserves.get(pool_id)) {
+                        all_pool_data.insert(*pool_id, Some(*reserves));
+                        total_successful += 1;
+                    }
+                }
+            }
+        }
+        self.last_block = Some(block_number);
+        
         let fetch_elapsed = fetch_start.elapsed();
         
         // Process results and detect changes
@@ -156,11 +313,69 @@ impl DataAggregator {
         Ok(snapshot)
     }
     
+    /// Pools to query by multicall, the reserves from the block's Sync logs and whether it is a full resync
+    async fn plan_reserve_fetch(
+        &self,
+        block_header: &BlockHeader,
+        block_number: u64,
+        monitored_pools: &[PoolId],
+    ) -> (Vec<PoolId>, HashMap<PoolId, (U256, U256)>, bool) {
+        let Some(event_sync) = self.event_sync.as_ref() else {
+            return (monitored_pools.to_vec(), HashMap::new(), false);
+        };
+        
+        if let Some(reason) = self.full_resync_reason(event_sync, block_number) {
+            info!("Block {}: Full reserve resync of {} pools ({})", block_number, monitored_pools.len(), reason);
+            return (monitored_pools.to_vec(), HashMap::new(), true);
+        }
+        
+        let logs_start = Instant::now();
+        let result = event_sync.fetcher.block_reserves(&block_header.hash, monitored_pools).await;
+        self.metrics.observe_duration(Stage::SyncLogs, logs_start.elapsed());
+        match result {
+            Ok(event_reserves) => {
+                // Pools without a Sync log address can only be polled
+                let unknown_pools: Vec<PoolId> = monitored_pools
+                    .iter()
+                    .filter(|pool_id| {
+                        !has_sync_logs(pool_id)
+                            || (!event_reserves.contains_key(pool_id) && !self.previous_reserves.contains_key(pool_id))
+                    })
+                    .copied()
+                    .collect();
+                debug!("Block {}: {} Sync events, {} pools polled", block_number, event_reserves.len(), unknown_pools.len());
+                (unknown_pools, event_reserves, false)
+            }
+            Err(e) => {
+                warn!("Block {}: Failed to fetch Sync logs, falling back to a full resync: {}", block_number, e);
+                self.metrics.increment(Metric::StageFailuresTotal, Stage::SyncLogs, 1);
+                (monitored_pools.to_vec(), HashMap::new(), true)
+            }
+        }
+    }
+    
+    fn full_resync_reason(&self, event_sync: &EventSync, block_number: u64) -> Option<&'static str> {
+        if event_sync.resync_requested {
+            return Some("requested");
+        }
+        let last_full_resync = match event_sync.last_full_resync {
+            Some(last_full_resync) => last_full_resync,
+            None => return Some("no synced state"),
+        };
+        if self.last_block.is_some_and(|last_block| block_number != last_block + 1) {
+            return Some("blocks missed");
+        }
+        if block_number.saturating_sub(last_full_resync) >= event_sync.full_resync_interval_blocks {
+            return Some("periodic");
+        }
+        None
+    }
+    
     /// Split monitored pools into batches for multicall
     fn split_pools_into_batches(&self, pools: &[PoolId]) -> Vec<Vec<PoolId>> {
         let mut batches = Vec::new();
         
-        for chunk in pools.chunks(self.max_pools_per_batch) {
+        for chunk in pools.chunks(self.batch_size.current()) {
             batches.push(chunk.to_vec());
         }
         
@@ -184,7 +399,7 @@ impl DataAggregator {
         
         
         // Check that we have data for most of our monitored pools
-        let received_count = snapshot.pool_reserves.len();
+        let received_count = snapshot.pools_with_data_count();
         
         if monitored_count > 0 {
             let success_rate = (received_count as f64) / (monitored_count as f64);
@@ -204,6 +419,9 @@ impl DataAggregator {
         AggregatorStats {
             monitored_pools_count,
             max_pools_per_batch: self.max_pools_per_batch,
+            current_pools_per_batch: self.batch_size.current(),
+            max_concurrent_batches: self.max_concurrent_batches,
+            endpoints: Vec::new(),
         }
     }
 }
@@ -213,6 +431,10 @@ impl DataAggregator {
 pub struct AggregatorStats {
     pub monitored_pools_count: usize,
     pub max_pools_per_batch: usize,
+    pub current_pools_per_batch: usize,
+    pub max_concurrent_batches: usize,
+    /// Health of the RPC endpoints, filled in by [crate::data_sync::DataSyncService::get_stats]
+    pub endpoints: Vec<EndpointHealth>,
 }
 
 #[cfg(test)]
@@ -266,6 +488,32 @@ mod tests {
     }
     
     
+    #[test]
+    fn test_adaptive_batch_size() {
+        let target = Duration::from_millis(100);
+        let mut batch_size = AdaptiveBatchSize::new(10, 100, target);
+        assert_eq!(batch_size.current(), 100);
+        
+        batch_size.observe_success(Duration::from_millis(200));
+        assert_eq!(batch_size.current(), 75);
+        batch_size.observe_failure();
+        assert_eq!(batch_size.current(), 37);
+        // Within the target band the size is kept
+        batch_size.observe_success(Duration::from_millis(80));
+        assert_eq!(batch_size.current(), 37);
+        batch_size.observe_success(Duration::from_millis(20));
+        assert_eq!(batch_size.current(), 46);
+        
+        for _ in 0..10 {
+            batch_size.observe_failure();
+        }
+        assert_eq!(batch_size.current(), 10);
+        for _ in 0..20 {
+            batch_size.observe_success(Duration::ZERO);
+        }
+        assert_eq!(batch_size.current(), 100);
+    }
+    
     #[test]
     fn test_get_stats() {
         let aggregator = create_test_aggregator();
@@ -0,0 +1,120 @@
+use crate::data_sync::transport::RpcTransport;
+use crate::data_sync::websocket::BlockHeader;
+use eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// What the data layer does about blocks whose header never arrived
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapHandling {
+    /// Emit a [BlockGap] and continue with the new head
+    #[default]
+    Report,
+    /// Build the snapshots of the missed blocks from historical state first, gaps longer than
+    /// `max_backfill_blocks` or whose headers can't be fetched are reported instead
+    Backfill,
+}
+
+impl std::str::FromStr for GapHandling {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "report" => Ok(GapHandling::Report),
+            "backfill" => Ok(GapHandling::Backfill),
+            other => Err(eyre!("Unknown gap handling: {} (expected report or backfill)", other)),
+        }
+    }
+}
+
+/// Consecutive blocks that were never processed, e.g. because the WebSocket dropped their
+/// headers or the header channel was full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockGap {
+    pub first_missed: u64,
+    pub last_missed: u64,
+}
+
+impl BlockGap {
+    pub fn missed_count(&self) -> u64 {
+        self.last_missed - self.first_missed + 1
+    }
+
+    pub fn blocks(&self) -> RangeInclusive<u64> {
+        self.first_missed..=self.last_missed
+    }
+}
+
+/// Tracks the last processed block number to find the blocks missed before a new head
+#[derive(Debug, Clone, Default)]
+pub struct GapDetector {
+    last_processed: Option<u64>,
+}
+
+impl GapDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn last_processed(&self) -> Option<u64> {
+        self.last_processed
+    }
+
+    /// Blocks between the last processed block and `block_number`, nothing before the first block
+    pub fn missed_before(&self, block_number: u64) -> Option<BlockGap> {
+        let last_processed = self.last_processed?;
+        (block_number > last_processed + 1).then(|| BlockGap { first_missed: last_processed + 1, last_missed: block_number - 1 })
+    }
+
+    /// A reorg onto a lower head moves the last processed block back
+    pub fn record(&mut self, block_number: u64) {
+        self.last_processed = Some(block_number);
+    }
+}
+
+/// Headers of the blocks of `gap`, oldest first
+pub async fn fetch_block_headers(transport: &dyn RpcTransport, gap: BlockGap) -> Result<Vec<BlockHeader>> {
+    let mut headers = Vec::with_capacity(gap.missed_count() as usize);
+    for number in gap.blocks() {
+        let block = transport.request("eth_getBlockByNumber", serde_json::json!([format!("0x{:x}", number), false])).await?;
+        if block.is_null() {
+            return Err(eyre!("Block {} not found", number));
+        }
+        headers.push(serde_json::from_value(block)?);
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sync::transport::MockTransport;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn test_gap_detection_and_header_fetch() {
+        let mut detector = GapDetector::new();
+        assert_eq!(detector.missed_before(10), None);
+        detector.record(10);
+        assert_eq!(detector.missed_before(11), None);
+        assert_eq!(detector.missed_before(10), None);
+
+        let gap = detector.missed_before(14).unwrap();
+        assert_eq!(gap, BlockGap { first_missed: 11, last_missed: 13 });
+        assert_eq!(gap.missed_count(), 3);
+
+        let transport = MockTransport::new();
+        transport.set_handler("eth_getBlockByNumber", |params: &Value| {
+            let number = params[0].as_str().unwrap().to_string();
+            Ok(serde_json::json!({ "number": number, "hash": "0xaa", "parentHash": "0xbb", "timestamp": "0x0" }))
+        });
+        let headers = fetch_block_headers(&transport, gap).await.unwrap();
+        let numbers: Vec<u64> = headers.iter().map(|header| header.block_number().unwrap()).collect();
+        assert_eq!(numbers, [11, 12, 13]);
+
+        transport.set_handler("eth_getBlockByNumber", |_| Ok(Value::Null));
+        assert!(fetch_block_headers(&transport, gap).await.is_err());
+        assert_eq!("backfill".parse::<GapHandling>().unwrap(), GapHandling::Backfill);
+    }
+}
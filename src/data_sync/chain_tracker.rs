@@ -0,0 +1,202 @@
+use crate::data_sync::transport::RpcTransport;
+use crate::data_sync::websocket::BlockHeader;
+use crate::logic::types::{BlockRef, ReorgEvent};
+use eyre::{Result, eyre};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// How a new block header relates to the tracked chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainUpdate {
+    /// The header extends the tracked chain
+    Extended,
+    /// The header is already part of the tracked chain
+    Duplicate,
+    /// The header belongs to a fork that replaces tracked blocks
+    Reorg(ReorgEvent),
+}
+
+/// Follows the canonical chain from newHeads headers and detects reorganizations
+///
+/// The last `max_reorg_depth + 1` blocks are kept. A header whose parent is not the tracked tip is
+/// walked back through `eth_getBlockByHash` until a tracked block is found, this block is the
+/// common ancestor of both forks and the tracked blocks above it are orphaned. Without a
+/// transport, or when the fork is deeper than `max_reorg_depth`, the common ancestor is unknown
+/// and every tracked block from the first unverified height is reported as orphaned.
+#[derive(Debug)]
+pub struct ChainTracker {
+    max_reorg_depth: usize,
+    /// Tracked canonical blocks, oldest first
+    blocks: VecDeque<BlockRef>,
+    transport: Option<Arc<dyn RpcTransport>>,
+}
+
+impl ChainTracker {
+    pub fn new(max_reorg_depth: usize) -> Self {
+        Self { max_reorg_depth: max_reorg_depth.max(1), blocks: VecDeque::new(), transport: None }
+    }
+
+    /// Fetch the unknown ancestors of a fork through `transport`
+    pub fn with_transport(mut self, transport: Arc<dyn RpcTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn max_reorg_depth(&self) -> usize {
+        self.max_reorg_depth
+    }
+
+    /// Newest tracked block
+    pub fn tip(&self) -> Option<&BlockRef> {
+        self.blocks.back()
+    }
+
+    /// Tracked blocks, oldest first
+    pub fn blocks(&self) -> impl Iterator<Item = &BlockRef> {
+        self.blocks.iter()
+    }
+
+    /// Add a new head to the tracked chain
+    pub async fn observe(&mut self, header: &BlockHeader) -> Result<ChainUpdate> {
+        let new_head = BlockRef { number: header.block_number()?, hash: header.hash.clone() };
+        if self.blocks.is_empty() {
+            self.blocks.push_back(new_head);
+            return Ok(ChainUpdate::Extended);
+        }
+        if self.blocks.contains(&new_head) {
+            return Ok(ChainUpdate::Duplicate);
+        }
+
+        // Walk back from the new head until a tracked block is its ancestor
+        let mut new_blocks = vec![new_head.clone()];
+        let mut parent = BlockRef { number: new_head.number.saturating_sub(1), hash: header.parent_hash.clone() };
+        let common_ancestor = loop {
+            if self.blocks.contains(&parent) {
+                break Some(parent.number);
+            }
+            if !self.is_tracked_height(parent.number) || new_blocks.len() > self.max_reorg_depth {
+                break None;
+            }
+            match self.fetch_header(&parent.hash).await {
+                Ok(Some(ancestor)) => {
+                    let parent_hash = ancestor.parent_hash.clone();
+                    new_blocks.push(parent.clone());
+                    parent = BlockRef { number: parent.number.saturating_sub(1), hash: parent_hash };
+                }
+                Ok(None) => break None,
+                Err(e) => {
+                    warn!("Failed to fetch ancestor {} of block {}: {}", parent.hash, new_head.number, e);
+                    break None;
+                }
+            }
+        };
+
+        // Without a common ancestor the first unverified height and everything above it is orphaned
+        let first_orphaned = common_ancestor.map_or(parent.number, |ancestor| ancestor + 1);
+        let orphaned: Vec<BlockRef> = {
+            let keep = self.blocks.iter().take_while(|block| block.number < first_orphaned).count();
+            self.blocks.drain(keep..).collect()
+        };
+        self.blocks.extend(new_blocks.into_iter().rev());
+        self.prune();
+
+        if orphaned.is_empty() {
+            debug!("Block {} extends the tracked chain", new_head.number);
+            return Ok(ChainUpdate::Extended);
+        }
+        Ok(ChainUpdate::Reorg(ReorgEvent { common_ancestor, orphaned, new_head }))
+    }
+
+    /// Whether the tracked blocks cover `number`, only then can walking back find an ancestor
+    fn is_tracked_height(&self, number: u64) -> bool {
+        match (self.blocks.front(), self.blocks.back()) {
+            (Some(oldest), Some(tip)) => number >= oldest.number && number <= tip.number,
+            _ => false,
+        }
+    }
+
+    async fn fetch_header(&self, hash: &str) -> Result<Option<BlockHeader>> {
+        let Some(transport) = self.transport.as_ref() else {
+            return Ok(None);
+        };
+        let block = transport.request("eth_getBlockByHash", serde_json::json!([hash, false])).await?;
+        if block.is_null() {
+            return Err(eyre!("Block {} not found", hash));
+        }
+        Ok(Some(serde_json::from_value(block)?))
+    }
+
+    fn prune(&mut self) {
+        let Some(tip) = self.blocks.back().map(|block| block.number) else {
+            return;
+        };
+        let oldest_kept = tip.saturating_sub(self.max_reorg_depth as u64);
+        while self.blocks.front().is_some_and(|block| block.number < oldest_kept) {
+            self.blocks.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sync::transport::MockTransport;
+    use serde_json::Value;
+
+    fn header(number: u64, hash: &str, parent_hash: &str) -> BlockHeader {
+        BlockHeader {
+            number: format!("0x{:x}", number),
+            hash: hash.to_string(),
+            parent_hash: parent_hash.to_string(),
+            timestamp: "0x0".to_string(),
+        }
+    }
+
+    fn block(number: u64, hash: &str) -> BlockRef {
+        BlockRef { number, hash: hash.to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_chain_tracker_detects_reorgs() {
+        let transport = Arc::new(MockTransport::new());
+        let fork = header(11, "0x11b", "0x10");
+        transport.set_handler("eth_getBlockByHash", move |params: &Value| match params[0].as_str() {
+            Some("0x11b") => Ok(serde_json::to_value(&fork).unwrap()),
+            _ => Ok(Value::Null),
+        });
+        let mut tracker = ChainTracker::new(3).with_transport(transport.clone());
+
+        for (number, hash, parent) in [(10, "0x10", "0x09"), (11, "0x11", "0x10"), (12, "0x12", "0x11")] {
+            assert_eq!(tracker.observe(&header(number, hash, parent)).await.unwrap(), ChainUpdate::Extended);
+        }
+        assert_eq!(tracker.observe(&header(12, "0x12", "0x11")).await.unwrap(), ChainUpdate::Duplicate);
+
+        // A depth 2 reorg, the unknown parent of the new head is fetched
+        let update = tracker.observe(&header(12, "0x12b", "0x11b")).await.unwrap();
+        assert_eq!(
+            update,
+            ChainUpdate::Reorg(ReorgEvent {
+                common_ancestor: Some(10),
+                orphaned: vec![block(11, "0x11"), block(12, "0x12")],
+                new_head: block(12, "0x12b"),
+            })
+        );
+        assert_eq!(transport.request_count("eth_getBlockByHash"), 1);
+        let hashes: Vec<&str> = tracker.blocks().map(|block| block.hash.as_str()).collect();
+        assert_eq!(hashes, ["0x10", "0x11b", "0x12b"]);
+
+        // Only max_reorg_depth + 1 blocks are kept
+        tracker.observe(&header(13, "0x13", "0x12b")).await.unwrap();
+        tracker.observe(&header(14, "0x14", "0x13")).await.unwrap();
+        assert_eq!(tracker.blocks().next().unwrap().number, 11);
+
+        // The fork point of a fork whose ancestors can't be fetched is unknown
+        let ChainUpdate::Reorg(reorg) = tracker.observe(&header(14, "0x14c", "0x13c")).await.unwrap() else {
+            panic!("expected a reorg");
+        };
+        assert_eq!(reorg.common_ancestor, None);
+        assert_eq!(reorg.orphaned, vec![block(13, "0x13"), block(14, "0x14")]);
+        assert_eq!(reorg.first_orphaned_block(), 13);
+    }
+}
@@ -1,3 +1,8 @@
+use crate::data_sync::block_gaps::GapHandling;
+use crate::data_sync::failover::{ReadMode, RpcEndpoint};
+use crate::data_sync::sync_events::ReserveSyncMode;
+use crate::logic::snapshot_channel::SnapshotDelivery;
+use crate::utils::constants::WMNT;
 use serde::{Deserialize, Serialize};
 use url::Url;
 use std::time::Duration;
@@ -9,10 +14,52 @@ pub struct DataSyncConfig {
     pub rpc_wss_url: String,
     /// HTTP RPC URL for fallback and Multicall requests
     pub rpc_http_url: String,
+    /// WebSocket endpoints to fail over between, replaces `rpc_wss_url` when not empty
+    #[serde(default)]
+    pub rpc_wss_endpoints: Vec<RpcEndpoint>,
+    /// HTTP endpoints to fail over between, replaces `rpc_http_url` when not empty
+    #[serde(default)]
+    pub rpc_http_endpoints: Vec<RpcEndpoint>,
+    /// Blocks an endpoint may lag behind the others before it is considered unhealthy
+    #[serde(default = "default_max_block_lag")]
+    pub max_block_lag: u64,
+    /// Seconds between block height and latency probes of the endpoints
+    #[serde(default = "default_endpoint_probe_interval_secs")]
+    pub endpoint_probe_interval_secs: u64,
+    /// Whether multicall reads fail over, are hedged or cross-checked by several HTTP endpoints
+    #[serde(default)]
+    pub rpc_read_mode: ReadMode,
+    /// HTTP endpoints a hedged or quorum read is sent to, at least 3 for quorum reads
+    #[serde(default = "default_rpc_read_fanout")]
+    pub rpc_read_fanout: usize,
     /// Multicall contract address on Mantle
     pub multicall_address: String,
     /// Maximum number of pools to query in a single Multicall batch
     pub max_pools_per_batch: usize,
+    /// Multicall batches in flight at the same time
+    #[serde(default = "default_max_concurrent_batches")]
+    pub max_concurrent_batches: usize,
+    /// Lower bound the batch size shrinks to when batches are slow or fail
+    #[serde(default = "default_min_pools_per_batch")]
+    pub min_pools_per_batch: usize,
+    /// Batch latency the batch size is adapted to, in milliseconds
+    #[serde(default = "default_target_batch_latency_ms")]
+    pub target_batch_latency_ms: u64,
+    /// Poll every pool each block, or apply Sync events with a periodic full resync
+    #[serde(default)]
+    pub reserve_sync_mode: ReserveSyncMode,
+    /// Blocks between full multicall resyncs in event-driven reserve sync
+    #[serde(default = "default_full_resync_interval_blocks")]
+    pub full_resync_interval_blocks: u64,
+    /// Deepest chain reorganization whose orphaned blocks can be identified
+    #[serde(default = "default_max_reorg_depth")]
+    pub max_reorg_depth: usize,
+    /// Whether missed blocks are backfilled or reported as gaps
+    #[serde(default)]
+    pub gap_handling: GapHandling,
+    /// Longest gap that is backfilled, longer ones are reported
+    #[serde(default = "default_max_backfill_blocks")]
+    pub max_backfill_blocks: u64,
     /// WebSocket connection timeout in seconds
     pub ws_connection_timeout_secs: u64,
     /// Maximum number of reconnection attempts
@@ -21,8 +68,60 @@ pub struct DataSyncConfig {
     pub reconnect_delay_secs: u64,
     /// Timeout for HTTP requests in seconds
     pub http_timeout_secs: u64,
-    /// Buffer size for the data channel to logic layer
+    /// Buffer size for the data channel to logic layer, used by the queue delivery mode
     pub channel_buffer_size: usize,
+    /// How snapshots are handed to the logic layer when it falls behind
+    #[serde(default)]
+    pub snapshot_delivery: SnapshotDelivery,
+    /// Executor address whose token balances are refreshed into the state cache every block
+    #[serde(default)]
+    pub executor_address: Option<String>,
+    /// Tokens to track the executor's balance of
+    #[serde(default = "default_inventory_tokens")]
+    pub inventory_tokens: Vec<String>,
+    /// Address to serve Prometheus metrics on, e.g. `127.0.0.1:9100`, disabled when unset
+    #[serde(default)]
+    pub metrics_listen_addr: Option<String>,
+}
+
+fn default_max_block_lag() -> u64 {
+    3
+}
+
+fn default_endpoint_probe_interval_secs() -> u64 {
+    5
+}
+
+fn default_rpc_read_fanout() -> usize {
+    2
+}
+
+fn default_max_concurrent_batches() -> usize {
+    4
+}
+
+fn default_min_pools_per_batch() -> usize {
+    10
+}
+
+fn default_target_batch_latency_ms() -> u64 {
+    500
+}
+
+fn default_full_resync_interval_blocks() -> u64 {
+    100
+}
+
+fn default_max_reorg_depth() -> usize {
+    64
+}
+
+fn default_max_backfill_blocks() -> u64 {
+    16
+}
+
+fn default_inventory_tokens() -> Vec<String> {
+    vec![format!("{WMNT:#x}")]
 }
 
 impl Default for DataSyncConfig {
@@ -30,14 +129,32 @@ impl Default for DataSyncConfig {
         Self {
             rpc_wss_url: "wss://rpc.mantle.xyz".to_string(),
             rpc_http_url: "https://rpc.mantle.xyz".to_string(),
+            rpc_wss_endpoints: Vec::new(),
+            rpc_http_endpoints: Vec::new(),
+            max_block_lag: default_max_block_lag(),
+            endpoint_probe_interval_secs: default_endpoint_probe_interval_secs(),
+            rpc_read_mode: ReadMode::Failover,
+            rpc_read_fanout: default_rpc_read_fanout(),
             // Standard Multicall3 address (deployed on most chains)
             multicall_address: "0xcA11bde05977b3631167028862bE2a173976CA11".to_string(),
             max_pools_per_batch: 50,
+            max_concurrent_batches: default_max_concurrent_batches(),
+            min_pools_per_batch: default_min_pools_per_batch(),
+            target_batch_latency_ms: default_target_batch_latency_ms(),
+            reserve_sync_mode: ReserveSyncMode::Poll,
+            full_resync_interval_blocks: default_full_resync_interval_blocks(),
+            max_reorg_depth: default_max_reorg_depth(),
+            gap_handling: GapHandling::Report,
+            max_backfill_blocks: default_max_backfill_blocks(),
             ws_connection_timeout_secs: 30,
             max_reconnect_attempts: 5,
             reconnect_delay_secs: 2,
             http_timeout_secs: 10,
             channel_buffer_size: 100,
+            snapshot_delivery: SnapshotDelivery::Latest,
+            executor_address: None,
+            inventory_tokens: default_inventory_tokens(),
+            metrics_listen_addr: None,
         }
     }
 }
@@ -61,6 +178,36 @@ impl DataSyncConfig {
             config.rpc_http_url = rpc_http_url;
         }
         
+        // Comma separated, each one `url` or `url|weight`
+        if let Ok(rpc_wss_urls) = std::env::var("RPC_WSS_URLS") {
+            config.rpc_wss_endpoints = parse_endpoints(&rpc_wss_urls)
+                .map_err(|e| eyre::eyre!("Invalid RPC_WSS_URLS: {}", e))?;
+        }
+        
+        if let Ok(rpc_http_urls) = std::env::var("RPC_HTTP_URLS") {
+            config.rpc_http_endpoints = parse_endpoints(&rpc_http_urls)
+                .map_err(|e| eyre::eyre!("Invalid RPC_HTTP_URLS: {}", e))?;
+        }
+        
+        if let Ok(max_lag_str) = std::env::var("MAX_BLOCK_LAG") {
+            config.max_block_lag = max_lag_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid MAX_BLOCK_LAG: {}", e))?;
+        }
+        
+        if let Ok(interval_str) = std::env::var("ENDPOINT_PROBE_INTERVAL_SECS") {
+            config.endpoint_probe_interval_secs = interval_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid ENDPOINT_PROBE_INTERVAL_SECS: {}", e))?;
+        }
+        
+        if let Ok(read_mode) = std::env::var("RPC_READ_MODE") {
+            config.rpc_read_mode = read_mode.parse()?;
+        }
+        
+        if let Ok(fanout_str) = std::env::var("RPC_READ_FANOUT") {
+            config.rpc_read_fanout = fanout_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid RPC_READ_FANOUT: {}", e))?;
+        }
+        
         if let Ok(multicall_address) = std::env::var("MULTICALL_ADDRESS") {
             config.multicall_address = multicall_address;
         }
@@ -70,6 +217,44 @@ impl DataSyncConfig {
                 .map_err(|e| eyre::eyre!("Invalid MAX_POOLS_PER_BATCH: {}", e))?;
         }
         
+        if let Ok(max_concurrent_str) = std::env::var("MAX_CONCURRENT_BATCHES") {
+            config.max_concurrent_batches = max_concurrent_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid MAX_CONCURRENT_BATCHES: {}", e))?;
+        }
+        
+        if let Ok(min_pools_str) = std::env::var("MIN_POOLS_PER_BATCH") {
+            config.min_pools_per_batch = min_pools_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid MIN_POOLS_PER_BATCH: {}", e))?;
+        }
+        
+        if let Ok(latency_str) = std::env::var("TARGET_BATCH_LATENCY_MS") {
+            config.target_batch_latency_ms = latency_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid TARGET_BATCH_LATENCY_MS: {}", e))?;
+        }
+        
+        if let Ok(reserve_sync_mode) = std::env::var("RESERVE_SYNC_MODE") {
+            config.reserve_sync_mode = reserve_sync_mode.parse()?;
+        }
+        
+        if let Ok(interval_str) = std::env::var("FULL_RESYNC_INTERVAL_BLOCKS") {
+            config.full_resync_interval_blocks = interval_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid FULL_RESYNC_INTERVAL_BLOCKS: {}", e))?;
+        }
+        
+        if let Ok(depth_str) = std::env::var("MAX_REORG_DEPTH") {
+            config.max_reorg_depth = depth_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid MAX_REORG_DEPTH: {}", e))?;
+        }
+        
+        if let Ok(handling_str) = std::env::var("GAP_HANDLING") {
+            config.gap_handling = handling_str.parse()?;
+        }
+        
+        if let Ok(blocks_str) = std::env::var("MAX_BACKFILL_BLOCKS") {
+            config.max_backfill_blocks = blocks_str.parse()
+                .map_err(|e| eyre::eyre!("Invalid MAX_BACKFILL_BLOCKS: {}", e))?;
+        }
+        
         if let Ok(timeout_str) = std::env::var("WS_CONNECTION_TIMEOUT_SECS") {
             config.ws_connection_timeout_secs = timeout_str.parse()
                 .map_err(|e| eyre::eyre!("Invalid WS_CONNECTION_TIMEOUT_SECS: {}", e))?;
@@ -95,9 +280,47 @@ impl DataSyncConfig {
                 .map_err(|e| eyre::eyre!("Invalid CHANNEL_BUFFER_SIZE: {}", e))?;
         }
         
+        if let Ok(executor_address) = std::env::var("EXECUTOR_ADDRESS") {
+            config.executor_address = Some(executor_address);
+        }
+        
+        if let Ok(inventory_tokens) = std::env::var("INVENTORY_TOKENS") {
+            config.inventory_tokens = inventory_tokens.split(',').map(|token| token.trim().to_string()).filter(|token| !token.is_empty()).collect();
+        }
+        
+        if let Ok(snapshot_delivery) = std::env::var("SNAPSHOT_DELIVERY") {
+            config.snapshot_delivery = snapshot_delivery.parse()?;
+        }
+        
+        if let Ok(metrics_listen_addr) = std::env::var("METRICS_LISTEN_ADDR") {
+            config.metrics_listen_addr = Some(metrics_listen_addr);
+        }
+        
         Ok(config)
     }
     
+    /// WebSocket endpoints, `rpc_wss_url` unless a list is configured
+    pub fn wss_endpoints(&self) -> Vec<RpcEndpoint> {
+        if self.rpc_wss_endpoints.is_empty() {
+            vec![RpcEndpoint::new(self.rpc_wss_url.clone())]
+        } else {
+            self.rpc_wss_endpoints.clone()
+        }
+    }
+    
+    /// HTTP endpoints, `rpc_http_url` unless a list is configured
+    pub fn http_endpoints(&self) -> Vec<RpcEndpoint> {
+        if self.rpc_http_endpoints.is_empty() {
+            vec![RpcEndpoint::new(self.rpc_http_url.clone())]
+        } else {
+            self.rpc_http_endpoints.clone()
+        }
+    }
+    
+    pub fn endpoint_probe_interval(&self) -> Duration {
+        Duration::from_secs(self.endpoint_probe_interval_secs.max(1))
+    }
+    
     pub fn ws_connection_timeout(&self) -> Duration {
         Duration::from_secs(self.ws_connection_timeout_secs)
     }
@@ -109,6 +332,18 @@ impl DataSyncConfig {
     pub fn http_timeout(&self) -> Duration {
         Duration::from_secs(self.http_timeout_secs)
     }
+    
+    pub fn target_batch_latency(&self) -> Duration {
+        Duration::from_millis(self.target_batch_latency_ms)
+    }
+}
+
+fn parse_endpoints(value: &str) -> eyre::Result<Vec<RpcEndpoint>> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(str::parse)
+        .collect()
 }
 
 #[cfg(test)]
@@ -131,4 +366,17 @@ mod tests {
         assert_eq!(config.reconnect_delay(), Duration::from_secs(2));
         assert_eq!(config.http_timeout(), Duration::from_secs(10));
     }
+    
+    #[test]
+    fn test_endpoint_lists() {
+        let mut config = DataSyncConfig::default();
+        assert_eq!(config.http_endpoints(), vec![RpcEndpoint::new("https://rpc.mantle.xyz")]);
+        
+        config.rpc_http_endpoints = parse_endpoints("https://a.example|2, https://b.example").unwrap();
+        assert_eq!(config.http_endpoints(), vec![
+            RpcEndpoint::new("https://a.example").with_weight(2.0),
+            RpcEndpoint::new("https://b.example"),
+        ]);
+        assert!(parse_endpoints("not a url").is_err());
+    }
 }
@@ -0,0 +1,720 @@
+use crate::data_sync::transport::{HttpTransport, RpcTransport, SUBSCRIPTION_BUFFER_SIZE, WsTransport, is_missing_block_error, is_node_error};
+use async_trait::async_trait;
+use eyre::{Result, eyre};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Weight of the newest sample in the latency and error rate averages
+const EWMA_ALPHA: f64 = 0.3;
+/// Error rate above which an endpoint is only used once the healthy ones failed
+const MAX_HEALTHY_ERROR_RATE: f64 = 0.5;
+/// Latency assumed for an endpoint that has not answered yet
+const UNKNOWN_LATENCY_MS: f64 = 100.0;
+
+/// How `eth_call` reads are spread over the endpoints, other requests always fail over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadMode {
+    /// Send to the healthiest endpoint, the next one only when it can't be reached
+    #[default]
+    Failover,
+    /// Send to the `read_fanout` healthiest endpoints at once and take the first answer
+    Hedged,
+    /// Send to the `read_fanout` healthiest endpoints, cross-check their block hash and answers
+    /// and take the answer of a strict majority, endpoints that disagree with it are flagged. Needs
+    /// a fanout of at least 3, without a strict majority the read fails.
+    Quorum,
+}
+
+impl std::str::FromStr for ReadMode {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "failover" => Ok(ReadMode::Failover),
+            "hedged" => Ok(ReadMode::Hedged),
+            "quorum" => Ok(ReadMode::Quorum),
+            other => Err(eyre!("Unknown read mode: {} (expected failover, hedged or quorum)", other)),
+        }
+    }
+}
+
+/// An RPC endpoint and its weight among the endpoints of the same transport
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcEndpoint {
+    pub url: String,
+    /// Relative preference, an endpoint with twice the weight tolerates twice the latency
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl RpcEndpoint {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into(), weight: default_weight() }
+    }
+
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+impl std::str::FromStr for RpcEndpoint {
+    type Err = eyre::Report;
+
+    /// `url` or `url|weight`
+    fn from_str(value: &str) -> Result<Self> {
+        let (url, weight) = match value.trim().rsplit_once('|') {
+            Some((url, weight)) => {
+                let weight: f64 = weight.trim().parse().map_err(|e| eyre!("Invalid endpoint weight {}: {}", weight, e))?;
+                (url.trim(), weight)
+            }
+            None => (value.trim(), default_weight()),
+        };
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(eyre!("Invalid endpoint weight {} of {}", weight, url));
+        }
+        url::Url::parse(url).map_err(|e| eyre!("Invalid endpoint URL {}: {}", url, e))?;
+        Ok(Self::new(url).with_weight(weight))
+    }
+}
+
+/// Health of an endpoint as seen by a [FailoverTransport]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EndpointHealth {
+    pub url: String,
+    pub weight: f64,
+    /// Moving average of the response time, `None` before the first response
+    pub latency_ms: Option<f64>,
+    /// Moving average of the share of failed requests
+    pub error_rate: f64,
+    /// Newest block the endpoint reported
+    pub block_number: Option<u64>,
+    /// Blocks behind the newest block reported by any endpoint
+    pub block_lag: u64,
+    pub requests: u64,
+    pub errors: u64,
+    /// Quorum reads in which the endpoint was on another block or gave another answer
+    pub disagreements: u64,
+    /// Higher is better, requests go to the healthy endpoint with the highest score first
+    pub score: f64,
+    pub healthy: bool,
+}
+
+#[derive(Debug, Default)]
+struct EndpointStats {
+    latency_ms: Option<f64>,
+    error_rate: f64,
+    block_number: Option<u64>,
+    requests: u64,
+    errors: u64,
+    disagreements: u64,
+}
+
+impl EndpointStats {
+    fn record_success(&mut self, latency: Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        self.latency_ms = Some(match self.latency_ms {
+            Some(average) => average + EWMA_ALPHA * (latency_ms - average),
+            None => latency_ms,
+        });
+        self.error_rate *= 1.0 - EWMA_ALPHA;
+        self.requests += 1;
+    }
+
+    fn record_failure(&mut self) {
+        self.error_rate += EWMA_ALPHA * (1.0 - self.error_rate);
+        self.requests += 1;
+        self.errors += 1;
+    }
+
+    /// A wrong answer weighs on the error rate like a failed request
+    fn record_disagreement(&mut self) {
+        self.error_rate += EWMA_ALPHA * (1.0 - self.error_rate);
+        self.disagreements += 1;
+    }
+
+    fn record_block(&mut self, block_number: u64) {
+        self.block_number = Some(self.block_number.map_or(block_number, |known| known.max(block_number)));
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    config: RpcEndpoint,
+    transport: Arc<dyn RpcTransport>,
+    stats: Arc<Mutex<EndpointStats>>,
+}
+
+/// Routes requests and subscriptions over several endpoints, healthiest first
+///
+/// Each endpoint is scored from its weight, latency, error rate and how many blocks it lags
+/// behind the others. A request that can't reach an endpoint, or that asks a node for a block it
+/// doesn't have yet, is retried on the next one. Other error responses of a node (e.g. a reverted
+/// call) are returned as they are. The block heights come
+/// from `eth_blockNumber` responses, newHeads notifications and [FailoverTransport::probe].
+///
+/// Reads can also be hedged or cross-checked by several endpoints, see [ReadMode].
+#[derive(Debug)]
+pub struct FailoverTransport {
+    endpoints: Vec<Endpoint>,
+    max_block_lag: u64,
+    read_mode: ReadMode,
+    read_fanout: usize,
+}
+
+impl FailoverTransport {
+    pub fn new(endpoints: Vec<(RpcEndpoint, Arc<dyn RpcTransport>)>, max_block_lag: u64) -> Self {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(config, transport)| Endpoint { config, transport, stats: Arc::default() })
+            .collect();
+        Self { endpoints, max_block_lag, read_mode: ReadMode::Failover, read_fanout: 2 }
+    }
+
+    /// Spread `eth_call` reads over `read_fanout` endpoints at once
+    pub fn with_read_mode(mut self, read_mode: ReadMode, read_fanout: usize) -> Self {
+        self.read_mode = read_mode;
+        self.read_fanout = read_fanout.max(1);
+        self
+    }
+
+    /// An [HttpTransport] per endpoint
+    pub fn http(endpoints: &[RpcEndpoint], request_timeout: Duration, max_block_lag: u64) -> Self {
+        let endpoints = endpoints
+            .iter()
+            .map(|endpoint| {
+                let transport: Arc<dyn RpcTransport> = Arc::new(HttpTransport::new(endpoint.url.clone(), request_timeout));
+                (endpoint.clone(), transport)
+            })
+            .collect();
+        Self::new(endpoints, max_block_lag)
+    }
+
+    /// A [WsTransport] per endpoint
+    pub fn ws(endpoints: &[RpcEndpoint], connection_timeout: Duration, max_block_lag: u64) -> Self {
+        let endpoints = endpoints
+            .iter()
+            .map(|endpoint| {
+                let transport: Arc<dyn RpcTransport> = Arc::new(WsTransport::new(endpoint.url.clone(), connection_timeout));
+                (endpoint.clone(), transport)
+            })
+            .collect();
+        Self::new(endpoints, max_block_lag)
+    }
+
+    /// Health of the endpoints, in configuration order
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        let highest_block = self.highest_block();
+        self.endpoints.iter().map(|endpoint| self.endpoint_health(endpoint, highest_block)).collect()
+    }
+
+    /// Query the block height of every endpoint, which also measures their latency
+    pub async fn probe(&self) {
+        futures::future::join_all(self.endpoints.iter().map(|endpoint| async move {
+            if let Err(e) = self.call(endpoint, "eth_blockNumber", Value::Array(Vec::new())).await {
+                debug!("Health check of RPC endpoint {} failed: {}", endpoint.config.url, e);
+            }
+        }))
+        .await;
+    }
+
+    /// Probe the endpoints every `interval` until the task is aborted
+    pub fn spawn_health_checks(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.probe().await;
+            }
+        })
+    }
+
+    fn highest_block(&self) -> Option<u64> {
+        self.endpoints.iter().filter_map(|endpoint| endpoint.stats.lock().unwrap().block_number).max()
+    }
+
+    fn endpoint_health(&self, endpoint: &Endpoint, highest_block: Option<u64>) -> EndpointHealth {
+        let stats = endpoint.stats.lock().unwrap();
+        // An endpoint whose height is unknown is not considered behind
+        let block_lag = match (highest_block, stats.block_number) {
+            (Some(highest_block), Some(block_number)) => highest_block.saturating_sub(block_number),
+            _ => 0,
+        };
+        let latency_ms = stats.latency_ms.unwrap_or(UNKNOWN_LATENCY_MS);
+        let score = endpoint.config.weight * (1.0 - stats.error_rate) / (1.0 + latency_ms / UNKNOWN_LATENCY_MS) / (1.0 + block_lag as f64);
+
+        EndpointHealth {
+            url: endpoint.config.url.clone(),
+            weight: endpoint.config.weight,
+            latency_ms: stats.latency_ms,
+            error_rate: stats.error_rate,
+            block_number: stats.block_number,
+            block_lag,
+            requests: stats.requests,
+            errors: stats.errors,
+            disagreements: stats.disagreements,
+            score,
+            healthy: stats.error_rate <= MAX_HEALTHY_ERROR_RATE && block_lag <= self.max_block_lag,
+        }
+    }
+
+    /// Endpoints in the order they are tried
+    fn ranked(&self) -> Vec<&Endpoint> {
+        let highest_block = self.highest_block();
+        let mut ranked: Vec<(EndpointHealth, &Endpoint)> =
+            self.endpoints.iter().map(|endpoint| (self.endpoint_health(endpoint, highest_block), endpoint)).collect();
+        ranked.sort_by(|(a, _), (b, _)| b.healthy.cmp(&a.healthy).then(b.score.total_cmp(&a.score)));
+        ranked.into_iter().map(|(_, endpoint)| endpoint).collect()
+    }
+
+    async fn call(&self, endpoint: &Endpoint, method: &str, params: Value) -> Result<Value> {
+        let start = Instant::now();
+        let result = endpoint.transport.request(method, params).await;
+        record_outcome(&endpoint.stats, method, start.elapsed(), &result);
+        result
+    }
+
+    /// Try `endpoints` one after the other until one can be reached
+    async fn failover(&self, endpoints: &[&Endpoint], method: &str, params: Value, mut last_error: Option<eyre::Report>) -> Result<Value> {
+        for endpoint in endpoints {
+            match self.call(endpoint, method, params.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(e) if is_answer(&e) => return Err(e),
+                Err(e) => {
+                    warn!("RPC endpoint {} failed {}, trying the next one: {}", endpoint.config.url, method, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| eyre!("No RPC endpoints configured")))
+    }
+
+    /// First answer of the `read_fanout` healthiest endpoints
+    ///
+    /// The slower requests are left to finish in the background, so that their latency and
+    /// failures are recorded even though their answers are not used.
+    async fn hedged(&self, method: &str, params: Value) -> Result<Value> {
+        let ranked = self.ranked();
+        let (hedged, rest) = ranked.split_at(self.read_fanout.min(ranked.len()));
+
+        let mut in_flight: FuturesUnordered<_> = hedged
+            .iter()
+            .map(|endpoint| {
+                let transport = Arc::clone(&endpoint.transport);
+                let stats = Arc::clone(&endpoint.stats);
+                let method = method.to_string();
+                let params = params.clone();
+                tokio::spawn(async move {
+                    let start = Instant::now();
+                    let result = transport.request(&method, params).await;
+                    record_outcome(&stats, &method, start.elapsed(), &result);
+                    result
+                })
+            })
+            .collect();
+        let mut node_error = None;
+        let mut last_error = None;
+        while let Some(joined) = in_flight.next().await {
+            let result = joined.unwrap_or_else(|e| Err(eyre!("Hedged {} request failed: {}", method, e)));
+            match result {
+                Ok(value) => return Ok(value),
+                Err(e) if is_answer(&e) => node_error = Some(e),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        drop(in_flight);
+
+        // Every hedged endpoint answered with an error, e.g. a reverted call
+        if let Some(e) = node_error {
+            return Err(e);
+        }
+        warn!("Hedged {} failed on {} endpoints, failing over", method, hedged.len());
+        self.failover(rest, method, params, last_error).await
+    }
+
+    /// Majority answer of the `read_fanout` healthiest endpoints
+    ///
+    /// For a call at a block number the endpoints also report their hash of that block. Endpoints
+    /// on another fork than the majority are left out of the vote, then answers that differ from
+    /// the majority answer are flagged. A tie fails the read without flagging anyone, there is no
+    /// telling which side is right.
+    async fn quorum(&self, method: &str, params: Value) -> Result<Value> {
+        let ranked = self.ranked();
+        let (voters, rest) = ranked.split_at(self.read_fanout.min(ranked.len()));
+        let block_tag = params.get(1).and_then(Value::as_str).filter(|tag| tag.starts_with("0x")).map(str::to_string);
+
+        let votes = futures::future::join_all(voters.iter().map(|endpoint| {
+            let params = params.clone();
+            let block_tag = block_tag.clone();
+            async move {
+                let block_hash = async {
+                    let block_tag = block_tag?;
+                    let block = self.call(endpoint, "eth_getBlockByNumber", serde_json::json!([block_tag, false])).await.ok()?;
+                    block["hash"].as_str().map(str::to_string)
+                };
+                let (answer, block_hash) = tokio::join!(self.call(endpoint, method, params), block_hash);
+                (*endpoint, answer, block_hash)
+            }
+        }))
+        .await;
+
+        let mut node_error = None;
+        let mut last_error = None;
+        let mut answers = Vec::with_capacity(votes.len());
+        for (endpoint, answer, block_hash) in votes {
+            match answer {
+                Ok(value) => answers.push((endpoint, value, block_hash)),
+                Err(e) if is_answer(&e) => node_error = Some(e),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if answers.is_empty() {
+            if let Some(e) = node_error {
+                return Err(e);
+            }
+            warn!("Quorum {} failed on {} endpoints, failing over", method, voters.len());
+            return self.failover(rest, method, params, last_error).await;
+        }
+
+        // Endpoints that don't know the block hash can't be checked against the others
+        let block_hashes: Vec<String> = answers.iter().filter_map(|(_, _, block_hash)| block_hash.clone()).collect();
+        let majority_hash = if block_hashes.is_empty() {
+            None
+        } else {
+            let majority_hash = strict_majority(block_hashes.into_iter())
+                .ok_or_else(|| eyre!("No majority block hash among {} endpoints for {}", answers.len(), method))?;
+            Some(majority_hash)
+        };
+        let (same_block, other_fork): (Vec<_>, Vec<_>) = answers.into_iter().partition(|(_, _, block_hash)| {
+            block_hash.is_none() || majority_hash.is_none() || *block_hash == majority_hash
+        });
+        for (endpoint, _, block_hash) in &other_fork {
+            warn!(
+                "RPC endpoint {} is on another fork for {}: block hash {:?}, majority {:?}",
+                endpoint.config.url, method, block_hash, majority_hash
+            );
+            endpoint.stats.lock().unwrap().record_disagreement();
+        }
+
+        let majority_answer = strict_majority(same_block.iter().map(|(_, value, _)| value.clone()))
+            .ok_or_else(|| eyre!("No majority answer among {} endpoints for {}", same_block.len(), method))?;
+        for (endpoint, value, _) in &same_block {
+            if *value != majority_answer {
+                warn!("RPC endpoint {} disagrees with the majority answer for {}", endpoint.config.url, method);
+                endpoint.stats.lock().unwrap().record_disagreement();
+            }
+        }
+        Ok(majority_answer)
+    }
+}
+
+/// Record the outcome of a request that took `latency` into the endpoint's stats
+fn record_outcome(stats: &Mutex<EndpointStats>, method: &str, latency: Duration, result: &Result<Value>) {
+    let mut stats = stats.lock().unwrap();
+    match result {
+        Ok(value) => {
+            stats.record_success(latency);
+            if method == "eth_blockNumber"
+                && let Some(block_number) = hex_u64(value)
+            {
+                stats.record_block(block_number);
+            }
+        }
+        // The node answered, it is reachable
+        Err(e) if is_answer(e) => stats.record_success(latency),
+        Err(_) => stats.record_failure(),
+    }
+}
+
+/// Whether `error` is the node's answer to the request, e.g. a reverted call, which the other
+/// endpoints would give as well. A node that lacks the requested block is not up to date.
+fn is_answer(error: &eyre::Report) -> bool {
+    is_node_error(error) && !is_missing_block_error(error)
+}
+
+/// The item shared by more than half of `items`
+fn strict_majority<T: PartialEq>(items: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts: Vec<(T, usize)> = Vec::new();
+    let mut total = 0;
+    for item in items {
+        total += 1;
+        match counts.iter_mut().find(|(known, _)| *known == item) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((item, 1)),
+        }
+    }
+    counts.into_iter().find(|(_, count)| 2 * count > total).map(|(item, _)| item)
+}
+
+fn hex_u64(value: &Value) -> Option<u64> {
+    value.as_str().and_then(|value| u64::from_str_radix(value.trim_start_matches("0x"), 16).ok())
+}
+
+#[async_trait]
+impl RpcTransport for FailoverTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        match self.read_mode {
+            ReadMode::Hedged if method == "eth_call" => self.hedged(method, params).await,
+            ReadMode::Quorum if method == "eth_call" => self.quorum(method, params).await,
+            _ => self.failover(&self.ranked(), method, params, None).await,
+        }
+    }
+
+    async fn subscribe(&self, params: Value) -> Result<mpsc::Receiver<Value>> {
+        let mut last_error = None;
+        for endpoint in self.ranked() {
+            let start = Instant::now();
+            match endpoint.transport.subscribe(params.clone()).await {
+                Ok(mut notifications) => {
+                    endpoint.stats.lock().unwrap().record_success(start.elapsed());
+                    debug!("Subscribed through RPC endpoint {}", endpoint.config.url);
+
+                    // newHeads notifications keep the block height of the endpoint up to date
+                    let stats = Arc::clone(&endpoint.stats);
+                    let (tx, rx) = mpsc::channel(SUBSCRIPTION_BUFFER_SIZE);
+                    tokio::spawn(async move {
+                        while let Some(notification) = notifications.recv().await {
+                            if let Some(block_number) = hex_u64(&notification["number"]) {
+                                stats.lock().unwrap().record_block(block_number);
+                            }
+                            if tx.send(notification).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+                    return Ok(rx);
+                }
+                Err(e) => {
+                    endpoint.stats.lock().unwrap().record_failure();
+                    warn!("Subscription through RPC endpoint {} failed, trying the next one: {}", endpoint.config.url, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| eyre!("No RPC endpoints configured")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sync::transport::{MockTransport, RpcError};
+    use serde_json::json;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn failover(endpoints: &[(&str, f64, &Arc<MockTransport>)]) -> FailoverTransport {
+        let endpoints = endpoints
+            .iter()
+            .map(|(url, weight, transport)| {
+                let transport: Arc<dyn RpcTransport> = Arc::clone(transport) as Arc<dyn RpcTransport>;
+                (RpcEndpoint::new(*url).with_weight(*weight), transport)
+            })
+            .collect();
+        FailoverTransport::new(endpoints, 2)
+    }
+
+    #[tokio::test]
+    async fn test_failover_routes_to_healthiest_endpoint() {
+        let primary = Arc::new(MockTransport::new());
+        let backup = Arc::new(MockTransport::new());
+        let transport = failover(&[("https://primary", 2.0, &primary), ("https://backup", 1.0, &backup)]);
+
+        // The heavier endpoint is preferred, a node error is not a reason to fail over
+        primary.push_response("eth_call", Err(RpcError::new(3, "execution reverted").into()));
+        assert!(transport.request("eth_call", json!([])).await.is_err());
+        assert_eq!(backup.request_count("eth_call"), 0);
+
+        // An unreachable endpoint fails over and drops behind the backup
+        primary.push_response("eth_call", Err(eyre!("connection refused")));
+        backup.push_response("eth_call", Ok(json!("0x01")));
+        assert_eq!(transport.request("eth_call", json!([])).await.unwrap(), json!("0x01"));
+        primary.push_response("eth_call", Err(eyre!("connection refused")));
+        backup.push_response("eth_call", Ok(json!("0x02")));
+        transport.request("eth_call", json!([])).await.unwrap();
+        let health = transport.health();
+        assert_eq!((health[0].errors, health[1].errors), (2, 0));
+        assert!(health[0].score < health[1].score);
+
+        backup.push_response("eth_call", Ok(json!("0x03")));
+        transport.request("eth_call", json!([])).await.unwrap();
+        assert_eq!(primary.request_count("eth_call"), 3);
+
+        // A lagging endpoint is unhealthy, requests go to the one at the tip
+        primary.set_handler("eth_blockNumber", |_| Ok(json!("0x64")));
+        backup.set_handler("eth_blockNumber", |_| Ok(json!("0x5a")));
+        transport.probe().await;
+        let health = transport.health();
+        assert_eq!((health[0].block_lag, health[1].block_lag), (0, 10));
+        assert!(health[0].healthy && !health[1].healthy);
+        primary.push_response("eth_call", Ok(json!("0x04")));
+        assert_eq!(transport.request("eth_call", json!([])).await.unwrap(), json!("0x04"));
+
+        // A node without the requested block yet fails over and counts against its health
+        primary.push_response("eth_call", Err(RpcError::new(RpcError::SERVER_ERROR, "header not found").into()));
+        backup.push_response("eth_call", Ok(json!("0x05")));
+        assert_eq!(transport.request("eth_call", json!([])).await.unwrap(), json!("0x05"));
+        assert_eq!(transport.health()[0].errors, 3);
+
+        assert_eq!("https://a.example|0.5".parse::<RpcEndpoint>().unwrap(), RpcEndpoint::new("https://a.example").with_weight(0.5));
+        assert!("https://a.example|-1".parse::<RpcEndpoint>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failover_subscription_tracks_block_height() {
+        let down = Arc::new(MockTransport::new());
+        let up = Arc::new(MockTransport::new());
+        down.push_response("eth_subscribe", Err(eyre!("connection refused")));
+        let transport = failover(&[("wss://down", 1.0, &down), ("wss://up", 1.0, &up)]);
+
+        let mut notifications = transport.subscribe(json!(["newHeads"])).await.unwrap();
+        assert_eq!(up.notify(json!({ "number": "0x2a" })).await, 1);
+        assert_eq!(notifications.recv().await.unwrap()["number"], "0x2a");
+
+        let health = transport.health();
+        assert_eq!(health[0].errors, 1);
+        assert_eq!(health[1].block_number, Some(42));
+    }
+
+    /// A local JSON-RPC server over HTTP that answers `handler(method, params)` after `delay`
+    async fn stub_server(delay: Duration, handler: impl Fn(&str, &Value) -> Value + Send + Sync + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler = Arc::new(handler);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = Arc::clone(&handler);
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    loop {
+                        let mut content_length = 0;
+                        loop {
+                            let mut line = String::new();
+                            if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                                return;
+                            }
+                            if line.trim_end().is_empty() {
+                                break;
+                            }
+                            if let Some((name, value)) = line.split_once(':')
+                                && name.eq_ignore_ascii_case("content-length")
+                            {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                        }
+                        let mut body = vec![0; content_length];
+                        if stream.read_exact(&mut body).await.is_err() {
+                            return;
+                        }
+
+                        let request: Value = serde_json::from_slice(&body).unwrap();
+                        tokio::time::sleep(delay).await;
+                        let result = handler(request["method"].as_str().unwrap(), &request["params"]);
+                        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }).to_string();
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                            response.len(),
+                            response
+                        );
+                        if stream.get_mut().write_all(response.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    /// A node answering `eth_call` with `answer` and having `block_hash` at every height
+    async fn stub_node(delay: Duration, answer: &'static str, block_hash: &'static str) -> String {
+        stub_server(delay, move |method, params| match method {
+            "eth_getBlockByNumber" => json!({ "number": params[0], "hash": block_hash, "parentHash": "0x00", "timestamp": "0x0" }),
+            _ => json!(answer),
+        })
+        .await
+    }
+
+    fn http_endpoints(endpoints: &[(&str, f64)]) -> Vec<RpcEndpoint> {
+        endpoints.iter().map(|(url, weight)| RpcEndpoint::new(*url).with_weight(*weight)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_hedged_reads_take_first_answer() {
+        let slow = stub_node(Duration::from_millis(500), "0x510e", "0x01").await;
+        let fast = stub_node(Duration::ZERO, "0xfa57", "0x01").await;
+        let endpoints = http_endpoints(&[(&slow, 4.0), (&fast, 1.0)]);
+        let call = json!([{ "to": "0x00", "data": "0x" }, "0x10"]);
+
+        // Failover only asks the preferred endpoint
+        let transport = FailoverTransport::http(&endpoints, Duration::from_secs(5), 2);
+        assert_eq!(transport.request("eth_call", call.clone()).await.unwrap(), json!("0x510e"));
+
+        let transport = FailoverTransport::http(&endpoints, Duration::from_secs(5), 2).with_read_mode(ReadMode::Hedged, 2);
+        let start = Instant::now();
+        assert_eq!(transport.request("eth_call", call.clone()).await.unwrap(), json!("0xfa57"));
+        assert!(start.elapsed() < Duration::from_millis(400));
+
+        // The slower request still finishes and its latency drops the heavier endpoint below the fast one
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        let health = transport.health();
+        assert!(health[0].latency_ms.is_some_and(|latency_ms| latency_ms >= 400.0));
+        assert_eq!(health[0].requests, 1);
+        assert!(health[0].score < health[1].score);
+
+        // An unreachable provider is covered by the other one
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let unreachable = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let endpoints = http_endpoints(&[(&unreachable, 1.0), (&fast, 1.0)]);
+        let transport = FailoverTransport::http(&endpoints, Duration::from_secs(5), 2).with_read_mode(ReadMode::Hedged, 2);
+        assert_eq!(transport.request("eth_call", call).await.unwrap(), json!("0xfa57"));
+        assert_eq!(transport.health()[0].errors, 1);
+    }
+
+    #[tokio::test]
+    async fn test_quorum_reads_flag_disagreement() {
+        let honest_a = stub_node(Duration::ZERO, "0xaa", "0x01").await;
+        let honest_b = stub_node(Duration::ZERO, "0xaa", "0x01").await;
+        let wrong = stub_node(Duration::ZERO, "0xbb", "0x01").await;
+        let forked = stub_node(Duration::ZERO, "0xcc", "0x02").await;
+        let call = json!([{ "to": "0x00", "data": "0x" }, "0x10"]);
+
+        for (odd_one, url) in [("wrong answer", &wrong), ("other fork", &forked)] {
+            let endpoints = http_endpoints(&[(&honest_a, 1.0), (url, 1.0), (&honest_b, 1.0)]);
+            let transport = FailoverTransport::http(&endpoints, Duration::from_secs(5), 2).with_read_mode(ReadMode::Quorum, 3);
+
+            assert_eq!(transport.request("eth_call", call.clone()).await.unwrap(), json!("0xaa"), "{odd_one}");
+            let disagreements: Vec<u64> = transport.health().iter().map(|health| health.disagreements).collect();
+            assert_eq!(disagreements, [0, 1, 0], "{odd_one}");
+        }
+        assert_eq!("quorum".parse::<ReadMode>().unwrap(), ReadMode::Quorum);
+    }
+
+    #[tokio::test]
+    async fn test_quorum_tie_flags_nobody() {
+        let first = stub_node(Duration::ZERO, "0xaa", "0x01").await;
+        let second = stub_node(Duration::ZERO, "0xbb", "0x01").await;
+        let forked = stub_node(Duration::ZERO, "0xbb", "0x02").await;
+        let call = json!([{ "to": "0x00", "data": "0x" }, "0x10"]);
+
+        for (tie, url) in [("answers", &second), ("block hashes", &forked)] {
+            let endpoints = http_endpoints(&[(&first, 1.0), (url, 1.0)]);
+            let transport = FailoverTransport::http(&endpoints, Duration::from_secs(5), 2).with_read_mode(ReadMode::Quorum, 2);
+
+            assert!(transport.request("eth_call", call.clone()).await.is_err(), "{tie}");
+            assert!(transport.health().iter().all(|health| health.disagreements == 0), "{tie}");
+        }
+    }
+}
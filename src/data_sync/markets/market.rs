@@ -2,6 +2,7 @@ use crate::logic::graph::{SwapPath, TokenGraph, SwapPathSet, SwapPathsContainer}
 use crate::utils::constants::WMNT;
 use super::market_config::MarketConfigSection;
 use crate::logic::pools::pool_id::PoolId;
+use crate::logic::pools::pool_index::DensePoolIndex;
 use crate::{PoolWrapper, Token};
 use alloy_primitives::Address;
 use dashmap::{DashMap, DashSet};
@@ -147,6 +148,16 @@ impl Market {
         &self.token_graph.pools
     }
 
+    /// Get the dense pool index layout shared with market snapshots
+    pub fn pool_index(&self) -> Arc<DensePoolIndex> {
+        self.token_graph.pool_dense_index.clone()
+    }
+
+    /// Get the dense index of a pool in the market
+    pub fn get_pool_dense_index(&self, pool_id: &PoolId) -> Option<usize> {
+        self.token_graph.get_pool_dense_index(pool_id)
+    }
+
     /// Get a reference to the pools map in the market.
     pub fn enabled_pools(&self) -> Vec<PoolWrapper> {
         self.token_graph.pools.values().filter(|pool| !self.market_without_lock.is_pool_disabled(&pool.get_pool_id())).cloned().collect()
@@ -215,7 +226,8 @@ impl Market {
 mod tests {
     use super::*;
     use crate::logic::pools::mock_pool::MockPool;
-    use alloy_primitives::Address;
+    use crate::logic::types::MarketSnapshot;
+    use alloy_primitives::{Address, U256};
     use eyre::Result;
 
     #[test]
@@ -245,6 +257,37 @@ mod tests {
         assert_eq!(market.get_pool(&PoolId::Address(pool_address)).unwrap().pool.get_address(), pool_address);
     }
 
+    #[test]
+    fn test_snapshot_shares_market_pool_index() {
+        let mut market = Market::default();
+        let pool_a = Address::repeat_byte(1);
+        let pool_b = Address::repeat_byte(2);
+        market.add_pool(MockPool { address: pool_a, token0: Address::repeat_byte(3), token1: Address::repeat_byte(4) });
+        market.add_pool(MockPool { address: pool_b, token0: Address::repeat_byte(3), token1: Address::repeat_byte(5) });
+
+        assert_eq!(market.get_pool_dense_index(&PoolId::Address(pool_a)), Some(0));
+        assert_eq!(market.get_pool_dense_index(&PoolId::Address(pool_b)), Some(1));
+
+        let pool_index = market.pool_index();
+        let mut snapshot = MarketSnapshot::with_pool_index(1, pool_index.clone());
+        snapshot.set_pool_reserves(PoolId::Address(pool_b), U256::from(10), U256::from(20));
+
+        // The snapshot reuses the market layout instead of allocating its own
+        assert!(Arc::ptr_eq(&snapshot.pool_index, &pool_index));
+        assert_eq!(snapshot.get_pool_state(1).map(|state| state.reserves()), Some((U256::from(10), U256::from(20))));
+        assert!(snapshot.get_pool_state(0).is_none());
+        assert_eq!(snapshot.pools_with_data_count(), 1);
+
+        // A pool the market doesn't know doesn't copy the shared layout
+        let unknown = PoolId::Address(Address::repeat_byte(9));
+        snapshot.set_pool_reserves(unknown, U256::from(30), U256::from(40));
+        snapshot.set_enabled_pools([unknown, PoolId::Address(pool_b)].into_iter().collect());
+        assert!(Arc::ptr_eq(&snapshot.pool_index, &pool_index));
+        assert_eq!(snapshot.get_pool_reserves(&unknown), Some((U256::from(30), U256::from(40))));
+        assert!(snapshot.is_pool_enabled(&unknown));
+        assert_eq!((snapshot.pools_with_data_count(), snapshot.enabled_pools_with_data_count()), (2, 2));
+    }
+
     #[test]
     fn test_add_token() {
         let mut market = Market::default();
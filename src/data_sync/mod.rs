@@ -3,8 +3,13 @@
 /// This layer implements the high-performance data synchronization architecture
 /// as described in the design document. It provides:
 /// 
+/// - Pluggable JSON-RPC transports (HTTP, WebSocket, scripted in-memory mock)
+/// - Weighted RPC endpoints with health scoring, failover, hedged and quorum reads
 /// - WebSocket-based blockchain event subscription (newHeads)
 /// - Multicall-based batch pool data querying
+/// - Optional event-driven reserve updates from UniswapV2 `Sync` logs
+/// - Parent hash verification of new heads and chain reorganization events
+/// - Missed block detection with backfill from historical state or gap events
 /// - Real-time market data aggregation
 /// - Atomic data delivery to the logic layer
 /// 
@@ -13,8 +18,13 @@
 
 // Core data sync components
 pub mod config;
+pub mod transport;
+pub mod failover;
 pub mod websocket;
 pub mod multicall;
+pub mod sync_events;
+pub mod chain_tracker;
+pub mod block_gaps;
 pub mod aggregator;
 pub mod service;
 
@@ -28,9 +38,14 @@ mod tests;
 // Re-export main components for easy usage
 pub use config::DataSyncConfig;
 pub use service::{DataSyncService, DataSyncServiceBuilder};
+pub use transport::{RpcTransport, RpcError, HttpTransport, WsTransport, MockTransport};
+pub use failover::{FailoverTransport, RpcEndpoint, EndpointHealth, ReadMode};
 pub use websocket::{WebSocketManager, BlockHeader};
-pub use multicall::MulticallManager;
-pub use aggregator::{DataAggregator, AggregatorStats};
+pub use multicall::{MulticallManager, ReserveFetchError, ReserveResult};
+pub use sync_events::{ReserveSyncMode, SyncLogFetcher};
+pub use chain_tracker::{ChainTracker, ChainUpdate};
+pub use block_gaps::{BlockGap, GapDetector, GapHandling};
+pub use aggregator::{DataAggregator, AggregatorStats, AdaptiveBatchSize};
 
 // Legacy re-exports (maintain compatibility)
 pub use markets::{Market, MarketWithoutLock, MarketConfigSection};
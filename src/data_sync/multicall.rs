@@ -1,7 +1,8 @@
 use alloy_primitives::{Address, Bytes, U256};
 use alloy_sol_types::{sol, SolCall};
 use eyre::Result;
-use serde_json::Value;
+use std::sync::Arc;
+use crate::data_sync::transport::{HttpTransport, RpcError, RpcTransport};
 use crate::logic::pools::PoolId;
 
 // Standard Multicall3 contract interface
@@ -30,29 +31,61 @@ sol! {
     
     /// ERC20 Pair interface for getReserves
     interface IUniswapV2Pair {
+        event Sync(uint112 reserve0, uint112 reserve1);
+
         function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
     }
+
+    /// ERC20 interface for balanceOf
+    interface IERC20 {
+        function balanceOf(address account) external view returns (uint256 balance);
+    }
+}
+
+/// Why the reserves of a single pool could not be fetched
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ReserveFetchError {
+    #[error("getReserves reverted")]
+    Reverted,
+    #[error("invalid getReserves return data: {0}")]
+    InvalidReturnData(String),
+    #[error("out of gas even when called alone: {0}")]
+    OutOfGas(String),
+    #[error("batch request failed: {0}")]
+    BatchFailed(String),
+}
+
+/// Reserves of one pool, or why they are missing
+pub type ReserveResult = std::result::Result<(U256, U256), ReserveFetchError>;
+
+/// Whether the whole aggregate3 request failed in the EVM, i.e. ran out of gas
+///
+/// Every call is made with `allowFailure`, so a reverting pool can't fail the request. Nodes
+/// word running out of gas differently but report it as a generic server error, an unknown
+/// block is reported the same way and is not a gas failure.
+fn is_gas_error(error: &eyre::Report) -> bool {
+    error
+        .downcast_ref::<RpcError>()
+        .is_some_and(|error| error.code == RpcError::SERVER_ERROR && !error.is_missing_block())
 }
 
 /// Multicall manager for batch querying pool reserves
 #[derive(Debug, Clone)]
 pub struct MulticallManager {
     multicall_address: Address,
-    http_client: reqwest::Client,
-    rpc_url: String,
+    transport: Arc<dyn RpcTransport>,
 }
 
 impl MulticallManager {
     pub fn new(multicall_address: Address, rpc_url: String, timeout: std::time::Duration) -> Self {
-        let http_client = reqwest::Client::builder()
-            .timeout(timeout)
-            .build()
-            .expect("Failed to create HTTP client");
-            
+        Self::from_transport(multicall_address, Arc::new(HttpTransport::new(rpc_url, timeout)))
+    }
+    
+    /// Send the calls through any transport, e.g. a [crate::data_sync::MockTransport] in tests
+    pub fn from_transport(multicall_address: Address, transport: Arc<dyn RpcTransport>) -> Self {
         Self {
             multicall_address,
-            http_client,
-            rpc_url,
+            transport,
         }
     }
     
@@ -66,14 +99,122 @@ impl MulticallManager {
         }
     }
     
+    /// Prepare balanceOf call data for a token holder
+    pub fn prepare_balance_of_call(token: Address, owner: Address) -> Multicall3::Call {
+        let call_data = IERC20::balanceOfCall { account: owner }.abi_encode();
+
+        Multicall3::Call {
+            target: token,
+            callData: call_data.into(),
+        }
+    }
+
+    /// Batch query the balances of `owner` for multiple tokens
+    ///
+    /// Every token is called with `allowFailure`, a token whose `balanceOf` reverts or returns
+    /// invalid data gets `None` without failing the others.
+    pub async fn batch_get_balances(&self, owner: Address, tokens: &[Address], block_number: Option<u64>) -> Result<Vec<(Address, Option<U256>)>> {
+        if tokens.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let calls: Vec<Multicall3::Call3> = tokens
+            .iter()
+            .map(|token| {
+                let call = Self::prepare_balance_of_call(*token, owner);
+                Multicall3::Call3 {
+                    target: call.target,
+                    allowFailure: true,
+                    callData: call.callData,
+                }
+            })
+            .collect();
+
+        let multicall_data = Multicall3::aggregate3Call { calls }.abi_encode();
+        let response = self.call_contract(
+            self.multicall_address,
+            multicall_data.into(),
+            block_number,
+        ).await?;
+        let decoded = Multicall3::aggregate3Call::abi_decode_returns(&response, true)?;
+
+        if decoded.returnData.len() != tokens.len() {
+            return Err(eyre::eyre!(
+                "aggregate3 returned {} results for {} calls",
+                decoded.returnData.len(),
+                tokens.len()
+            ));
+        }
+
+        Ok(tokens
+            .iter()
+            .zip(decoded.returnData.iter())
+            .map(|(token, result)| {
+                if !result.success {
+                    tracing::warn!("balanceOf of token {:?} reverted", token);
+                    return (*token, None);
+                }
+                match IERC20::balanceOfCall::abi_decode_returns(&result.returnData, true) {
+                    Ok(balance) => (*token, Some(balance.balance)),
+                    Err(e) => {
+                        tracing::warn!("Failed to decode balance of token {:?}: {}", token, e);
+                        (*token, None)
+                    }
+                }
+            })
+            .collect())
+    }
+
     /// Batch query reserves for multiple pools
-    pub async fn batch_get_reserves(&self, pool_addresses: &[PoolId], block_number: Option<u64>) -> Result<Vec<(PoolId, Option<(U256, U256)>)>> {
+    ///
+    /// Every pool is called with `allowFailure`, so a reverting pool only fails its own entry.
+    /// When the whole request fails for gas reasons the batch is split in halves and retried
+    /// until the offending pool is isolated. Other errors of the full batch are returned as is.
+    pub async fn batch_get_reserves(&self, pool_addresses: &[PoolId], block_number: Option<u64>) -> Result<Vec<(PoolId, ReserveResult)>> {
         if pool_addresses.is_empty() {
             return Ok(Vec::new());
         }
         
-        // Prepare multicall calls
-        let calls: Vec<Multicall3::Call> = pool_addresses
+        let mut results: Vec<Option<ReserveResult>> = vec![None; pool_addresses.len()];
+        let mut pending: Vec<std::ops::Range<usize>> = std::iter::once(0..pool_addresses.len()).collect();
+        
+        while let Some(range) = pending.pop() {
+            match self.aggregate3_reserves(&pool_addresses[range.clone()], block_number).await {
+                Ok(batch_results) => {
+                    for (slot, result) in results[range].iter_mut().zip(batch_results) {
+                        *slot = Some(result);
+                    }
+                }
+                Err(e) if is_gas_error(&e) && range.len() > 1 => {
+                    let middle = range.start + range.len() / 2;
+                    tracing::debug!("Reserve batch of {} pools ran out of gas, retrying in halves: {}", range.len(), e);
+                    pending.push(middle..range.end);
+                    pending.push(range.start..middle);
+                }
+                Err(e) if range.len() == pool_addresses.len() && !is_gas_error(&e) => return Err(e),
+                Err(e) => {
+                    let error = if is_gas_error(&e) {
+                        ReserveFetchError::OutOfGas(e.to_string())
+                    } else {
+                        ReserveFetchError::BatchFailed(e.to_string())
+                    };
+                    for slot in &mut results[range] {
+                        *slot = Some(Err(error.clone()));
+                    }
+                }
+            }
+        }
+        
+        Ok(pool_addresses
+            .iter()
+            .zip(results)
+            .map(|(pool_id, result)| (*pool_id, result.unwrap_or_else(|| Err(ReserveFetchError::BatchFailed("no result".to_string())))))
+            .collect())
+    }
+    
+    /// One aggregate3 request for the reserves of `pool_addresses`
+    async fn aggregate3_reserves(&self, pool_addresses: &[PoolId], block_number: Option<u64>) -> Result<Vec<ReserveResult>> {
+        let calls: Vec<Multicall3::Call3> = pool_addresses
             .iter()
             .map(|pool_id| {
                 let address = match pool_id {
@@ -84,47 +225,45 @@ impl MulticallManager {
                         Address::from_slice(&hash.as_slice()[0..20])
                     }
                 };
-                Self::prepare_get_reserves_call(address)
+                let call = Self::prepare_get_reserves_call(address);
+                Multicall3::Call3 {
+                    target: call.target,
+                    allowFailure: true,
+                    callData: call.callData,
+                }
             })
             .collect();
         
-        // Encode multicall
-        let multicall_data = Multicall3::aggregateCall { calls }.abi_encode();
-        
-        // Make RPC call
+        let multicall_data = Multicall3::aggregate3Call { calls }.abi_encode();
         let response = self.call_contract(
             self.multicall_address,
             multicall_data.into(),
             block_number,
         ).await?;
+        let decoded = Multicall3::aggregate3Call::abi_decode_returns(&response, true)?;
         
-        // Decode response
-        let decoded = Multicall3::aggregateCall::abi_decode_returns(&response, true)?;
-        
-        // Parse results
-        let mut results = Vec::new();
-        for (i, return_data) in decoded.returnData.iter().enumerate() {
-            let pool_id = pool_addresses[i];
-            
-            if return_data.is_empty() {
-                results.push((pool_id, None));
-                continue;
-            }
-            
-            match IUniswapV2Pair::getReservesCall::abi_decode_returns(return_data, true) {
-                Ok(reserves) => {
-                    let reserve0 = U256::from(reserves.reserve0);
-                    let reserve1 = U256::from(reserves.reserve1);
-                    results.push((pool_id, Some((reserve0, reserve1))));
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to decode reserves for pool {:?}: {}", pool_id, e);
-                    results.push((pool_id, None));
-                }
-            }
+        if decoded.returnData.len() != pool_addresses.len() {
+            return Err(eyre::eyre!(
+                "aggregate3 returned {} results for {} calls",
+                decoded.returnData.len(),
+                pool_addresses.len()
+            ));
+        }
+        
+        Ok(decoded.returnData.iter().map(Self::decode_reserves).collect())
+    }
+    
+    fn decode_reserves(result: &Multicall3::Result) -> ReserveResult {
+        if !result.success {
+            return Err(ReserveFetchError::Reverted);
+        }
+        if result.returnData.is_empty() {
+            return Err(ReserveFetchError::InvalidReturnData("empty return data".to_string()));
         }
         
-        Ok(results)
+        IUniswapV2Pair::getReservesCall::abi_decode_returns(&result.returnData, true)
+            .map(|reserves| (U256::from(reserves.reserve0), U256::from(reserves.reserve1)))
+            .map_err(|e| ReserveFetchError::InvalidReturnData(e.to_string()))
     }
     
     /// Make a contract call via RPC
@@ -139,35 +278,17 @@ impl MulticallManager {
             None => "latest".to_string(),
         };
         
-        let request_body = serde_json::json!({
-            "jsonrpc": "2.0",
-            "method": "eth_call",
-            "params": [
-                {
-                    "to": format!("{:#x}", to),
-                    "data": format!("{:#x}", data)
-                },
-                block_param
-            ],
-            "id": 1
-        });
-        
-        let response = self.http_client
-            .post(&self.rpc_url)
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
-        
-        let response_json: Value = response.json().await?;
-        
-        if let Some(error) = response_json.get("error") {
-            return Err(eyre::eyre!("RPC error: {}", error));
-        }
+        let params = serde_json::json!([
+            {
+                "to": format!("{:#x}", to),
+                "data": format!("{:#x}", data)
+            },
+            block_param
+        ]);
         
-        let result = response_json
-            .get("result")
-            .and_then(|r| r.as_str())
+        let response = self.transport.request("eth_call", params).await?;
+        let result = response
+            .as_str()
             .ok_or_else(|| eyre::eyre!("Missing result in RPC response"))?;
         
         let bytes = hex::decode(result.trim_start_matches("0x"))?;
@@ -193,6 +314,28 @@ mod tests {
         assert_eq!(&call.callData[0..4], expected_selector);
     }
     
+    #[test]
+    fn test_prepare_balance_of_call() {
+        let token = Address::repeat_byte(0x42);
+        let owner = Address::repeat_byte(0x24);
+        let call = MulticallManager::prepare_balance_of_call(token, owner);
+
+        assert_eq!(call.target, token);
+        let decoded = IERC20::balanceOfCall::abi_decode(&call.callData, true).unwrap();
+        assert_eq!(decoded.account, owner);
+    }
+
+    #[test]
+    fn test_gas_errors_are_classified_by_code() {
+        // geth and reth word the same failure differently
+        for message in ["gas required exceeds allowance (30000000)", "EVM error OutOfGas"] {
+            assert!(is_gas_error(&RpcError::new(RpcError::SERVER_ERROR, message).into()));
+        }
+        assert!(!is_gas_error(&RpcError::new(RpcError::SERVER_ERROR, "header not found").into()));
+        assert!(!is_gas_error(&RpcError::new(-32005, "limit exceeded").into()));
+        assert!(!is_gas_error(&eyre::eyre!("out of gas")));
+    }
+
     #[test]
     fn test_multicall_manager_creation() {
         let multicall_address = Address::repeat_byte(0x11);
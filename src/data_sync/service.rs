@@ -2,16 +2,26 @@ use crate::data_sync::{
     config::DataSyncConfig,
     websocket::{WebSocketManager, BlockHeader},
     multicall::MulticallManager,
+    transport::RpcTransport,
+    failover::{EndpointHealth, FailoverTransport, ReadMode},
+    sync_events::{ReserveSyncMode, SyncLogFetcher},
+    chain_tracker::{ChainTracker, ChainUpdate},
+    block_gaps::{fetch_block_headers, BlockGap, GapDetector, GapHandling},
     aggregator::{DataAggregator, AggregatorStats},
     markets::{Market, MarketConfigSection},
 };
-use crate::logic::types::MarketSnapshot;
+use crate::logic::snapshot_channel::{snapshot_channel, DeliveryStats, SnapshotReceiver, SnapshotSender};
 use crate::logic::pools::PoolId;
+use crate::logic::types::ReorgEvent;
+use crate::utils::{Metric, MetricsRegistry, Stage, StateCache, spawn_metrics_server};
 use crate::PoolWrapper;
 use alloy_primitives::Address;
 use eyre::Result;
+use std::collections::VecDeque;
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use tokio::sync::{broadcast, mpsc, RwLock};
 use tokio::task::JoinHandle;
 use tracing::{info, warn, error, debug};
 
@@ -26,13 +36,32 @@ pub struct DataSyncService {
     market: Arc<RwLock<Market>>,
     aggregator: Arc<RwLock<DataAggregator>>,
     
+    // Executor inventory refreshed every block, next to the reserves
+    state_cache: Arc<StateCache>,
+    executor_address: Option<Address>,
+    inventory_tokens: Vec<Address>,
+    balance_reader: MulticallManager,
+    
+    // Stage latencies and counters, optionally served over HTTP
+    metrics: Arc<MetricsRegistry>,
+    
     // Channels for communication
-    market_data_tx: mpsc::Sender<MarketSnapshot>,
-    market_data_rx: Option<mpsc::Receiver<MarketSnapshot>>,
+    market_data_tx: Arc<SnapshotSender>,
+    market_data_rx: Option<SnapshotReceiver>,
+    reorg_tx: broadcast::Sender<ReorgEvent>,
+    gap_tx: broadcast::Sender<BlockGap>,
+    
+    // Used to fetch the ancestors of forks and the headers of missed blocks
+    request_transport: Arc<dyn RpcTransport>,
+    
+    // Endpoint health of the transports built from the config
+    failover_transports: Vec<Arc<FailoverTransport>>,
     
     // Task handles
     websocket_task: Option<JoinHandle<()>>,
+    health_check_tasks: Vec<JoinHandle<()>>,
     aggregation_task: Option<JoinHandle<()>>,
+    metrics_task: Option<JoinHandle<()>>,
     
     // Shutdown coordination
     shutdown_tx: Option<mpsc::Sender<()>>,
@@ -41,6 +70,33 @@ pub struct DataSyncService {
 impl DataSyncService {
     /// Create a new data synchronization service
     pub async fn new(config: DataSyncConfig, initial_pools: Vec<PoolWrapper>) -> Result<Self> {
+        // Two endpoints can only tie when they disagree
+        if config.rpc_read_mode == ReadMode::Quorum && config.rpc_read_fanout < 3 {
+            return Err(eyre::eyre!("Quorum reads need a fanout of at least 3, got {}", config.rpc_read_fanout));
+        }
+        let request_transport = Arc::new(
+            FailoverTransport::http(&config.http_endpoints(), config.http_timeout(), config.max_block_lag)
+                .with_read_mode(config.rpc_read_mode, config.rpc_read_fanout),
+        );
+        let subscription_transport = Arc::new(FailoverTransport::ws(&config.wss_endpoints(), config.ws_connection_timeout(), config.max_block_lag));
+        let mut service = Self::from_transports(
+            config,
+            initial_pools,
+            Arc::clone(&request_transport) as Arc<dyn RpcTransport>,
+            Arc::clone(&subscription_transport) as Arc<dyn RpcTransport>,
+        ).await?;
+        service.failover_transports = vec![request_transport, subscription_transport];
+        Ok(service)
+    }
+    
+    /// Create the service on top of given transports instead of the HTTP and WebSocket URLs of
+    /// the config, e.g. a [crate::data_sync::MockTransport] to run it offline
+    pub async fn from_transports(
+        config: DataSyncConfig,
+        initial_pools: Vec<PoolWrapper>,
+        request_transport: Arc<dyn RpcTransport>,
+        subscription_transport: Arc<dyn RpcTransport>,
+    ) -> Result<Self> {
         info!("Initializing DataSyncService with {} initial pools", initial_pools.len());
         
         // Create WebSocket manager
@@ -49,17 +105,13 @@ impl DataSyncService {
             config.ws_connection_timeout(),
             config.max_reconnect_attempts,
             config.reconnect_delay(),
-        ));
+        ).with_transport(subscription_transport));
         
         // Create Multicall manager
         let multicall_address = config.multicall_address.parse::<Address>()
             .map_err(|e| eyre::eyre!("Invalid multicall address: {}", e))?;
         
-        let multicall_manager = MulticallManager::new(
-            multicall_address,
-            config.rpc_http_url.clone(),
-            config.http_timeout(),
-        );
+        let multicall_manager = MulticallManager::from_transport(multicall_address, Arc::clone(&request_transport));
         
         // Create market and add initial pools
         let mut market = Market::new(MarketConfigSection::default());
@@ -69,35 +121,114 @@ impl DataSyncService {
         let market = Arc::new(RwLock::new(market));
         
         // Create data aggregator
-        let aggregator = Arc::new(RwLock::new(DataAggregator::new(
-            multicall_manager,
+        let mut aggregator = DataAggregator::new(
+            multicall_manager.clone(),
             config.max_pools_per_batch,
-        )));
+        )
+        .with_max_concurrent_batches(config.max_concurrent_batches)
+        .with_adaptive_batch_size(config.min_pools_per_batch, config.target_batch_latency());
+        if config.reserve_sync_mode == ReserveSyncMode::Events {
+            aggregator = aggregator.with_event_sync(SyncLogFetcher::new(Arc::clone(&request_transport)), config.full_resync_interval_blocks);
+        }
+        let aggregator = Arc::new(RwLock::new(aggregator));
+        
+        // Parse the executor inventory to track
+        let executor_address = config.executor_address.as_deref()
+            .map(|address| address.parse::<Address>())
+            .transpose()
+            .map_err(|e| eyre::eyre!("Invalid executor address: {}", e))?;
+        let inventory_tokens = config.inventory_tokens.iter()
+            .map(|token| token.parse::<Address>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| eyre::eyre!("Invalid inventory token address: {}", e))?;
         
         // Create market data channel
-        let (market_data_tx, market_data_rx) = mpsc::channel(config.channel_buffer_size);
+        let (market_data_tx, market_data_rx) = snapshot_channel(config.snapshot_delivery, config.channel_buffer_size);
+        let market_data_tx = Arc::new(market_data_tx);
+        let (reorg_tx, _) = broadcast::channel(config.channel_buffer_size.max(1));
+        let (gap_tx, _) = broadcast::channel(config.channel_buffer_size.max(1));
         
         Ok(Self {
             config,
             websocket_manager,
             market,
             aggregator,
+            state_cache: Arc::new(StateCache::new_default()),
+            executor_address,
+            inventory_tokens,
+            balance_reader: multicall_manager,
+            metrics: Arc::new(MetricsRegistry::new()),
             market_data_tx,
             market_data_rx: Some(market_data_rx),
+            reorg_tx,
+            gap_tx,
+            request_transport,
+            failover_transports: Vec::new(),
             websocket_task: None,
+            health_check_tasks: Vec::new(),
             aggregation_task: None,
+            metrics_task: None,
             shutdown_tx: None,
         })
     }
     
+    /// Use a shared state cache for the executor balances, e.g. the one read by the profit calculator
+    pub fn with_state_cache(mut self, state_cache: Arc<StateCache>) -> Self {
+        self.state_cache = state_cache;
+        self
+    }
+    
+    /// State cache the executor balances are refreshed into
+    pub fn state_cache(&self) -> Arc<StateCache> {
+        Arc::clone(&self.state_cache)
+    }
+    
+    /// Record into a shared metrics registry, e.g. the one the arbitrage engine records into
+    pub fn with_metrics(mut self, metrics: Arc<MetricsRegistry>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+    
+    /// Metrics registry the data layer records into
+    pub fn metrics(&self) -> Arc<MetricsRegistry> {
+        Arc::clone(&self.metrics)
+    }
+    
+    /// Delivered and skipped snapshot counts of the channel to the logic layer
+    pub fn delivery_stats(&self) -> Arc<DeliveryStats> {
+        self.market_data_tx.stats()
+    }
+    
+    /// Chain reorganizations detected from now on, sent before the snapshot of the new head
+    pub fn subscribe_reorgs(&self) -> broadcast::Receiver<ReorgEvent> {
+        self.reorg_tx.subscribe()
+    }
+    
+    /// Missed blocks that were not backfilled, see [DataSyncConfig::gap_handling]
+    pub fn subscribe_gaps(&self) -> broadcast::Receiver<BlockGap> {
+        self.gap_tx.subscribe()
+    }
+    
     /// Start the data synchronization service
-    pub async fn start(&mut self) -> Result<mpsc::Receiver<MarketSnapshot>> {
+    pub async fn start(&mut self) -> Result<SnapshotReceiver> {
         info!("Starting DataSyncService");
         
         // Take the receiver to return to caller
         let market_data_rx = self.market_data_rx.take()
             .ok_or_else(|| eyre::eyre!("DataSyncService already started"))?;
         
+        self.aggregator.write().await.set_metrics(Arc::clone(&self.metrics));
+        if let Some(metrics_listen_addr) = self.config.metrics_listen_addr.as_deref() {
+            let (_, metrics_task) = spawn_metrics_server(metrics_listen_addr, Arc::clone(&self.metrics)).await?;
+            self.metrics_task = Some(metrics_task);
+        }
+        
+        // Keep the block heights and latencies of idle endpoints current
+        for transport in &self.failover_transports {
+            let task = Arc::clone(transport).spawn_health_checks(self.config.endpoint_probe_interval());
+            self.health_check_tasks.push(task);
+        }
+        
         // Start WebSocket subscription
         let (block_rx, shutdown_tx) = self.websocket_manager.subscribe_new_heads().await?;
         self.shutdown_tx = Some(shutdown_tx);
@@ -132,6 +263,14 @@ impl DataSyncService {
             }
         }
         
+        if let Some(metrics_task) = self.metrics_task.take() {
+            metrics_task.abort();
+        }
+        
+        for health_check_task in self.health_check_tasks.drain(..) {
+            health_check_task.abort();
+        }
+        
         info!("DataSyncService stopped");
         Ok(())
     }
@@ -165,67 +304,180 @@ impl DataSyncService {
     }
     
     
+    /// Health of the configured RPC endpoints, HTTP ones first
+    ///
+    /// Empty for a service built with [DataSyncService::from_transports].
+    pub fn endpoint_health(&self) -> Vec<EndpointHealth> {
+        self.failover_transports.iter().flat_map(|transport| transport.health()).collect()
+    }
+    
     /// Get aggregator statistics
     pub async fn get_stats(&self) -> AggregatorStats {
         let aggregator = self.aggregator.read().await;
         let monitored_count = self.get_monitored_pools().await.len();
-        aggregator.get_stats(monitored_count)
+        let mut stats = aggregator.get_stats(monitored_count);
+        stats.endpoints = self.endpoint_health();
+        stats
     }
     
     /// Start the aggregation task that processes new blocks
     async fn start_aggregation_task(
         &self,
-        mut block_rx: mpsc::Receiver<BlockHeader>,
+        mut block_rx: mpsc::Receiver<(BlockHeader, Instant)>,
     ) -> Result<JoinHandle<()>> {
         let aggregator = Arc::clone(&self.aggregator);
         let market = Arc::clone(&self.market);
-        let market_data_tx = self.market_data_tx.clone();
+        let market_data_tx = Arc::clone(&self.market_data_tx);
+        let state_cache = Arc::clone(&self.state_cache);
+        let executor_address = self.executor_address;
+        let inventory_tokens = self.inventory_tokens.clone();
+        let balance_reader = self.balance_reader.clone();
+        let metrics = Arc::clone(&self.metrics);
+        let subscription_epoch = self.websocket_manager.subscription_epoch();
+        let reorg_tx = self.reorg_tx.clone();
+        let mut chain_tracker = ChainTracker::new(self.config.max_reorg_depth)
+            .with_transport(Arc::clone(&self.request_transport));
+        let gap_tx = self.gap_tx.clone();
+        let gap_handling = self.config.gap_handling;
+        let max_backfill_blocks = self.config.max_backfill_blocks;
+        let request_transport = Arc::clone(&self.request_transport);
         
         let task = tokio::spawn(async move {
             info!("Aggregation task started");
+            let mut last_epoch = 0;
+            let mut gap_detector = GapDetector::new();
+            // Headers of missed blocks, followed by the head that revealed the gap
+            let mut backfill: VecDeque<(BlockHeader, Instant, bool)> = VecDeque::new();
             
-            while let Some(block_header) = block_rx.recv().await {
+            loop {
+                let (block_header, received_at, backfilled) = match backfill.pop_front() {
+                    Some(queued) => queued,
+                    None => match block_rx.recv().await {
+                        Some((block_header, received_at)) => (block_header, received_at, false),
+                        None => break,
+                    },
+                };
                 debug!("Processing new block: {}", block_header.number);
                 
+                // Headers dropped by the WebSocket or by a full header channel leave gaps
+                if let Ok(block_number) = block_header.block_number() {
+                    if let Some(gap) = gap_detector.missed_before(block_number) {
+                        if gap_handling == GapHandling::Backfill && gap.missed_count() <= max_backfill_blocks {
+                            match fetch_block_headers(request_transport.as_ref(), gap).await {
+                                Ok(headers) => {
+                                    info!("Backfilling blocks {}..={} missed before block {}", gap.first_missed, gap.last_missed, block_number);
+                                    metrics.increment(Metric::BlocksTotal, Stage::Backfill, gap.missed_count());
+                                    let fetched_at = Instant::now();
+                                    backfill.extend(headers.into_iter().map(|header| (header, fetched_at, true)));
+                                    backfill.push_back((block_header, received_at, false));
+                                    continue;
+                                }
+                                Err(e) => {
+                                    warn!("Failed to fetch the headers of missed blocks {}..={}: {}", gap.first_missed, gap.last_missed, e);
+                                    metrics.increment(Metric::StageFailuresTotal, Stage::Backfill, 1);
+                                }
+                            }
+                        }
+                        warn!("Missed blocks {}..={} before block {}", gap.first_missed, gap.last_missed, block_number);
+                        metrics.increment(Metric::BlocksSkippedTotal, Stage::BlockToSnapshot, gap.missed_count());
+                        let _ = gap_tx.send(gap);
+                    }
+                    gap_detector.record(block_number);
+                }
+                
+                match chain_tracker.observe(&block_header).await {
+                    Ok(ChainUpdate::Extended) => {}
+                    Ok(ChainUpdate::Duplicate) => {
+                        debug!("Skipping already processed block {}", block_header.number);
+                        continue;
+                    }
+                    Ok(ChainUpdate::Reorg(reorg)) => {
+                        warn!(
+                            "Chain reorganization at block {}: {} orphaned blocks, common ancestor {:?}",
+                            reorg.new_head.number, reorg.depth(), reorg.common_ancestor
+                        );
+                        metrics.increment(Metric::BlocksTotal, Stage::Reorg, reorg.depth() as u64);
+                        // Reserves synced from orphaned blocks are stale
+                        aggregator.write().await.request_full_resync();
+                        let _ = reorg_tx.send(reorg);
+                    }
+                    Err(e) => warn!("Failed to track block {}: {}", block_header.number, e),
+                }
+                
+                // Logs may have been missed while the subscription was down
+                let epoch = subscription_epoch.load(Ordering::Acquire);
+                if epoch != last_epoch {
+                    if last_epoch != 0 {
+                        info!("newHeads subscription re-established, requesting a full reserve resync");
+                        aggregator.write().await.request_full_resync();
+                    }
+                    last_epoch = epoch;
+                }
+                
                 // Get current monitored pools and market stats from market
-                let (monitored_pools, total_pools_count) = {
+                let (monitored_pools, total_pools_count, pool_index) = {
                     let market_guard = market.read().await;
                     let pools = market_guard.enabled_pools()
                         .into_iter()
                         .map(|pool| pool.get_pool_id())
                         .collect::<Vec<_>>();
                     let total_count = market_guard.pools().len();
-                    (pools, total_count)
+                    (pools, total_count, market_guard.pool_index())
                 };
                 
                 let monitored_count = monitored_pools.len();
                 
+                // Refresh the executor inventory at the same block as the reserves, while they are fetched
+                let balance_refresh = async {
+                    let (Some(owner), Ok(block_number)) = (executor_address, block_header.block_number()) else {
+                        return;
+                    };
+                    let refresh_start = Instant::now();
+                    if let Err(e) = refresh_balances(&balance_reader, owner, &inventory_tokens, block_number, &state_cache).await {
+                        warn!("Failed to refresh executor balances for block {}: {}", block_number, e);
+                        metrics.increment(Metric::StageFailuresTotal, Stage::BalanceRefresh, 1);
+                    }
+                    metrics.observe_duration(Stage::BalanceRefresh, refresh_start.elapsed());
+                };
+                
                 // Aggregate market data for this block
                 let mut aggregator_guard = aggregator.write().await;
-                match aggregator_guard.aggregate_market_data(&block_header, monitored_pools, Some(total_pools_count)).await {
-                    Ok(snapshot) => {
+                let (aggregated, ()) = tokio::join!(
+                    aggregator_guard.aggregate_market_data(&block_header, monitored_pools, Some(total_pools_count), pool_index),
+                    balance_refresh,
+                );
+                match aggregated {
+                    Ok(mut snapshot) => {
+                        snapshot.backfill = backfilled;
                         // Validate snapshot before sending
                         if let Err(e) = aggregator_guard.validate_snapshot(&snapshot, monitored_count) {
                             warn!("Invalid market snapshot: {}", e);
+                            metrics.increment(Metric::StageFailuresTotal, Stage::BlockToSnapshot, 1);
                             continue;
                         }
                         
+                        metrics.observe_duration(Stage::BlockToSnapshot, received_at.elapsed());
+                        metrics.increment(Metric::BlocksTotal, Stage::BlockToSnapshot, 1);
+                        
                         // Send snapshot to logic layer
-                        if let Err(e) = market_data_tx.try_send(snapshot) {
-                            match e {
-                                mpsc::error::TrySendError::Full(_) => {
-                                    warn!("Market data channel is full, dropping snapshot");
-                                }
-                                mpsc::error::TrySendError::Closed(_) => {
-                                    error!("Market data channel is closed, stopping aggregation");
-                                    break;
+                        let block_number = snapshot.block_number;
+                        match market_data_tx.send(snapshot) {
+                            Ok(outcome) => {
+                                if let Some(skipped_block) = outcome.skipped_block(block_number) {
+                                    debug!("Logic layer is behind, block {} will not be processed", skipped_block);
+                                    metrics.increment(Metric::BlocksSkippedTotal, Stage::Delivery, 1);
                                 }
                             }
+                            Err(_) => {
+                                error!("Market data channel is closed, stopping aggregation");
+                                break;
+                            }
                         }
                     }
                     Err(e) => {
                         error!("Failed to aggregate market data for block {}: {}", 
                                block_header.number, e);
+                        metrics.increment(Metric::StageFailuresTotal, Stage::BlockToSnapshot, 1);
                     }
                 }
                 
@@ -250,6 +502,40 @@ impl DataSyncService {
     }
 }
 
+/// Refresh the token balances of `owner` into the state cache, returns the number of balances updated
+///
+/// Balances that can't be read at `block_number` are removed, so that they count as missing
+/// instead of being read from an older block.
+async fn refresh_balances(
+    balance_reader: &MulticallManager,
+    owner: Address,
+    tokens: &[Address],
+    block_number: u64,
+    state_cache: &StateCache,
+) -> Result<usize> {
+    let balances = match balance_reader.batch_get_balances(owner, tokens, Some(block_number)).await {
+        Ok(balances) => balances,
+        Err(e) => {
+            tokens.iter().for_each(|token| state_cache.remove_balance(owner, *token));
+            return Err(e);
+        }
+    };
+
+    let mut updated = 0;
+    for (token, balance) in balances {
+        match balance {
+            Some(balance) => {
+                state_cache.set_balance(owner, token, balance);
+                updated += 1;
+            }
+            None => state_cache.remove_balance(owner, token),
+        }
+    }
+
+    debug!("Refreshed {}/{} balances of {:?} at block {}", updated, tokens.len(), owner, block_number);
+    Ok(updated)
+}
+
 impl Drop for DataSyncService {
     fn drop(&mut self) {
         // Attempt graceful shutdown on drop
@@ -263,6 +549,7 @@ impl Drop for DataSyncService {
 pub struct DataSyncServiceBuilder {
     config: Option<DataSyncConfig>,
     pools: Vec<PoolWrapper>,
+    transport: Option<Arc<dyn RpcTransport>>,
 }
 
 impl DataSyncServiceBuilder {
@@ -270,6 +557,7 @@ impl DataSyncServiceBuilder {
         Self {
             config: None,
             pools: Vec::new(),
+            transport: None,
         }
     }
     
@@ -288,12 +576,21 @@ impl DataSyncServiceBuilder {
         self
     }
     
+    /// Use one transport for both requests and subscriptions instead of the config URLs
+    pub fn with_transport(mut self, transport: Arc<dyn RpcTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+    
     pub async fn build(self) -> Result<DataSyncService> {
         let config = self.config.unwrap_or_else(|| {
             DataSyncConfig::from_env().unwrap_or_default()
         });
         
-        DataSyncService::new(config, self.pools).await
+        match self.transport {
+            Some(transport) => DataSyncService::from_transports(config, self.pools, Arc::clone(&transport), transport).await,
+            None => DataSyncService::new(config, self.pools).await,
+        }
     }
 }
 
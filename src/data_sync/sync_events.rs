@@ -0,0 +1,153 @@
+use crate::data_sync::multicall::IUniswapV2Pair;
+use crate::data_sync::transport::RpcTransport;
+use crate::logic::pools::PoolId;
+use alloy_primitives::{Address, B256, U256};
+use alloy_sol_types::SolEvent;
+use eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How the reserves of the monitored pools are kept up to date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReserveSyncMode {
+    /// Query `getReserves` of every monitored pool on every block
+    #[default]
+    Poll,
+    /// Apply the `Sync` events of each block to the last known reserves, with a periodic full
+    /// multicall resync
+    Events,
+}
+
+impl std::str::FromStr for ReserveSyncMode {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "poll" => Ok(ReserveSyncMode::Poll),
+            "events" => Ok(ReserveSyncMode::Events),
+            other => Err(eyre!("Unknown reserve sync mode: {} (expected poll or events)", other)),
+        }
+    }
+}
+
+/// Whether the reserves of a pool can follow its `Sync` logs, pools identified by a hash have no
+/// log address and must be polled
+pub fn has_sync_logs(pool_id: &PoolId) -> bool {
+    matches!(pool_id, PoolId::Address(_))
+}
+
+/// Fetches the UniswapV2 `Sync` logs of a block through `eth_getLogs`
+#[derive(Debug, Clone)]
+pub struct SyncLogFetcher {
+    transport: Arc<dyn RpcTransport>,
+}
+
+impl SyncLogFetcher {
+    pub fn new(transport: Arc<dyn RpcTransport>) -> Self {
+        Self { transport }
+    }
+
+    /// Reserves emitted by `pools` in the block `block_hash`, the last `Sync` of a pool wins
+    pub async fn block_reserves(&self, block_hash: &str, pools: &[PoolId]) -> Result<HashMap<PoolId, (U256, U256)>> {
+        let addresses: Vec<String> = pools
+            .iter()
+            .filter_map(|pool_id| match pool_id {
+                PoolId::Address(address) => Some(format!("{:#x}", address)),
+                PoolId::B256(_) => None,
+            })
+            .collect();
+        if addresses.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let filter = serde_json::json!([{
+            "blockHash": block_hash,
+            "address": addresses,
+            "topics": [format!("{:#x}", IUniswapV2Pair::Sync::SIGNATURE_HASH)],
+        }]);
+        let logs = self.transport.request("eth_getLogs", filter).await?;
+        let logs = logs.as_array().ok_or_else(|| eyre!("eth_getLogs did not return an array: {}", logs))?;
+
+        let mut decoded = Vec::with_capacity(logs.len());
+        for log in logs {
+            if let Some(sync) = decode_sync_log(log)? {
+                decoded.push(sync);
+            }
+        }
+        // Logs are returned in block order, sort anyway so that the last Sync of a pool wins
+        decoded.sort_by_key(|(log_index, _, _)| *log_index);
+
+        Ok(decoded.into_iter().map(|(_, pool_id, reserves)| (pool_id, reserves)).collect())
+    }
+}
+
+fn hex_u64(value: &Value) -> Option<u64> {
+    value.as_str().and_then(|value| u64::from_str_radix(value.trim_start_matches("0x"), 16).ok())
+}
+
+/// `(log index, pool, reserves)` of a `Sync` log
+type DecodedSync = (u64, PoolId, (U256, U256));
+
+/// `None` for removed logs and other events
+fn decode_sync_log(log: &Value) -> Result<Option<DecodedSync>> {
+    if log.get("removed").and_then(Value::as_bool) == Some(true) {
+        return Ok(None);
+    }
+
+    let topic0 = log["topics"].get(0).and_then(Value::as_str).map(str::parse::<B256>).transpose()?;
+    if topic0 != Some(IUniswapV2Pair::Sync::SIGNATURE_HASH) {
+        return Ok(None);
+    }
+
+    let address: Address = log["address"].as_str().ok_or_else(|| eyre!("Sync log without address"))?.parse()?;
+    let data = hex::decode(log["data"].as_str().unwrap_or_default().trim_start_matches("0x"))?;
+    let sync = IUniswapV2Pair::Sync::abi_decode_data(&data, true)?;
+    let log_index = hex_u64(&log["logIndex"]).unwrap_or_default();
+
+    Ok(Some((log_index, PoolId::Address(address), (U256::from(sync.0), U256::from(sync.1)))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sync::transport::MockTransport;
+    use alloy_sol_types::SolValue;
+
+    fn sync_log(pool: Address, log_index: u64, reserve0: u64, reserve1: u64) -> Value {
+        serde_json::json!({
+            "address": format!("{:#x}", pool),
+            "topics": [format!("{:#x}", IUniswapV2Pair::Sync::SIGNATURE_HASH)],
+            "data": format!("0x{}", hex::encode((U256::from(reserve0), U256::from(reserve1)).abi_encode_params())),
+            "logIndex": format!("0x{:x}", log_index),
+            "removed": false,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_block_reserves_from_sync_logs() {
+        let pool = Address::repeat_byte(0x01);
+        let other = Address::repeat_byte(0x02);
+        let mut removed = sync_log(other, 1, 9, 9);
+        removed["removed"] = Value::Bool(true);
+        let mut transfer = sync_log(other, 3, 9, 9);
+        transfer["topics"][0] = Value::String(format!("{:#x}", B256::repeat_byte(0xdd)));
+
+        let transport = Arc::new(MockTransport::new());
+        transport.push_response(
+            "eth_getLogs",
+            Ok(Value::Array(vec![sync_log(pool, 4, 30, 40), removed, sync_log(pool, 2, 10, 20), transfer])),
+        );
+        let fetcher = SyncLogFetcher::new(transport.clone());
+
+        let reserves = fetcher.block_reserves("0xabc", &[PoolId::Address(pool), PoolId::Address(other)]).await.unwrap();
+        assert_eq!(reserves, HashMap::from([(PoolId::Address(pool), (U256::from(30), U256::from(40)))]));
+
+        let (_, params) = &transport.requests()[0];
+        assert_eq!(params[0]["blockHash"], "0xabc");
+        assert_eq!(params[0]["address"].as_array().unwrap().len(), 2);
+        assert_eq!("events".parse::<ReserveSyncMode>().unwrap(), ReserveSyncMode::Events);
+    }
+}
@@ -46,6 +46,7 @@ mod integration_tests {
             reconnect_delay_secs: 1,
             http_timeout_secs: 1,
             channel_buffer_size: 10,
+            ..DataSyncConfig::default()
         };
         
         // Create test pools using MockPool
@@ -151,6 +152,17 @@ mod integration_tests {
         ).await;
         assert!(result.is_err());
         
+        // Quorum reads need three voters
+        let result = DataSyncService::new(
+            DataSyncConfig {
+                rpc_read_mode: ReadMode::Quorum,
+                rpc_read_fanout: 2,
+                ..DataSyncConfig::default()
+            },
+            vec![]
+        ).await;
+        assert!(result.is_err());
+        
         // Test valid config
         let result = DataSyncService::new(DataSyncConfig::default(), vec![]).await;
         assert!(result.is_ok());
@@ -373,3 +385,484 @@ mod performance_tests {
         assert!(duration.as_millis() < 100);
     }
 }
+
+/// Offline tests of the data flow against a scripted transport
+#[cfg(test)]
+mod offline_tests {
+    use super::super::*;
+    use crate::data_sync::multicall::{IUniswapV2Pair, Multicall3};
+    use crate::logic::snapshot_channel::SnapshotDelivery;
+    use crate::logic::pools::{DensePoolIndex, PoolId};
+    use crate::logic::pools::mock_pool::MockPool;
+    use crate::utils::{Metric, MetricsRegistry, Stage};
+    use crate::PoolWrapper;
+    use alloy_primitives::{Address, B256, Bytes, U256};
+    use alloy_sol_types::{SolCall, SolEvent, SolValue};
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::time::{Duration, timeout};
+    
+    /// A node whose Multicall3 answers getReserves from `reserves`, other pools revert
+    fn mock_node(reserves: HashMap<Address, (u64, u64)>) -> Arc<MockTransport> {
+        mock_node_with_gas_limit(reserves, usize::MAX)
+    }
+    
+    /// Like [mock_node], requests with more than `max_calls` calls run out of gas
+    fn mock_node_with_gas_limit(reserves: HashMap<Address, (u64, u64)>, max_calls: usize) -> Arc<MockTransport> {
+        let transport = Arc::new(MockTransport::new());
+        serve_reserves(&transport, reserves, max_calls);
+        transport
+    }
+    
+    /// Answer getReserves from `reserves` from now on
+    fn serve_reserves(transport: &MockTransport, reserves: HashMap<Address, (u64, u64)>, max_calls: usize) {
+        transport.set_handler("eth_call", move |params: &Value| {
+            let data = params[0]["data"].as_str().ok_or_else(|| eyre::eyre!("missing call data"))?;
+            let call = Multicall3::aggregate3Call::abi_decode(&hex::decode(data.trim_start_matches("0x"))?, true)?;
+            if call.calls.len() > max_calls {
+                return Err(RpcError::new(RpcError::SERVER_ERROR, "gas required exceeds allowance (30000000)").into());
+            }
+            let return_data: Vec<Multicall3::Result> = call.calls.iter()
+                .map(|call| match reserves.get(&call.target) {
+                    Some((reserve0, reserve1)) => Multicall3::Result {
+                        success: true,
+                        returnData: (U256::from(*reserve0), U256::from(*reserve1), U256::ZERO).abi_encode_params().into(),
+                    },
+                    None => Multicall3::Result { success: false, returnData: Bytes::new() },
+                })
+                .collect();
+            Ok(json!(format!("0x{}", hex::encode(Multicall3::aggregate3Call::abi_encode_returns(&(return_data,))))))
+        });
+    }
+    
+    fn new_head(number: u64) -> Value {
+        json!({
+            "number": format!("0x{:x}", number),
+            "hash": format!("0x{:064x}", number),
+            "parentHash": format!("0x{:064x}", number.saturating_sub(1)),
+            "timestamp": "0x61234567",
+        })
+    }
+    
+    fn sync_log(pool: Address, reserve0: u64, reserve1: u64) -> Value {
+        json!({
+            "address": format!("{:#x}", pool),
+            "topics": [format!("{:#x}", IUniswapV2Pair::Sync::SIGNATURE_HASH)],
+            "data": format!("0x{}", hex::encode((U256::from(reserve0), U256::from(reserve1)).abi_encode_params())),
+            "logIndex": "0x0",
+            "removed": false,
+        })
+    }
+    
+    fn pool_index(pools: &[PoolId]) -> Arc<DensePoolIndex> {
+        let mut pool_index = DensePoolIndex::new();
+        pools.iter().for_each(|pool_id| { pool_index.insert(*pool_id); });
+        Arc::new(pool_index)
+    }
+    
+    fn mock_pool(byte: u8) -> PoolWrapper {
+        PoolWrapper::new(Arc::new(MockPool {
+            address: Address::repeat_byte(byte),
+            token0: Address::repeat_byte(0xa0),
+            token1: Address::repeat_byte(0xa1),
+        }))
+    }
+    
+    async fn wait_for_subscription(transport: &MockTransport) {
+        timeout(Duration::from_secs(5), async {
+            while transport.subscription_count() == 0 {
+                tokio::task::yield_now().await;
+            }
+        }).await.expect("no newHeads subscription");
+    }
+    
+    #[tokio::test]
+    async fn test_aggregator_with_mock_transport() {
+        let transport = mock_node(HashMap::from([
+            (Address::repeat_byte(0x01), (100, 200)),
+            (Address::repeat_byte(0x02), (300, 400)),
+        ]));
+        // The first of the two batches fails as a whole
+        transport.push_response("eth_call", Err(eyre::eyre!("execution timeout")));
+        
+        let multicall_manager = MulticallManager::from_transport(Address::repeat_byte(0x11), transport.clone());
+        let mut aggregator = DataAggregator::new(multicall_manager, 2);
+        let metrics = Arc::new(MetricsRegistry::new());
+        aggregator.set_metrics(Arc::clone(&metrics));
+        
+        let pools: Vec<PoolId> = [0x03, 0x04, 0x01, 0x02, 0x05].into_iter().map(|byte| PoolId::Address(Address::repeat_byte(byte))).collect();
+        let header: BlockHeader = serde_json::from_value(new_head(42)).unwrap();
+        let mut pool_index = DensePoolIndex::new();
+        pools.iter().for_each(|pool_id| { pool_index.insert(*pool_id); });
+        let pool_index = Arc::new(pool_index);
+        let snapshot = aggregator.aggregate_market_data(&header, pools, None, pool_index).await.unwrap();
+        
+        assert_eq!(snapshot.block_number, 42);
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(Address::repeat_byte(0x01))), Some((U256::from(100), U256::from(200))));
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(Address::repeat_byte(0x02))), Some((U256::from(300), U256::from(400))));
+        assert_eq!(snapshot.pools_with_data_count(), 2);
+        
+        // Three batches, each requested at the block of the header
+        let calls = transport.requests();
+        assert_eq!(calls.len(), 3);
+        assert!(calls.iter().all(|(method, params)| method == "eth_call" && params[1] == json!("0x2a")));
+        assert_eq!(metrics.counter(Metric::StageFailuresTotal, Stage::MulticallBatch), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_concurrent_batches_are_bounded_and_adapt() {
+        let reserves: HashMap<Address, (u64, u64)> = (1..=12).map(|byte| (Address::repeat_byte(byte), (byte as u64, 1))).collect();
+        let transport = mock_node(reserves);
+        transport.set_latency(Duration::from_millis(30));
+        
+        let multicall_manager = MulticallManager::from_transport(Address::repeat_byte(0x11), transport.clone());
+        let mut aggregator = DataAggregator::new(multicall_manager, 2)
+            .with_max_concurrent_batches(3)
+            .with_adaptive_batch_size(1, Duration::from_millis(10));
+        
+        let pools: Vec<PoolId> = (1..=12).map(|byte| PoolId::Address(Address::repeat_byte(byte))).collect();
+        let mut pool_index = DensePoolIndex::new();
+        pools.iter().for_each(|pool_id| { pool_index.insert(*pool_id); });
+        let header: BlockHeader = serde_json::from_value(new_head(5)).unwrap();
+        let snapshot = aggregator.aggregate_market_data(&header, pools, None, Arc::new(pool_index)).await.unwrap();
+        
+        // Six batches of two pools, at most three at a time, all merged into the snapshot
+        assert_eq!(transport.request_count("eth_call"), 6);
+        assert_eq!(transport.max_in_flight(), 3);
+        assert_eq!(snapshot.pools_with_data_count(), 12);
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(Address::repeat_byte(12))), Some((U256::from(12), U256::from(1))));
+        
+        // Every batch was slower than the target
+        let stats = aggregator.get_stats(12);
+        assert_eq!(stats.current_pools_per_batch, 1);
+        assert_eq!(stats.max_concurrent_batches, 3);
+    }
+    
+    #[tokio::test]
+    async fn test_event_sync_applies_sync_logs() {
+        let pool1 = Address::repeat_byte(0x01);
+        let pool2 = Address::repeat_byte(0x02);
+        let transport = mock_node(HashMap::from([(pool1, (100, 200)), (pool2, (300, 400))]));
+        let multicall_manager = MulticallManager::from_transport(Address::repeat_byte(0x11), transport.clone());
+        let mut aggregator = DataAggregator::new(multicall_manager, 50)
+            .with_event_sync(SyncLogFetcher::new(transport.clone()), 10);
+        
+        let pools = vec![PoolId::Address(pool1), PoolId::Address(pool2)];
+        let index = pool_index(&pools);
+        let mut aggregate = async |number: u64| {
+            let header: BlockHeader = serde_json::from_value(new_head(number)).unwrap();
+            aggregator.aggregate_market_data(&header, pools.clone(), None, Arc::clone(&index)).await.unwrap()
+        };
+        
+        // The first block has no state yet and is fully resynced
+        let snapshot = aggregate(1).await;
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(pool2)), Some((U256::from(300), U256::from(400))));
+        assert_eq!((transport.request_count("eth_call"), transport.request_count("eth_getLogs")), (1, 0));
+        
+        // Then only the Sync logs of the block are applied
+        transport.push_response("eth_getLogs", Ok(json!([sync_log(pool1, 110, 190)])));
+        let snapshot = aggregate(2).await;
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(pool1)), Some((U256::from(110), U256::from(190))));
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(pool2)), Some((U256::from(300), U256::from(400))));
+        assert_eq!((transport.request_count("eth_call"), transport.request_count("eth_getLogs")), (1, 1));
+        
+        // Failed logs fall back to a full resync, which restores the node's reserves
+        transport.push_response("eth_getLogs", Err(eyre::eyre!("query timeout")));
+        let snapshot = aggregate(3).await;
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(pool1)), Some((U256::from(100), U256::from(200))));
+        assert_eq!(transport.request_count("eth_call"), 2);
+        
+        // A missed block and the resync interval both trigger a full resync
+        aggregate(5).await;
+        assert_eq!(transport.request_count("eth_call"), 3);
+        transport.set_handler("eth_getLogs", |_| Ok(json!([])));
+        for number in 6..15 {
+            aggregate(number).await;
+        }
+        assert_eq!(transport.request_count("eth_call"), 3);
+        aggregate(15).await;
+        assert_eq!(transport.request_count("eth_call"), 4);
+    }
+    
+    #[tokio::test]
+    async fn test_event_sync_polls_pools_without_sync_logs() {
+        let pool1 = Address::repeat_byte(0x01);
+        let pool2 = Address::repeat_byte(0x02);
+        let transport = mock_node(HashMap::from([(pool1, (100, 200)), (pool2, (300, 400))]));
+        let multicall_manager = MulticallManager::from_transport(Address::repeat_byte(0x11), transport.clone());
+        let mut aggregator = DataAggregator::new(multicall_manager, 50)
+            .with_event_sync(SyncLogFetcher::new(transport.clone()), 10);
+        
+        // A pool identified by a hash has no log address, its reserves are read from pool2's contract
+        let hashed_pool = PoolId::B256(B256::right_padding_from(pool2.as_slice()));
+        let pools = vec![PoolId::Address(pool1), hashed_pool];
+        let index = pool_index(&pools);
+        let mut aggregate = async |number: u64| {
+            let header: BlockHeader = serde_json::from_value(new_head(number)).unwrap();
+            aggregator.aggregate_market_data(&header, pools.clone(), None, Arc::clone(&index)).await.unwrap()
+        };
+        
+        let snapshot = aggregate(1).await;
+        assert_eq!(snapshot.get_pool_reserves(&hashed_pool), Some((U256::from(300), U256::from(400))));
+        
+        // Between full resyncs the hashed pool is still polled every block
+        serve_reserves(&transport, HashMap::from([(pool1, (100, 200)), (pool2, (310, 390))]), usize::MAX);
+        transport.push_response("eth_getLogs", Ok(json!([sync_log(pool1, 110, 190)])));
+        let snapshot = aggregate(2).await;
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(pool1)), Some((U256::from(110), U256::from(190))));
+        assert_eq!(snapshot.get_pool_reserves(&hashed_pool), Some((U256::from(310), U256::from(390))));
+        assert_eq!((transport.request_count("eth_call"), transport.request_count("eth_getLogs")), (2, 1));
+    }
+    
+    #[tokio::test]
+    async fn test_reserves_isolate_failing_pools() {
+        let reserves = HashMap::from([
+            (Address::repeat_byte(0x01), (100, 200)),
+            (Address::repeat_byte(0x03), (300, 400)),
+            (Address::repeat_byte(0x04), (500, 600)),
+        ]);
+        // Only two calls fit into a request
+        let transport = mock_node_with_gas_limit(reserves, 2);
+        let multicall_manager = MulticallManager::from_transport(Address::repeat_byte(0x11), transport.clone());
+        
+        let pools: Vec<PoolId> = (1..=4).map(|byte| PoolId::Address(Address::repeat_byte(byte))).collect();
+        let results = multicall_manager.batch_get_reserves(&pools, Some(1)).await.unwrap();
+        
+        assert_eq!(results.iter().map(|(pool_id, _)| *pool_id).collect::<Vec<_>>(), pools);
+        assert_eq!(results[0].1, Ok((U256::from(100), U256::from(200))));
+        assert_eq!(results[1].1, Err(ReserveFetchError::Reverted));
+        assert_eq!(results[3].1, Ok((U256::from(500), U256::from(600))));
+        // The full batch and its two halves
+        assert_eq!(transport.request_count("eth_call"), 3);
+        
+        // A single call that still runs out of gas only fails its own pool
+        let transport = mock_node_with_gas_limit(HashMap::new(), 0);
+        let multicall_manager = MulticallManager::from_transport(Address::repeat_byte(0x11), transport.clone());
+        let results = multicall_manager.batch_get_reserves(&pools[..2], Some(1)).await.unwrap();
+        assert!(results.iter().all(|(_, result)| matches!(result, Err(ReserveFetchError::OutOfGas(_)))));
+        
+        // Other errors of the full batch are not retried, whatever their wording
+        for error in [
+            eyre::eyre!("connection reset while out of gas"),
+            RpcError::new(RpcError::SERVER_ERROR, "header not found").into(),
+            RpcError::new(-32005, "limit exceeded").into(),
+        ] {
+            let transport = mock_node(HashMap::new());
+            transport.push_response("eth_call", Err(error));
+            let multicall_manager = MulticallManager::from_transport(Address::repeat_byte(0x11), transport.clone());
+            assert!(multicall_manager.batch_get_reserves(&pools, Some(1)).await.is_err());
+            assert_eq!(transport.request_count("eth_call"), 1);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_balances_isolate_failing_tokens() {
+        let owner = Address::repeat_byte(0xee);
+        let balances = HashMap::from([(Address::repeat_byte(0x01), 1_000u64), (Address::repeat_byte(0x02), 2_000)]);
+        let transport = Arc::new(MockTransport::new());
+        transport.set_handler("eth_call", move |params: &Value| {
+            let data = params[0]["data"].as_str().ok_or_else(|| eyre::eyre!("missing call data"))?;
+            let call = Multicall3::aggregate3Call::abi_decode(&hex::decode(data.trim_start_matches("0x"))?, true)?;
+            let return_data: Vec<Multicall3::Result> = call.calls.iter()
+                .map(|call| match balances.get(&call.target) {
+                    Some(balance) => Multicall3::Result { success: true, returnData: U256::from(*balance).abi_encode().into() },
+                    None => Multicall3::Result { success: false, returnData: Bytes::new() },
+                })
+                .collect();
+            Ok(json!(format!("0x{}", hex::encode(Multicall3::aggregate3Call::abi_encode_returns(&(return_data,))))))
+        });
+        let multicall_manager = MulticallManager::from_transport(Address::repeat_byte(0x11), transport.clone());
+
+        // The token whose balanceOf reverts only loses its own balance
+        let tokens: Vec<Address> = (1..=3).map(Address::repeat_byte).collect();
+        let results = multicall_manager.batch_get_balances(owner, &tokens, Some(1)).await.unwrap();
+        assert_eq!(results, vec![
+            (tokens[0], Some(U256::from(1_000))),
+            (tokens[1], Some(U256::from(2_000))),
+            (tokens[2], None),
+        ]);
+        assert_eq!(transport.request_count("eth_call"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_service_resyncs_after_resubscription() {
+        let pool = Address::repeat_byte(0x01);
+        let transport = mock_node(HashMap::from([(pool, (1_000, 2_000))]));
+        transport.set_handler("eth_getLogs", move |_| Ok(json!([sync_log(pool, 1_500, 1_400)])));
+        let config = DataSyncConfig {
+            reconnect_delay_secs: 0,
+            reserve_sync_mode: ReserveSyncMode::Events,
+            ..DataSyncConfig::default()
+        };
+        let mut service = DataSyncServiceBuilder::new()
+            .with_config(config)
+            .add_pool(mock_pool(0x01))
+            .with_transport(transport.clone())
+            .build()
+            .await
+            .unwrap();
+        
+        let mut snapshots = service.start().await.unwrap();
+        let mut next_reserves = async |number: u64| {
+            wait_for_subscription(&transport).await;
+            transport.notify(new_head(number)).await;
+            let snapshot = timeout(Duration::from_secs(5), snapshots.recv()).await.unwrap().unwrap();
+            snapshot.get_pool_reserves(&PoolId::Address(pool)).unwrap()
+        };
+        
+        assert_eq!(next_reserves(1).await, (U256::from(1_000), U256::from(2_000)));
+        assert_eq!(next_reserves(2).await, (U256::from(1_500), U256::from(1_400)));
+        assert_eq!(transport.request_count("eth_call"), 1);
+        
+        // Events may have been missed while the subscription was down
+        transport.close_subscriptions();
+        assert_eq!(next_reserves(3).await, (U256::from(1_000), U256::from(2_000)));
+        assert_eq!(transport.request_count("eth_call"), 2);
+        
+        service.stop().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_service_emits_reorg_events() {
+        let pool = Address::repeat_byte(0x01);
+        let transport = mock_node(HashMap::from([(pool, (1_000, 2_000))]));
+        transport.set_handler("eth_getLogs", move |_| Ok(json!([sync_log(pool, 1_500, 1_400)])));
+        let config = DataSyncConfig {
+            reconnect_delay_secs: 0,
+            reserve_sync_mode: ReserveSyncMode::Events,
+            ..DataSyncConfig::default()
+        };
+        let mut service = DataSyncServiceBuilder::new()
+            .with_config(config)
+            .add_pool(mock_pool(0x01))
+            .with_transport(transport.clone())
+            .build()
+            .await
+            .unwrap();
+        
+        let mut snapshots = service.start().await.unwrap();
+        let mut reorgs = service.subscribe_reorgs();
+        wait_for_subscription(&transport).await;
+        for number in [1, 2] {
+            transport.notify(new_head(number)).await;
+            timeout(Duration::from_secs(5), snapshots.recv()).await.unwrap().unwrap();
+        }
+        assert_eq!(transport.request_count("eth_call"), 1);
+        
+        // A sibling of block 2 replaces it, the reserves synced from it are queried again
+        let mut fork = new_head(2);
+        fork["hash"] = json!(format!("0x{:064x}", 0x2b));
+        transport.notify(fork).await;
+        let reorg = timeout(Duration::from_secs(5), reorgs.recv()).await.unwrap().unwrap();
+        assert_eq!(reorg.common_ancestor, Some(1));
+        assert_eq!(reorg.orphaned.len(), 1);
+        assert_eq!(reorg.orphaned[0].number, 2);
+        assert_eq!(reorg.first_orphaned_block(), 2);
+        
+        let snapshot = timeout(Duration::from_secs(5), snapshots.recv()).await.unwrap().unwrap();
+        assert_eq!(snapshot.block_number, 2);
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(pool)), Some((U256::from(1_000), U256::from(2_000))));
+        assert_eq!(transport.request_count("eth_call"), 2);
+        assert_eq!(service.metrics().counter(Metric::BlocksTotal, Stage::Reorg), 1);
+        
+        service.stop().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_service_backfills_or_reports_missed_blocks() {
+        let pool = Address::repeat_byte(0x01);
+        for gap_handling in [GapHandling::Report, GapHandling::Backfill] {
+            let transport = mock_node(HashMap::from([(pool, (1_000, 2_000))]));
+            transport.set_handler("eth_getBlockByNumber", |params: &Value| {
+                let number = u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16)?;
+                Ok(new_head(number))
+            });
+            let config = DataSyncConfig {
+                reconnect_delay_secs: 0,
+                snapshot_delivery: SnapshotDelivery::Queue,
+                gap_handling,
+                ..DataSyncConfig::default()
+            };
+            let mut service = DataSyncServiceBuilder::new()
+                .with_config(config)
+                .add_pool(mock_pool(0x01))
+                .with_transport(transport.clone())
+                .build()
+                .await
+                .unwrap();
+            
+            let mut snapshots = service.start().await.unwrap();
+            let mut gaps = service.subscribe_gaps();
+            wait_for_subscription(&transport).await;
+            transport.notify(new_head(1)).await;
+            transport.notify(new_head(4)).await;
+            
+            let mut delivered = Vec::new();
+            let mut backfilled = Vec::new();
+            while delivered.last() != Some(&4) {
+                let snapshot = timeout(Duration::from_secs(5), snapshots.recv()).await.unwrap().unwrap();
+                delivered.push(snapshot.block_number);
+                backfilled.push(snapshot.backfill);
+            }
+            match gap_handling {
+                GapHandling::Report => {
+                    assert_eq!(delivered, [1, 4]);
+                    assert_eq!(backfilled, [false, false]);
+                    assert_eq!(gaps.try_recv().unwrap(), BlockGap { first_missed: 2, last_missed: 3 });
+                    assert_eq!(service.metrics().counter(Metric::BlocksSkippedTotal, Stage::BlockToSnapshot), 2);
+                }
+                GapHandling::Backfill => {
+                    // Each missed block is queried at its own height
+                    assert_eq!(delivered, [1, 2, 3, 4]);
+                    // The head that revealed the gap is the only current one
+                    assert_eq!(backfilled, [false, true, true, false]);
+                    assert!(gaps.try_recv().is_err());
+                    assert_eq!(transport.request_count("eth_getBlockByNumber"), 2);
+                    let heights: Vec<Value> = transport.requests().into_iter()
+                        .filter(|(method, _)| method == "eth_call")
+                        .map(|(_, params)| params[1].clone())
+                        .collect();
+                    assert_eq!(heights, [json!("0x1"), json!("0x2"), json!("0x3"), json!("0x4")]);
+                    assert_eq!(service.metrics().counter(Metric::BlocksTotal, Stage::Backfill), 2);
+                }
+            }
+            
+            service.stop().await.unwrap();
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_service_with_mock_transport() {
+        let transport = mock_node(HashMap::from([(Address::repeat_byte(0x01), (1_000, 2_000))]));
+        let config = DataSyncConfig {
+            reconnect_delay_secs: 0,
+            ..DataSyncConfig::default()
+        };
+        let mut service = DataSyncServiceBuilder::new()
+            .with_config(config)
+            .add_pool(mock_pool(0x01))
+            .with_transport(transport.clone())
+            .build()
+            .await
+            .unwrap();
+        
+        let mut snapshots = service.start().await.unwrap();
+        wait_for_subscription(&transport).await;
+        assert_eq!(transport.notify(new_head(7)).await, 1);
+        
+        let snapshot = timeout(Duration::from_secs(5), snapshots.recv()).await.unwrap().unwrap();
+        assert_eq!(snapshot.block_number, 7);
+        assert_eq!(snapshot.get_pool_reserves(&PoolId::Address(Address::repeat_byte(0x01))), Some((U256::from(1_000), U256::from(2_000))));
+        
+        // A dropped subscription is re-established
+        transport.close_subscriptions();
+        wait_for_subscription(&transport).await;
+        transport.notify(new_head(8)).await;
+        let snapshot = timeout(Duration::from_secs(5), snapshots.recv()).await.unwrap().unwrap();
+        assert_eq!(snapshot.block_number, 8);
+        assert_eq!(transport.request_count("eth_subscribe"), 2);
+        
+        service.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,607 @@
+use async_trait::async_trait;
+use eyre::{Result, eyre};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, info, warn};
+
+/// Notifications buffered per subscription before new ones are dropped
+pub const SUBSCRIPTION_BUFFER_SIZE: usize = 100;
+
+/// JSON-RPC request/response and subscriptions, independent of how they reach the node
+#[async_trait]
+pub trait RpcTransport: Send + Sync + fmt::Debug {
+    /// Send a request and return its `result`, an error response is returned as an error
+    async fn request(&self, method: &str, params: Value) -> Result<Value>;
+
+    /// Open an `eth_subscribe` subscription and receive the `result` of its notifications
+    ///
+    /// The receiver ends when the subscription is lost, e.g. because the connection dropped.
+    async fn subscribe(&self, params: Value) -> Result<mpsc::Receiver<Value>>;
+}
+
+fn request_body(id: u64, method: &str, params: Value) -> Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params,
+    })
+}
+
+/// An error response of the node, as opposed to a failure to reach it
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("RPC error {code}: {message}")]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+/// Messages of a generic server error meaning the node doesn't know the block
+const MISSING_BLOCK_MESSAGES: [&str; 3] = ["header not found", "unknown block", "block not found"];
+
+impl RpcError {
+    /// Generic server error, geth reports most failures of a valid request with it
+    pub const SERVER_ERROR: i64 = -32000;
+    /// Resource not found, the code EIP-1898 recommends for an unknown block
+    pub const RESOURCE_NOT_FOUND: i64 = -32001;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// The `error` object of a response, a malformed one keeps its JSON as the message
+    fn from_response(error: &Value) -> Self {
+        match (error.get("code").and_then(Value::as_i64), error.get("message").and_then(Value::as_str)) {
+            (Some(code), Some(message)) => Self::new(code, message),
+            (code, _) => Self::new(code.unwrap_or_default(), error.to_string()),
+        }
+    }
+
+    /// Whether the node doesn't know the requested block, e.g. because it lags behind the chain
+    pub fn is_missing_block(&self) -> bool {
+        match self.code {
+            Self::RESOURCE_NOT_FOUND => true,
+            Self::SERVER_ERROR => {
+                let message = self.message.to_ascii_lowercase();
+                MISSING_BLOCK_MESSAGES.iter().any(|missing| message.contains(missing))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The `result` of a JSON-RPC response
+fn response_result(mut response: Value) -> Result<Value> {
+    if let Some(error) = response.get("error") {
+        return Err(RpcError::from_response(error).into());
+    }
+
+    response
+        .get_mut("result")
+        .map(Value::take)
+        .ok_or_else(|| eyre!("Missing result in RPC response"))
+}
+
+/// Whether `error` is an error response of the node, e.g. a reverted call, rather than a
+/// failure to reach it
+pub(crate) fn is_node_error(error: &eyre::Report) -> bool {
+    error.downcast_ref::<RpcError>().is_some()
+}
+
+/// Whether `error` is a node that doesn't know the requested block, another node may have it
+pub(crate) fn is_missing_block_error(error: &eyre::Report) -> bool {
+    error.downcast_ref::<RpcError>().is_some_and(RpcError::is_missing_block)
+}
+
+/// JSON-RPC over HTTP, one POST per request, subscriptions are not supported
+#[derive(Debug)]
+pub struct HttpTransport {
+    http_client: reqwest::Client,
+    rpc_url: String,
+    next_id: AtomicU64,
+}
+
+impl HttpTransport {
+    pub fn new(rpc_url: String, request_timeout: Duration) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(request_timeout)
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self { http_client, rpc_url, next_id: AtomicU64::new(1) }
+    }
+
+    pub fn rpc_url(&self) -> &str {
+        &self.rpc_url
+    }
+}
+
+#[async_trait]
+impl RpcTransport for HttpTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let response = self
+            .http_client
+            .post(&self.rpc_url)
+            .header("Content-Type", "application/json")
+            .json(&request_body(id, method, params))
+            .send()
+            .await?;
+
+        response_result(response.json().await?)
+    }
+
+    async fn subscribe(&self, _params: Value) -> Result<mpsc::Receiver<Value>> {
+        Err(eyre!("Subscriptions are not supported over HTTP ({})", self.rpc_url))
+    }
+}
+
+enum PendingResponse {
+    Request(oneshot::Sender<Result<Value>>),
+    Subscribe(oneshot::Sender<Result<mpsc::Receiver<Value>>>),
+}
+
+impl PendingResponse {
+    /// Whether the caller stopped waiting, e.g. because its request timed out
+    fn is_closed(&self) -> bool {
+        match self {
+            PendingResponse::Request(response) => response.is_closed(),
+            PendingResponse::Subscribe(response) => response.is_closed(),
+        }
+    }
+}
+
+struct WsCommand {
+    id: u64,
+    payload: String,
+    pending: PendingResponse,
+}
+
+/// JSON-RPC over a single WebSocket connection shared by requests and subscriptions
+///
+/// The connection is opened on first use and re-opened by the next call after it dropped.
+/// Subscriptions do not survive a dropped connection, their receivers end and the caller
+/// subscribes again.
+#[derive(Debug)]
+pub struct WsTransport {
+    rpc_url: String,
+    /// Used for connecting and for each request
+    timeout: Duration,
+    connection: tokio::sync::Mutex<Option<mpsc::UnboundedSender<WsCommand>>>,
+    next_id: AtomicU64,
+}
+
+impl fmt::Debug for WsCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsCommand").field("id", &self.id).finish_non_exhaustive()
+    }
+}
+
+impl WsTransport {
+    pub fn new(rpc_url: String, timeout: Duration) -> Self {
+        Self { rpc_url, timeout, connection: tokio::sync::Mutex::new(None), next_id: AtomicU64::new(1) }
+    }
+
+    pub fn rpc_url(&self) -> &str {
+        &self.rpc_url
+    }
+
+    async fn connection(&self) -> Result<mpsc::UnboundedSender<WsCommand>> {
+        let mut connection = self.connection.lock().await;
+        if let Some(commands) = connection.as_ref()
+            && !commands.is_closed()
+        {
+            return Ok(commands.clone());
+        }
+
+        info!("Connecting to WebSocket: {}", self.rpc_url);
+        let (ws_stream, _) = timeout(self.timeout, connect_async(self.rpc_url.as_str()))
+            .await
+            .map_err(|_| eyre!("WebSocket connection timeout"))?
+            .map_err(|e| eyre!("WebSocket connection failed: {}", e))?;
+
+        let (commands, command_rx) = mpsc::unbounded_channel();
+        tokio::spawn(Self::run_connection(ws_stream, command_rx, self.rpc_url.clone()));
+        *connection = Some(commands.clone());
+        Ok(commands)
+    }
+
+    async fn send(&self, method: &str, params: Value, pending: PendingResponse) -> Result<()> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let payload = request_body(id, method, params).to_string();
+        self.connection()
+            .await?
+            .send(WsCommand { id, payload, pending })
+            .map_err(|_| eyre!("WebSocket connection closed"))
+    }
+
+    async fn wait<T>(&self, method: &str, response: oneshot::Receiver<Result<T>>) -> Result<T> {
+        timeout(self.timeout, response)
+            .await
+            .map_err(|_| eyre!("Timed out waiting for the response to {}", method))?
+            .map_err(|_| eyre!("WebSocket connection closed before the response to {}", method))?
+    }
+
+    /// Own the socket: write requests, route responses by id and notifications by subscription id
+    async fn run_connection<S>(ws_stream: S, mut commands: mpsc::UnboundedReceiver<WsCommand>, rpc_url: String)
+    where
+        S: futures::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>>
+            + futures::Sink<Message, Error = tokio_tungstenite::tungstenite::Error>
+            + Unpin,
+    {
+        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+        let mut pending: HashMap<u64, PendingResponse> = HashMap::new();
+        let mut subscriptions: HashMap<String, mpsc::Sender<Value>> = HashMap::new();
+
+        loop {
+            tokio::select! {
+                command = commands.recv() => {
+                    let Some(command) = command else { break };
+                    if let Err(e) = ws_sender.send(Message::Text(command.payload.into())).await {
+                        warn!("Failed to send WebSocket request: {}", e);
+                        break;
+                    }
+                    Self::track_pending(&mut pending, command.id, command.pending);
+                }
+                ws_msg = ws_receiver.next() => {
+                    match ws_msg {
+                        Some(Ok(Message::Text(text))) => {
+                            let unsubscribed = Self::handle_message(text.as_str(), &mut pending, &mut subscriptions);
+                            for subscription_id in unsubscribed {
+                                let unsubscribe = request_body(0, "eth_unsubscribe", serde_json::json!([subscription_id]));
+                                let _ = ws_sender.send(Message::Text(unsubscribe.to_string().into())).await;
+                            }
+                        }
+                        Some(Ok(Message::Ping(data))) => {
+                            if let Err(e) = ws_sender.send(Message::Pong(data)).await {
+                                warn!("Failed to send pong: {}", e);
+                                break;
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) => {
+                            info!("WebSocket closed by server");
+                            break;
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => {
+                            warn!("WebSocket error: {}", e);
+                            break;
+                        }
+                        None => {
+                            info!("WebSocket stream ended");
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Dropping the pending responses and subscription senders fails the waiting callers
+        // and ends the subscriptions
+        debug!("WebSocket connection to {} closed with {} pending requests", rpc_url, pending.len());
+    }
+
+    /// Wait for the response to `id`, dropping the requests their callers gave up on
+    ///
+    /// A node may never answer a request that timed out, without the sweep its entry would
+    /// stay until the connection closes.
+    fn track_pending(pending: &mut HashMap<u64, PendingResponse>, id: u64, response: PendingResponse) {
+        pending.retain(|_, response| !response.is_closed());
+        pending.insert(id, response);
+    }
+
+    /// Route one message, returns the subscriptions whose receiver is gone
+    fn handle_message(
+        text: &str,
+        pending: &mut HashMap<u64, PendingResponse>,
+        subscriptions: &mut HashMap<String, mpsc::Sender<Value>>,
+    ) -> Vec<String> {
+        let mut message: Value = match serde_json::from_str(text) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to parse WebSocket message: {}", e);
+                return Vec::new();
+            }
+        };
+
+        if let Some(id) = message.get("id").and_then(Value::as_u64) {
+            match pending.remove(&id) {
+                Some(PendingResponse::Request(response)) => {
+                    let _ = response.send(response_result(message));
+                }
+                Some(PendingResponse::Subscribe(response)) => {
+                    let subscription_id = response_result(message).and_then(|result| {
+                        result.as_str().map(str::to_string).ok_or_else(|| eyre!("Invalid subscription ID format"))
+                    });
+                    let _ = response.send(subscription_id.map(|subscription_id| {
+                        let (notifications, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER_SIZE);
+                        subscriptions.insert(subscription_id, notifications);
+                        receiver
+                    }));
+                }
+                None => {}
+            }
+            return Vec::new();
+        }
+
+        let Some(params) = message.get_mut("params") else { return Vec::new() };
+        let Some(subscription_id) = params.get("subscription").and_then(Value::as_str).map(str::to_string) else {
+            return Vec::new();
+        };
+        let Some(notifications) = subscriptions.get(&subscription_id) else { return Vec::new() };
+
+        match notifications.try_send(params.get_mut("result").map(Value::take).unwrap_or_default()) {
+            Ok(()) => Vec::new(),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                warn!("Subscription {} is full, dropping notification", subscription_id);
+                Vec::new()
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                subscriptions.remove(&subscription_id);
+                vec![subscription_id]
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for WsTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let (response, response_rx) = oneshot::channel();
+        self.send(method, params, PendingResponse::Request(response)).await?;
+        self.wait(method, response_rx).await
+    }
+
+    async fn subscribe(&self, params: Value) -> Result<mpsc::Receiver<Value>> {
+        let (response, response_rx) = oneshot::channel();
+        self.send("eth_subscribe", params, PendingResponse::Subscribe(response)).await?;
+        self.wait("eth_subscribe", response_rx).await
+    }
+}
+
+type MockHandler = Arc<dyn Fn(&Value) -> Result<Value> + Send + Sync>;
+
+#[derive(Default)]
+struct MockState {
+    latency: Duration,
+    in_flight: usize,
+    max_in_flight: usize,
+    responses: HashMap<String, VecDeque<Result<Value>>>,
+    handlers: HashMap<String, MockHandler>,
+    requests: Vec<(String, Value)>,
+    subscribers: Vec<mpsc::Sender<Value>>,
+}
+
+/// Scripted in-memory transport for offline tests
+///
+/// A request is answered by the next response queued for its method, then by the handler of
+/// its method, otherwise it fails. Notifications are pushed to the open subscriptions by the
+/// test. `eth_subscribe` can be failed by queueing an error response for it.
+#[derive(Default)]
+pub struct MockTransport {
+    state: Mutex<MockState>,
+}
+
+impl fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("MockTransport")
+            .field("requests", &state.requests.len())
+            .field("subscribers", &state.subscribers.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the response to the next `method` request
+    pub fn push_response(&self, method: &str, response: Result<Value>) {
+        self.state.lock().unwrap().responses.entry(method.to_string()).or_default().push_back(response);
+    }
+
+    /// Answer every `method` request without a queued response, the handler gets the params
+    pub fn set_handler(&self, method: &str, handler: impl Fn(&Value) -> Result<Value> + Send + Sync + 'static) {
+        self.state.lock().unwrap().handlers.insert(method.to_string(), Arc::new(handler));
+    }
+
+    /// Delay every request by `latency` before it is answered
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Most requests that were waiting for their answer at the same time
+    pub fn max_in_flight(&self) -> usize {
+        self.state.lock().unwrap().max_in_flight
+    }
+
+    /// Every request and subscription so far, as `(method, params)`
+    pub fn requests(&self) -> Vec<(String, Value)> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn request_count(&self, method: &str) -> usize {
+        self.state.lock().unwrap().requests.iter().filter(|(name, _)| name == method).count()
+    }
+
+    /// Subscriptions whose receiver is still open
+    pub fn subscription_count(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.subscribers.retain(|subscriber| !subscriber.is_closed());
+        state.subscribers.len()
+    }
+
+    /// Push a notification to every open subscription, returns how many received it
+    pub async fn notify(&self, notification: Value) -> usize {
+        let subscribers = self.state.lock().unwrap().subscribers.clone();
+        let mut delivered = 0;
+        for subscriber in subscribers {
+            if subscriber.send(notification.clone()).await.is_ok() {
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    /// End every open subscription, as a dropped connection would
+    pub fn close_subscriptions(&self) {
+        self.state.lock().unwrap().subscribers.clear();
+    }
+
+    fn record(&self, method: &str, params: &Value) -> Option<Result<Value>> {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push((method.to_string(), params.clone()));
+        state.responses.get_mut(method).and_then(VecDeque::pop_front)
+    }
+}
+
+#[async_trait]
+impl RpcTransport for MockTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let latency = {
+            let mut state = self.state.lock().unwrap();
+            state.in_flight += 1;
+            state.max_in_flight = state.max_in_flight.max(state.in_flight);
+            state.latency
+        };
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        self.state.lock().unwrap().in_flight -= 1;
+
+        if let Some(response) = self.record(method, &params) {
+            return response;
+        }
+
+        let handler = self.state.lock().unwrap().handlers.get(method).cloned();
+        match handler {
+            Some(handler) => handler(&params),
+            None => Err(eyre!("No mock response for {}", method)),
+        }
+    }
+
+    async fn subscribe(&self, params: Value) -> Result<mpsc::Receiver<Value>> {
+        if let Some(Err(e)) = self.record("eth_subscribe", &params) {
+            return Err(e);
+        }
+
+        let (notifications, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER_SIZE);
+        self.state.lock().unwrap().subscribers.push(notifications);
+        Ok(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_mock_transport_script() {
+        let transport = MockTransport::new();
+        transport.set_handler("eth_blockNumber", |_| Ok(json!("0x10")));
+        transport.push_response("eth_blockNumber", Err(eyre!("node is syncing")));
+
+        assert!(transport.request("eth_blockNumber", json!([])).await.is_err());
+        assert_eq!(transport.request("eth_blockNumber", json!([])).await.unwrap(), json!("0x10"));
+        assert!(transport.request("eth_chainId", json!([])).await.is_err());
+        assert_eq!(transport.request_count("eth_blockNumber"), 2);
+
+        let mut heads = transport.subscribe(json!(["newHeads"])).await.unwrap();
+        assert_eq!(transport.notify(json!({ "number": "0x11" })).await, 1);
+        assert_eq!(heads.recv().await, Some(json!({ "number": "0x11" })));
+        transport.close_subscriptions();
+        assert!(heads.recv().await.is_none());
+        assert_eq!(transport.subscription_count(), 0);
+    }
+
+    #[test]
+    fn test_rpc_error_classification() {
+        let response = |error: Value| response_result(json!({ "jsonrpc": "2.0", "id": 1, "error": error })).unwrap_err();
+
+        let lagging = response(json!({ "code": -32000, "message": "header not found" }));
+        assert!(is_node_error(&lagging) && is_missing_block_error(&lagging));
+        let unknown = response(json!({ "code": -32001, "message": "resource not found" }));
+        assert!(is_missing_block_error(&unknown));
+
+        let reverted = response(json!({ "code": 3, "message": "execution reverted: header not found", "data": "0x" }));
+        assert!(is_node_error(&reverted) && !is_missing_block_error(&reverted));
+        let malformed = response(json!("boom"));
+        assert_eq!(malformed.downcast_ref::<RpcError>(), Some(&RpcError::new(0, "\"boom\"")));
+
+        // Failing to reach the node is not a node error, whatever its wording
+        assert!(!is_node_error(&eyre!("RPC error: header not found")));
+    }
+
+    #[test]
+    fn test_ws_transport_drops_abandoned_requests() {
+        let mut pending = HashMap::new();
+        let (response, timed_out) = oneshot::channel();
+        WsTransport::track_pending(&mut pending, 1, PendingResponse::Request(response));
+        let (response, _waiting) = oneshot::channel();
+        WsTransport::track_pending(&mut pending, 2, PendingResponse::Request(response));
+        assert_eq!(pending.len(), 2);
+
+        drop(timed_out);
+        let (response, _waiting) = oneshot::channel();
+        WsTransport::track_pending(&mut pending, 3, PendingResponse::Subscribe(response));
+        let mut ids: Vec<u64> = pending.keys().copied().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_ws_transport_routes_responses_and_notifications() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // A node answering eth_blockNumber and pushing two heads after eth_subscribe
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                let request: Value = serde_json::from_str(text.as_str()).unwrap();
+                let id = request["id"].clone();
+                match request["method"].as_str().unwrap() {
+                    "eth_subscribe" => {
+                        ws.send(Message::Text(json!({ "jsonrpc": "2.0", "id": id, "result": "0xab" }).to_string().into())).await.unwrap();
+                        for number in ["0x1", "0x2"] {
+                            let notification = json!({
+                                "jsonrpc": "2.0",
+                                "method": "eth_subscription",
+                                "params": { "subscription": "0xab", "result": { "number": number } },
+                            });
+                            ws.send(Message::Text(notification.to_string().into())).await.unwrap();
+                        }
+                    }
+                    "eth_blockNumber" => {
+                        ws.send(Message::Text(json!({ "jsonrpc": "2.0", "id": id, "result": "0x2" }).to_string().into())).await.unwrap();
+                    }
+                    _ => {
+                        let error = json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": "method not found" } });
+                        ws.send(Message::Text(error.to_string().into())).await.unwrap();
+                    }
+                }
+            }
+        });
+
+        let transport = WsTransport::new(format!("ws://{}", addr), Duration::from_secs(5));
+        let mut heads = transport.subscribe(json!(["newHeads"])).await.unwrap();
+        assert_eq!(transport.request("eth_blockNumber", json!([])).await.unwrap(), json!("0x2"));
+        let error = transport.request("eth_foo", json!([])).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<RpcError>(), Some(&RpcError::new(-32601, "method not found")));
+        assert!(is_node_error(&error) && !is_missing_block_error(&error));
+        assert_eq!(heads.recv().await, Some(json!({ "number": "0x1" })));
+        assert_eq!(heads.recv().await, Some(json!({ "number": "0x2" })));
+    }
+}
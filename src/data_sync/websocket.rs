@@ -1,13 +1,13 @@
+use crate::data_sync::transport::{RpcTransport, WsTransport};
 use eyre::{Result, eyre};
-use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
-use tokio::time::{timeout, sleep};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::time::sleep;
 use tracing::{info, warn, error, debug};
-use url::Url;
 
 /// Block header information from newHeads subscription
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +44,9 @@ pub struct WebSocketManager {
     connection_timeout: Duration,
     max_reconnect_attempts: u32,
     reconnect_delay: Duration,
+    transport: Arc<dyn RpcTransport>,
+    // Successful newHeads subscriptions so far
+    subscriptions: Arc<AtomicU64>,
 }
 
 impl WebSocketManager {
@@ -53,22 +56,45 @@ impl WebSocketManager {
         max_reconnect_attempts: u32,
         reconnect_delay: Duration,
     ) -> Self {
+        let transport = Arc::new(WsTransport::new(rpc_url.clone(), connection_timeout));
         Self {
             rpc_url,
             connection_timeout,
             max_reconnect_attempts,
             reconnect_delay,
+            transport,
+            subscriptions: Arc::new(AtomicU64::new(0)),
         }
     }
     
+    /// Subscribe through another transport instead of a WebSocket connection to `rpc_url`
+    pub fn with_transport(mut self, transport: Arc<dyn RpcTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+    
+    pub fn rpc_url(&self) -> &str {
+        &self.rpc_url
+    }
+    
+    pub fn connection_timeout(&self) -> Duration {
+        self.connection_timeout
+    }
+    
+    /// Number of successful newHeads subscriptions, grows every time the subscription is
+    /// re-established after a connection loss
+    pub fn subscription_epoch(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.subscriptions)
+    }
+    
     /// Start subscribing to newHeads events
-    /// Returns a receiver for block headers and a shutdown sender
-    pub async fn subscribe_new_heads(&self) -> Result<(mpsc::Receiver<BlockHeader>, mpsc::Sender<()>)> {
+    /// Returns a receiver for block headers, each with the time it arrived, and a shutdown sender
+    pub async fn subscribe_new_heads(&self) -> Result<(mpsc::Receiver<(BlockHeader, Instant)>, mpsc::Sender<()>)> {
         let (block_tx, block_rx) = mpsc::channel(100);
         let (shutdown_tx, mut shutdown_rx) = mpsc::channel(1);
         
-        let rpc_url = self.rpc_url.clone();
-        let connection_timeout = self.connection_timeout;
+        let transport = Arc::clone(&self.transport);
+        let subscriptions = Arc::clone(&self.subscriptions);
         let max_reconnect_attempts = self.max_reconnect_attempts;
         let reconnect_delay = self.reconnect_delay;
         
@@ -77,9 +103,9 @@ impl WebSocketManager {
             let mut reconnect_count = 0;
             
             loop {
-                match Self::connect_and_subscribe(
-                    &rpc_url,
-                    connection_timeout,
+                match Self::forward_new_heads(
+                    transport.as_ref(),
+                    &subscriptions,
                     &block_tx,
                     &mut shutdown_rx,
                 ).await {
@@ -106,131 +132,58 @@ impl WebSocketManager {
         Ok((block_rx, shutdown_tx))
     }
     
-    /// Connect to WebSocket and handle subscription
-    async fn connect_and_subscribe(
-        rpc_url: &str,
-        connection_timeout: Duration,
-        block_tx: &mpsc::Sender<BlockHeader>,
+    /// Subscribe to newHeads and forward the headers until shutdown
+    ///
+    /// A lost subscription is returned as an error so that the caller subscribes again.
+    async fn forward_new_heads(
+        transport: &dyn RpcTransport,
+        subscriptions: &AtomicU64,
+        block_tx: &mpsc::Sender<(BlockHeader, Instant)>,
         shutdown_rx: &mut mpsc::Receiver<()>,
     ) -> Result<()> {
-        // Parse and connect to WebSocket URL
-        let url = Url::parse(rpc_url)?;
-        info!("Connecting to WebSocket: {}", url);
-        
-        let (ws_stream, _) = timeout(connection_timeout, connect_async(url.as_str())).await
-            .map_err(|_| eyre!("WebSocket connection timeout"))?
-            .map_err(|e| eyre!("WebSocket connection failed: {}", e))?;
-        
-        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-        
-        // Subscribe to newHeads
-        let subscribe_request = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "eth_subscribe",
-            "params": ["newHeads"]
-        });
-        
-        ws_sender.send(Message::Text(subscribe_request.to_string().into())).await?;
-        info!("Sent newHeads subscription request");
-        
-        // Wait for subscription confirmation
-        let subscription_id = match ws_receiver.next().await {
-            Some(Ok(Message::Text(text))) => {
-                let response: Value = serde_json::from_str(text.as_str())?;
-                if let Some(result) = response.get("result") {
-                    info!("Subscription confirmed with ID: {}", result);
-                    result.as_str()
-                        .ok_or_else(|| eyre!("Invalid subscription ID format"))?
-                        .to_string()
-                } else if let Some(error) = response.get("error") {
-                    return Err(eyre!("Subscription error: {}", error));
-                } else {
-                    return Err(eyre!("Unexpected subscription response: {}", text));
-                }
-            }
-            Some(Ok(msg)) => {
-                return Err(eyre!("Unexpected message type during subscription: {:?}", msg));
-            }
-            Some(Err(e)) => {
-                return Err(eyre!("WebSocket error during subscription: {}", e));
-            }
-            None => {
-                return Err(eyre!("WebSocket closed during subscription"));
-            }
-        };
+        let mut notifications = transport.subscribe(serde_json::json!(["newHeads"])).await?;
+        subscriptions.fetch_add(1, Ordering::AcqRel);
+        info!("Successfully subscribed to newHeads");
         
-        info!("Successfully subscribed to newHeads with ID: {}", subscription_id);
-        
-        // Main event loop
         loop {
             tokio::select! {
-                // Handle incoming WebSocket messages
-                ws_msg = ws_receiver.next() => {
-                    match ws_msg {
-                        Some(Ok(Message::Text(text))) => {
-                            if let Err(e) = Self::handle_message(text.as_str(), block_tx).await {
-                                warn!("Failed to handle WebSocket message: {}", e);
-                            }
-                        }
-                        Some(Ok(Message::Close(_))) => {
-                            info!("WebSocket closed by server");
-                            break;
-                        }
-                        Some(Ok(Message::Ping(data))) => {
-                            // Respond to ping with pong
-                            if let Err(e) = ws_sender.send(Message::Pong(data)).await {
-                                error!("Failed to send pong: {}", e);
-                                break;
-                            }
-                        }
-                        Some(Ok(_)) => {
-                            // Ignore other message types
-                        }
-                        Some(Err(e)) => {
-                            error!("WebSocket error: {}", e);
-                            break;
-                        }
-                        None => {
-                            info!("WebSocket stream ended");
-                            break;
-                        }
+                notification = notifications.recv() => {
+                    let Some(notification) = notification else {
+                        return Err(eyre!("newHeads subscription ended"));
+                    };
+                    if let Err(e) = Self::handle_notification(notification, block_tx) {
+                        warn!("Failed to handle newHeads notification: {}", e);
+                    }
+                    if block_tx.is_closed() {
+                        info!("Block header receiver dropped");
+                        return Ok(());
                     }
                 }
                 
                 // Handle shutdown signal
                 _ = shutdown_rx.recv() => {
                     info!("Received shutdown signal");
-                    break;
+                    return Ok(());
                 }
             }
         }
-        
-        Ok(())
     }
     
-    /// Handle incoming WebSocket message
-    async fn handle_message(text: &str, block_tx: &mpsc::Sender<BlockHeader>) -> Result<()> {
-        let message: Value = serde_json::from_str(text)?;
+    /// Handle a newHeads notification
+    fn handle_notification(notification: Value, block_tx: &mpsc::Sender<(BlockHeader, Instant)>) -> Result<()> {
+        let received_at = Instant::now();
+        let block_header: BlockHeader = serde_json::from_value(notification)?;
         
-        // Check if this is a subscription notification
-        if let Some(params) = message.get("params") {
-            if let Some(result) = params.get("result") {
-                // Parse block header
-                let block_header: BlockHeader = serde_json::from_value(result.clone())?;
-                
-                debug!("Received new block: {}", block_header.number);
-                
-                // Send to channel (non-blocking)
-                if let Err(e) = block_tx.try_send(block_header) {
-                    match e {
-                        mpsc::error::TrySendError::Full(_) => {
-                            warn!("Block header channel is full, dropping block");
-                        }
-                        mpsc::error::TrySendError::Closed(_) => {
-                            return Err(eyre!("Block header channel is closed"));
-                        }
-                    }
+        debug!("Received new block: {}", block_header.number);
+        
+        // Send to channel (non-blocking), the time spent queued counts towards the block's latency
+        if let Err(e) = block_tx.try_send((block_header, received_at)) {
+            match e {
+                mpsc::error::TrySendError::Full(_) => {
+                    warn!("Block header channel is full, dropping block");
+                }
+                mpsc::error::TrySendError::Closed(_) => {
+                    return Err(eyre!("Block header channel is closed"));
                 }
             }
         }
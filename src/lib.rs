@@ -12,12 +12,14 @@ pub mod benchmarks;
 // Re-export key components from each layer
 pub use data_sync::{Market, MarketWithoutLock, MarketConfigSection};
 pub use logic::{
-    ArbitrageEngine, ArbitrageEngineBuilder, Pathfinder, ProfitCalculator, 
-    ArbitrageOpportunity, MarketSnapshot, ProfitCalculationResult,
-    SwapPath, SwapPathHash, SwapPathsContainer, add_swap_path, remove_pool,
+    ArbitrageEngine, ArbitrageEngineBuilder, ExecutionPlan, OpportunitySelector, SelectionMode, 
+    DisappearanceReason, OpportunityEvent, OpportunityTracker, TrackedOpportunity, 
+    ArbitrageStrategy, CrossDexSpreadStrategy, CyclicStrategy, PoolChange, StrategyEvaluation, CYCLIC_STRATEGY_NAME, Pathfinder, ProfitCalculator, CurveSampling, CurveScale, ProfitCurve, ProfitCurvePoint,
+    ArbitrageOpportunity, CalculationFailure, CalculationFailureStats, ExactOutQuote, HopTrace, InventoryView, MarketSnapshot, ProfitCalculationResult,
+    CompiledPath, CompiledPathSet, SwapPath, SwapPathHash, SwapPathsContainer, add_swap_path, remove_pool,
     find_all_paths_spfa, SPFAPathBuilder, TokenGraph,
-    AbiSwapEncoder, CalculationError, Pool, PoolClass, PoolProtocol, PoolWrapper, 
+    AbiSwapEncoder, CalculationError, CalculationErrorKind, Pool, PoolClass, PoolProtocol, PoolWrapper, 
     PreswapRequirement, get_protocol_by_factory, PoolId, MockPool
 };
 pub use execution::{TransactionExecutor};
-pub use utils::{Token, TokenWrapper, StateCache, CachedStateProvider, CacheStats, CacheManager};
+pub use utils::{Token, TokenWrapper, StateCache, CachedStateProvider, CacheStats, CacheManager, MetricsRegistry};
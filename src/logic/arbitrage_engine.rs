@@ -1,11 +1,20 @@
-use crate::logic::graph::{SwapPath, TokenGraph};
-use super::pathfinder::Pathfinder;
-use super::profit_calculator::ProfitCalculator;
-use super::types::{ArbitrageConfig, ArbitrageOpportunity, MarketSnapshot};
+use crate::logic::graph::{CompiledPathSet, SwapPath, TokenGraph};
+use super::opportunity_selector::{ExecutionPlan, OpportunitySelector, SelectionMode};
+use super::opportunity_tracker::{DisappearanceReason, OpportunityEvent, OpportunityTracker};
+use super::snapshot_channel::SnapshotReceiver;
+use super::strategy::{ArbitrageStrategy, CyclicStrategy, PoolChange};
+use super::types::{
+    ArbitrageConfig, ArbitrageOpportunity, CalculationCutoff, CalculationFailureStats, InventoryView, MarketSnapshot, ProfitCalculationBatch,
+    ReorgEvent,
+};
+use crate::utils::{Metric, MetricsRegistry, Stage};
 use alloy_primitives::U256;
 use eyre::{eyre, Result};
-use tokio::sync::mpsc;
-use tracing::{error, info, warn};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, error, info, warn};
 
 /// ArbitrageEngine is the core component of the Logic Layer
 /// 
@@ -14,38 +23,87 @@ use tracing::{error, info, warn};
 /// 2. Parallel Profit Calculation (triggered by new market data)
 /// 
 /// This engine follows the "precomputed paths + real-time calculation" approach
-/// for maximum performance in the real-time arbitrage detection system.
+/// for maximum performance in the real-time arbitrage detection system. The detection itself
+/// is done by the registered strategies, by default the [CyclicStrategy].
 pub struct ArbitrageEngine {
     /// Configuration for the arbitrage engine
     config: ArbitrageConfig,
-    /// Pre-computed arbitrage paths (static topology)
-    precomputed_paths: Vec<SwapPath>,
-    /// Profit calculator component (the "hot path")
-    profit_calculator: ProfitCalculator,
+    /// Inventory handed to every registered strategy
+    inventory: Option<InventoryView>,
     /// Channel receiver for market data updates
-    market_data_receiver: Option<mpsc::Receiver<MarketSnapshot>>,
+    market_data_receiver: Option<SnapshotReceiver>,
+    /// Highest block number available to the engine, an older snapshot is no longer evaluated
+    latest_block_signal: Option<Arc<AtomicU64>>,
+    /// Channel receiver for configuration changes applied between snapshots
+    config_receiver: Option<mpsc::Receiver<ArbitrageConfig>>,
+    /// Chain reorganizations reported by the data layer
+    reorg_receiver: Option<broadcast::Receiver<ReorgEvent>>,
+    /// Expirations caused by reorgs, delivered with the events of the next snapshot
+    reorg_events: Vec<OpportunityEvent>,
+    /// Calculation failures of the last processed snapshot
+    last_failure_stats: RwLock<Option<CalculationFailureStats>>,
+    /// Opportunities followed across snapshots
+    opportunity_tracker: Mutex<OpportunityTracker>,
+    /// Detectors run on every snapshot, in registration order
+    strategies: Vec<Box<dyn ArbitrageStrategy>>,
+    /// Per-stage timings and counts of the processed snapshots
+    metrics: Arc<MetricsRegistry>,
     /// Engine state
     is_initialized: bool,
 }
 
 impl ArbitrageEngine {
-    /// Create a new ArbitrageEngine with the given configuration
+    /// Create a new ArbitrageEngine with the given configuration, detecting the precomputed cycles
     pub fn new(config: ArbitrageConfig) -> Self {
-        let profit_calculator = ProfitCalculator::new(config.clone());
+        let mut engine = Self::without_strategies(config);
+        engine.strategies.push(Box::new(CyclicStrategy::new(engine.config.clone())));
+        engine
+    }
+
+    /// Create an engine running exactly `strategies`, in this order
+    ///
+    /// Leaving out the [CyclicStrategy] turns the precomputed cycles off.
+    pub fn with_strategies(config: ArbitrageConfig, strategies: Vec<Box<dyn ArbitrageStrategy>>) -> Result<Self> {
+        let mut engine = Self::without_strategies(config);
+        for strategy in strategies {
+            engine.register_strategy(strategy)?;
+        }
+        Ok(engine)
+    }
+
+    fn without_strategies(config: ArbitrageConfig) -> Self {
+        let opportunity_tracker = Mutex::new(OpportunityTracker::new(config.max_tracked_opportunities));
 
         Self {
             config,
-            precomputed_paths: Vec::new(),
-            profit_calculator,
+            inventory: None,
             market_data_receiver: None,
+            latest_block_signal: None,
+            config_receiver: None,
+            reorg_receiver: None,
+            reorg_events: Vec::new(),
+            last_failure_stats: RwLock::new(None),
+            opportunity_tracker,
+            strategies: Vec::new(),
+            metrics: Arc::new(MetricsRegistry::new()),
             is_initialized: false,
         }
     }
 
-    /// Initialize the engine by pre-computing all arbitrage paths
+    /// Record into a shared metrics registry, e.g. the one of the data layer
+    pub fn set_metrics(&mut self, metrics: Arc<MetricsRegistry>) {
+        self.metrics = metrics;
+    }
+
+    /// Metrics registry the engine records into
+    pub fn metrics(&self) -> Arc<MetricsRegistry> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Initialize the engine by initializing its strategies
     /// 
-    /// This is the "Path Discovery" phase that runs once during system startup.
-    /// It analyzes the token graph and finds all possible 3-hop and 4-hop cycles
+    /// This is the "Path Discovery" phase that runs once during system startup. The
+    /// [CyclicStrategy] analyzes the token graph and finds all possible 3-hop and 4-hop cycles
     /// from WMNT back to WMNT.
     pub fn initialize(&mut self, token_graph: &TokenGraph) -> Result<()> {
         info!("初始化套利引擎...");
@@ -54,18 +112,13 @@ impl ArbitrageEngine {
             warn!("套利引擎已经初始化，跳过重复初始化");
             return Ok(());
         }
-
-        // Create pathfinder and discover all arbitrage paths
-        let pathfinder = Pathfinder::new(self.config.max_hops, self.config.max_precomputed_paths);
-        
-        info!("开始预计算套利路径...");
-        let paths = pathfinder.precompute_arbitrage_paths(token_graph)?;
-        
-        if paths.is_empty() {
-            return Err(eyre!("未找到任何套利路径，请检查代币图配置"));
+        if self.strategies.is_empty() {
+            return Err(eyre!("未注册任何套利策略"));
         }
 
-        self.precomputed_paths = paths;
+        for strategy in self.strategies.iter_mut() {
+            strategy.init(token_graph).map_err(|e| eyre!("策略 {} 初始化失败: {}", strategy.name(), e))?;
+        }
         self.is_initialized = true;
 
         // Convert MNT Wei to human readable format for logging
@@ -73,7 +126,7 @@ impl ArbitrageEngine {
         
         info!(
             "套利引擎初始化完成！预计算路径数量: {}, 配置: max_hops={}, min_profit={:.4} MNT",
-            self.precomputed_paths.len(),
+            self.get_compiled_paths().len(),
             self.config.max_hops,
             min_profit_mnt
         );
@@ -81,11 +134,47 @@ impl ArbitrageEngine {
         Ok(())
     }
 
+    /// Register a strategy after the registered ones, its opportunities are merged with theirs
+    ///
+    /// Strategies are initialized together with the engine and have to be registered before
+    /// [ArbitrageEngine::initialize].
+    pub fn register_strategy(&mut self, mut strategy: Box<dyn ArbitrageStrategy>) -> Result<()> {
+        if self.is_initialized {
+            return Err(eyre!("引擎已初始化，请在 initialize() 之前注册策略"));
+        }
+        if self.strategies.iter().any(|registered| registered.name() == strategy.name()) {
+            return Err(eyre!("策略名称重复: {}", strategy.name()));
+        }
+
+        info!("注册套利策略: {}", strategy.name());
+        if self.inventory.is_some() {
+            strategy.set_inventory(self.inventory.clone());
+        }
+        self.strategies.push(strategy);
+        Ok(())
+    }
+
+    /// Names of all strategies the engine runs, in the order they run
+    pub fn strategy_names(&self) -> Vec<String> {
+        self.strategies.iter().map(|strategy| strategy.name().to_string()).collect()
+    }
+
+    /// Forward a pool change to the registered strategies, e.g. to find the cycles again
+    pub fn on_pool_change(&mut self, token_graph: &TokenGraph, change: PoolChange) -> Result<()> {
+        for strategy in self.strategies.iter_mut() {
+            strategy.on_pool_change(token_graph, change).map_err(|e| eyre!("策略 {} 处理池子变更失败: {}", strategy.name(), e))?;
+        }
+        Ok(())
+    }
+
     /// Set up the market data channel receiver
     /// 
-    /// This allows the engine to receive real-time market data updates from the Data Layer
-    pub fn set_market_data_receiver(&mut self, receiver: mpsc::Receiver<MarketSnapshot>) {
+    /// This allows the engine to receive real-time market data updates from the Data Layer,
+    /// either the receiver returned by `DataSyncService::start` or a plain queue
+    pub fn set_market_data_receiver(&mut self, receiver: impl Into<SnapshotReceiver>) {
         info!("设置市场数据接收器");
+        let receiver = receiver.into();
+        self.latest_block_signal = Some(receiver.stats().latest_block_signal());
         self.market_data_receiver = Some(receiver);
     }
 
@@ -97,31 +186,143 @@ impl ArbitrageEngine {
         &self,
         market_snapshot: &MarketSnapshot,
     ) -> Result<Vec<ArbitrageOpportunity>> {
+        let (opportunities, _) = self.find_opportunities(market_snapshot)?;
+        Ok(opportunities)
+    }
+
+    /// Process a market snapshot and report how the opportunities changed since the previous one
+    ///
+    /// Instead of every opportunity of the block, only those that appeared, changed or
+    /// disappeared are returned, each with its identity and history across blocks.
+    pub fn process_market_snapshot_events(&self, market_snapshot: &MarketSnapshot) -> Result<Vec<OpportunityEvent>> {
+        let (opportunities, batch) = self.find_opportunities(market_snapshot)?;
+
+        let mut tracker = self.opportunity_tracker.lock().map_err(|_| eyre!("机会跟踪器锁已损坏"))?;
+        if batch.is_truncated() {
+            Ok(tracker.update_truncated(market_snapshot.block_number, opportunities, &batch.results))
+        } else {
+            Ok(tracker.update(market_snapshot.block_number, opportunities, &batch.results))
+        }
+    }
+
+    /// Number of opportunities currently followed across snapshots
+    pub fn tracked_opportunities_count(&self) -> usize {
+        self.opportunity_tracker.lock().map(|tracker| tracker.len()).unwrap_or_default()
+    }
+
+    /// Opportunities of all strategies, with the profit results they were selected from
+    ///
+    /// A truncated evaluation returns the opportunities found so far, flagged as truncated, and
+    /// skips the remaining strategies since the snapshot is already outdated. A failing
+    /// strategy is logged and skipped so that it cannot hide the others' opportunities.
+    fn find_opportunities(&self, market_snapshot: &MarketSnapshot) -> Result<(Vec<ArbitrageOpportunity>, ProfitCalculationBatch)> {
         if !self.is_initialized {
             return Err(eyre!("引擎未初始化，请先调用 initialize()"));
         }
 
-        // Calculate profits for all pre-computed paths in parallel
-        let profit_results = self.profit_calculator.calculate_profits_parallel(
-            &self.precomputed_paths,
-            market_snapshot,
-        );
+        let cutoff = self.calculation_cutoff();
+        let mut opportunities = Vec::new();
+        let mut batch = ProfitCalculationBatch::default();
+        // Strategies reporting profit results count as profit calculation, the others as strategies
+        let mut calculation = StageTally::default();
+        let mut strategies = StageTally::default();
 
-        // Filter and convert successful calculations to opportunities
-        let opportunities: Vec<ArbitrageOpportunity> = profit_results
-            .into_iter()
-            .filter_map(|result| {
-                if result.calculation_successful 
-                    && result.net_profit_mnt_wei > self.config.min_profit_threshold_mnt_wei 
-                {
-                    result.to_opportunity()
-                } else {
-                    None
+        for strategy in self.strategies.iter() {
+            let start = Instant::now();
+            let evaluation = strategy.evaluate(market_snapshot, &cutoff);
+            let elapsed = start.elapsed();
+            let evaluation = match evaluation {
+                Ok(evaluation) => evaluation,
+                Err(e) => {
+                    warn!("策略 {} 处理区块 {} 失败: {}", strategy.name(), market_snapshot.block_number, e);
+                    self.metrics.increment(Metric::StageFailuresTotal, Stage::Strategies, 1);
+                    strategies.add(elapsed, 0, 0);
+                    continue;
                 }
-            })
-            .collect();
+            };
 
-        Ok(opportunities)
+            let found = evaluation.opportunities.len();
+            opportunities.extend(evaluation.opportunities.into_iter().map(|opportunity| {
+                if opportunity.strategy.is_some() { opportunity } else { opportunity.with_strategy(strategy.name()) }
+            }));
+            match evaluation.batch {
+                Some(strategy_batch) => {
+                    calculation.add(elapsed, strategy_batch.results.len(), found);
+                    batch.paths_count += strategy_batch.paths_count;
+                    batch.results.extend(strategy_batch.results);
+                    batch.truncation = batch.truncation.or(strategy_batch.truncation);
+                }
+                None => strategies.add(elapsed, 0, found),
+            }
+            if batch.is_truncated() {
+                break;
+            }
+        }
+
+        calculation.record(self, Stage::ProfitCalculation);
+        strategies.record(self, Stage::Strategies);
+        self.record_failure_stats(market_snapshot, &batch);
+
+        if let Some(reason) = batch.truncation {
+            warn!(
+                "区块 {} 的利润计算被提前终止 ({:?})，已计算 {}/{} 条路径",
+                market_snapshot.block_number,
+                reason,
+                batch.results.len(),
+                batch.paths_count
+            );
+            self.metrics.increment(Metric::TruncatedEvaluationsTotal, Stage::ProfitCalculation, 1);
+            opportunities = opportunities.into_iter().map(ArbitrageOpportunity::with_truncation).collect();
+        }
+
+        Ok((opportunities, batch))
+    }
+
+    fn record_block(&self, stage: Stage, paths_evaluated: usize, opportunities_found: usize) {
+        self.metrics.increment(Metric::BlocksTotal, stage, 1);
+        if paths_evaluated > 0 {
+            self.metrics.increment(Metric::PathsEvaluatedTotal, stage, paths_evaluated as u64);
+        }
+        self.metrics.increment(Metric::OpportunitiesFoundTotal, stage, opportunities_found as u64);
+        self.metrics.observe(Metric::OpportunitiesPerBlock, stage, opportunities_found as f64);
+    }
+
+    /// Record the failure statistics of the profit results of a snapshot
+    fn record_failure_stats(&self, market_snapshot: &MarketSnapshot, batch: &ProfitCalculationBatch) {
+        let mut failure_stats = CalculationFailureStats::from_results(market_snapshot.block_number, &batch.results);
+        failure_stats.truncation = batch.truncation;
+        debug!(
+            "区块 {} 计算失败统计: 数据错误 {}, 无利可图 {}, 按类型 {:?}",
+            failure_stats.block_number,
+            failure_stats.data_error_count(),
+            failure_stats.unprofitable_count(),
+            failure_stats.by_kind
+        );
+        if let Ok(mut last_failure_stats) = self.last_failure_stats.write() {
+            *last_failure_stats = Some(failure_stats);
+        }
+    }
+
+    fn calculation_cutoff(&self) -> CalculationCutoff {
+        let mut cutoff = CalculationCutoff::new();
+        if let Some(max_evaluation_time_ms) = self.config.max_evaluation_time_ms {
+            cutoff = cutoff.with_deadline(Instant::now() + Duration::from_millis(max_evaluation_time_ms));
+        }
+        if self.config.cancel_on_newer_block
+            && let Some(latest_block) = &self.latest_block_signal
+        {
+            cutoff = cutoff.with_latest_block(Arc::clone(latest_block));
+        }
+        cutoff
+    }
+
+    /// Select the pool-disjoint opportunities to execute among those found for a block
+    ///
+    /// Opportunities sharing a pool invalidate each other, so only a subset of what
+    /// [ArbitrageEngine::process_market_snapshot] returns can be executed in the same block.
+    /// The plan is built from that result, the snapshot is not evaluated again.
+    pub fn build_execution_plan(&self, block_number: u64, opportunities: Vec<ArbitrageOpportunity>) -> ExecutionPlan {
+        OpportunitySelector::new(self.config.selection_mode).select(block_number, opportunities)
     }
 
     /// Start the real-time processing loop
@@ -142,7 +343,9 @@ impl ArbitrageEngine {
 
         info!("启动套利引擎实时处理循环...");
 
-        while let Some(market_snapshot) = receiver.recv().await {
+        while let Some(market_snapshot) = self.next_market_snapshot(&mut receiver).await {
+            // Opportunity lists are complete per block, expirations are not reported
+            self.reorg_events.clear();
             match self.process_market_snapshot(&market_snapshot) {
                 Ok(opportunities) => {
                     if !opportunities.is_empty() {
@@ -166,32 +369,222 @@ impl ArbitrageEngine {
         Ok(())
     }
 
+    /// Start the real-time processing loop, sending opportunity events instead of full lists
+    ///
+    /// Blocks that change nothing send nothing, see [ArbitrageEngine::process_market_snapshot_events].
+    pub async fn start_real_time_event_processing(
+        &mut self,
+        event_sender: mpsc::Sender<Vec<OpportunityEvent>>,
+    ) -> Result<()> {
+        if !self.is_initialized {
+            return Err(eyre!("引擎未初始化，无法启动实时处理"));
+        }
+
+        let Some(mut receiver) = self.market_data_receiver.take() else {
+            return Err(eyre!("未设置市场数据接收器"));
+        };
+
+        info!("启动套利引擎实时事件处理循环...");
+
+        while let Some(market_snapshot) = self.next_market_snapshot(&mut receiver).await {
+            match self.process_market_snapshot_events(&market_snapshot) {
+                Ok(snapshot_events) => {
+                    let mut events = std::mem::take(&mut self.reorg_events);
+                    events.extend(snapshot_events);
+                    if !events.is_empty() {
+                        debug!("区块 {} 产生 {} 个套利机会事件", market_snapshot.block_number, events.len());
+
+                        if let Err(e) = event_sender.send(events).await {
+                            error!("发送套利机会事件失败: {}", e);
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("处理市场快照失败: {}", e);
+                }
+            }
+        }
+
+        warn!("套利引擎实时事件处理循环结束");
+        Ok(())
+    }
+
+    /// Wait for the next snapshot to evaluate, applying configuration changes and reorgs that
+    /// arrive in the meantime
+    ///
+    /// Backfilled snapshots are skipped, a newer head already arrived and opportunities found on
+    /// their outdated state could not be executed.
+    async fn next_market_snapshot(&mut self, receiver: &mut SnapshotReceiver) -> Option<MarketSnapshot> {
+        loop {
+            let market_snapshot = self.next_delivered_snapshot(receiver).await?;
+            if !market_snapshot.backfill {
+                return Some(market_snapshot);
+            }
+            debug!("跳过补齐的历史区块 {}", market_snapshot.block_number);
+            self.metrics.increment(Metric::BlocksSkippedTotal, Stage::Backfill, 1);
+        }
+    }
+
+    async fn next_delivered_snapshot(&mut self, receiver: &mut SnapshotReceiver) -> Option<MarketSnapshot> {
+        loop {
+            if self.config_receiver.is_none() && self.reorg_receiver.is_none() {
+                return receiver.recv().await;
+            }
+
+            // Pending configuration changes and reorgs go first, they apply to the snapshot that follows them
+            tokio::select! {
+                biased;
+                new_config = recv_if_set(&mut self.config_receiver) => match new_config {
+                    Some(new_config) => {
+                        if let Err(e) = self.update_config(new_config) {
+                            error!("拒绝配置变更: {}", e);
+                        }
+                    }
+                    None => self.config_receiver = None,
+                },
+                reorg = recv_reorg_if_set(&mut self.reorg_receiver) => match reorg {
+                    Ok(reorg) => match self.handle_reorg(&reorg) {
+                        Ok(events) => self.reorg_events.extend(events),
+                        Err(e) => error!("处理链重组失败: {}", e),
+                    },
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("错过了 {} 个链重组事件，清空跟踪中的套利机会", missed);
+                        if let Ok(mut tracker) = self.opportunity_tracker.lock() {
+                            self.reorg_events.extend(tracker.expire_all(DisappearanceReason::ReorgsMissed));
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => self.reorg_receiver = None,
+                },
+                market_snapshot = receiver.recv() => return market_snapshot,
+            }
+        }
+    }
+
+    /// Invalidate what was derived from the orphaned blocks of a reorg
+    ///
+    /// Tracked opportunities last reported in an orphaned block expire, the returned events
+    /// report them. The latest block signal moves back to the new head.
+    pub fn handle_reorg(&self, reorg: &ReorgEvent) -> Result<Vec<OpportunityEvent>> {
+        let first_orphaned_block = reorg.first_orphaned_block();
+        warn!(
+            "检测到链重组: 深度 {}, 新链头 {}, 区块 {} 及之后的结果作废",
+            reorg.depth(),
+            reorg.new_head.number,
+            first_orphaned_block
+        );
+
+        if let Ok(mut last_failure_stats) = self.last_failure_stats.write()
+            && last_failure_stats.as_ref().is_some_and(|stats| stats.block_number >= first_orphaned_block)
+        {
+            *last_failure_stats = None;
+        }
+
+        // The old tip may be above the new head, the blocks of the new chain must not count as outdated
+        if let Some(latest_block) = &self.latest_block_signal {
+            latest_block.store(reorg.new_head.number, Ordering::Release);
+        }
+
+        let mut tracker = self.opportunity_tracker.lock().map_err(|_| eyre!("机会跟踪器锁已损坏"))?;
+        Ok(tracker.invalidate_from(first_orphaned_block))
+    }
+
     /// Get statistics about the engine's current state
     pub fn get_statistics(&self) -> ArbitrageEngineStats {
         ArbitrageEngineStats {
             is_initialized: self.is_initialized,
-            precomputed_paths_count: self.precomputed_paths.len(),
+            precomputed_paths_count: self.get_compiled_paths().len(),
             max_hops: self.config.max_hops,
             min_profit_threshold_mnt_wei: self.config.min_profit_threshold_mnt_wei,
             parallel_calculation_enabled: self.config.enable_parallel_calculation,
+            last_failure_stats: self.get_last_failure_stats(),
+            strategies: self.strategy_names(),
         }
     }
 
+    /// Get the configuration currently in effect
+    pub fn get_config(&self) -> &ArbitrageConfig {
+        &self.config
+    }
+
+    /// Get the calculation failures of the last processed snapshot, by error kind and pool
+    pub fn get_last_failure_stats(&self) -> Option<CalculationFailureStats> {
+        self.last_failure_stats.read().ok().and_then(|stats| stats.clone())
+    }
+
     /// Get a reference to the pre-computed paths (for debugging/analysis)
     pub fn get_precomputed_paths(&self) -> &[SwapPath] {
-        &self.precomputed_paths
+        self.get_compiled_paths().swap_paths()
+    }
+
+    /// Cap trade sizes at the executor's inventory, or lift the cap with `None`
+    pub fn set_inventory(&mut self, inventory: Option<InventoryView>) {
+        for strategy in self.strategies.iter_mut() {
+            strategy.set_inventory(inventory.clone());
+        }
+        self.inventory = inventory;
+    }
+
+    /// Get the compiled form of the pre-computed paths, empty without a [CyclicStrategy]
+    pub fn get_compiled_paths(&self) -> &CompiledPathSet {
+        static NO_PATHS: LazyLock<CompiledPathSet> = LazyLock::new(CompiledPathSet::default);
+        self.strategies
+            .iter()
+            .find(|strategy| strategy.name() == CyclicStrategy::NAME)
+            .and_then(|strategy| strategy.compiled_paths())
+            .unwrap_or(&NO_PATHS)
+    }
+
+    /// Follow the newest available block to stop evaluating outdated snapshots, see
+    /// [ArbitrageConfig::cancel_on_newer_block]. Set by [ArbitrageEngine::set_market_data_receiver].
+    pub fn set_latest_block_signal(&mut self, latest_block: Arc<AtomicU64>) {
+        self.latest_block_signal = Some(latest_block);
+    }
+
+    /// Receive configuration changes, e.g. from a [super::ConfigWatcher], while the real-time
+    /// processing loop runs. They are applied with [ArbitrageEngine::update_config] between snapshots.
+    pub fn set_config_receiver(&mut self, receiver: mpsc::Receiver<ArbitrageConfig>) {
+        self.config_receiver = Some(receiver);
+    }
+
+    /// Receive chain reorganizations, e.g. from the data sync service, while the real-time
+    /// processing loop runs. They are handled with [ArbitrageEngine::handle_reorg] between snapshots.
+    pub fn set_reorg_receiver(&mut self, receiver: broadcast::Receiver<ReorgEvent>) {
+        self.reorg_receiver = Some(receiver);
     }
 
     /// Update configuration (some settings can be changed at runtime)
+    ///
+    /// The new configuration is validated first, then handed to the registered strategies
+    /// through [ArbitrageStrategy::on_config_change]. A strategy rejecting it leaves the engine
+    /// and every strategy on the current configuration.
     pub fn update_config(&mut self, new_config: ArbitrageConfig) -> Result<()> {
+        new_config.validate()?;
+
         // Some settings can't be changed after initialization
         if self.is_initialized {
-            if new_config.max_hops != self.config.max_hops 
-                || new_config.max_precomputed_paths != self.config.max_precomputed_paths {
-                return Err(eyre!("无法更改需要重新初始化的配置项（max_hops, max_precomputed_paths）"));
+            let changes = new_config.reinitialization_changes(&self.config);
+            if !changes.is_empty() {
+                return Err(eyre!("以下配置项需要重新初始化引擎，无法在运行时更改: {}", changes.join(", ")));
             }
         }
 
+        for index in 0..self.strategies.len() {
+            if let Err(e) = self.strategies[index].on_config_change(&new_config) {
+                // Strategies that already took the change over go back to the current configuration
+                for strategy in self.strategies[..index].iter_mut() {
+                    if let Err(e) = strategy.on_config_change(&self.config) {
+                        error!("策略 {} 恢复配置失败: {}", strategy.name(), e);
+                    }
+                }
+                return Err(eyre!("策略 {} 拒绝配置变更: {}", self.strategies[index].name(), e));
+            }
+        }
+
+        if let Ok(mut tracker) = self.opportunity_tracker.lock() {
+            tracker.set_max_tracked(new_config.max_tracked_opportunities);
+        }
+
         self.config = new_config;
         info!("套利引擎配置已更新");
         Ok(())
@@ -206,17 +599,70 @@ pub struct ArbitrageEngineStats {
     pub max_hops: u8,
     pub min_profit_threshold_mnt_wei: U256,
     pub parallel_calculation_enabled: bool,
+    pub last_failure_stats: Option<CalculationFailureStats>,
+    /// Names of the strategies run on every snapshot
+    pub strategies: Vec<String>,
+}
+
+/// Time, paths and opportunities of the strategies of one stage in a block
+#[derive(Default)]
+struct StageTally {
+    ran: bool,
+    duration: Duration,
+    paths_evaluated: usize,
+    opportunities_found: usize,
+}
+
+impl StageTally {
+    fn add(&mut self, duration: Duration, paths_evaluated: usize, opportunities_found: usize) {
+        self.ran = true;
+        self.duration += duration;
+        self.paths_evaluated += paths_evaluated;
+        self.opportunities_found += opportunities_found;
+    }
+
+    fn record(&self, engine: &ArbitrageEngine, stage: Stage) {
+        if self.ran {
+            engine.metrics.observe_duration(stage, self.duration);
+            engine.record_block(stage, self.paths_evaluated, self.opportunities_found);
+        }
+    }
+}
+
+/// Next value of an optional receiver, pending forever without one
+async fn recv_if_set<T>(receiver: &mut Option<mpsc::Receiver<T>>) -> Option<T> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn recv_reorg_if_set(
+    receiver: &mut Option<broadcast::Receiver<ReorgEvent>>,
+) -> Result<ReorgEvent, broadcast::error::RecvError> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
 }
 
 /// Builder pattern for creating and configuring an ArbitrageEngine
 pub struct ArbitrageEngineBuilder {
     config: ArbitrageConfig,
+    inventory: Option<InventoryView>,
+    cyclic_strategy: bool,
+    strategies: Vec<Box<dyn ArbitrageStrategy>>,
+    metrics: Option<Arc<MetricsRegistry>>,
 }
 
 impl ArbitrageEngineBuilder {
     pub fn new() -> Self {
         Self {
             config: ArbitrageConfig::default(),
+            inventory: None,
+            cyclic_strategy: true,
+            strategies: Vec::new(),
+            metrics: None,
         }
     }
 
@@ -246,8 +692,67 @@ impl ArbitrageEngineBuilder {
         self
     }
 
-    pub fn build(self) -> ArbitrageEngine {
-        ArbitrageEngine::new(self.config)
+    pub fn with_slippage_tolerance_bps(mut self, slippage_tolerance_bps: u32) -> Self {
+        self.config.slippage_tolerance_bps = slippage_tolerance_bps;
+        self
+    }
+
+    pub fn with_flash_swaps(mut self, enabled: bool, flash_swap_fee_bps: u32, flash_swap_callback_gas: u64) -> Self {
+        self.config.enable_flash_swaps = enabled;
+        self.config.flash_swap_fee_bps = flash_swap_fee_bps;
+        self.config.flash_swap_callback_gas = flash_swap_callback_gas;
+        self
+    }
+
+    /// Stop evaluating a snapshot after `max_evaluation_time_ms`, or when a newer block arrives
+    pub fn with_evaluation_cutoff(mut self, max_evaluation_time_ms: Option<u64>, cancel_on_newer_block: bool) -> Self {
+        self.config.max_evaluation_time_ms = max_evaluation_time_ms;
+        self.config.cancel_on_newer_block = cancel_on_newer_block;
+        self
+    }
+
+    pub fn with_selection_mode(mut self, selection_mode: SelectionMode) -> Self {
+        self.config.selection_mode = selection_mode;
+        self
+    }
+
+    pub fn with_inventory(mut self, inventory: InventoryView) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    /// Run `strategy` after the ones added so far, the [CyclicStrategy] runs first
+    pub fn with_strategy(mut self, strategy: Box<dyn ArbitrageStrategy>) -> Self {
+        self.strategies.push(strategy);
+        self
+    }
+
+    /// Leave out the [CyclicStrategy], e.g. to add a differently configured one at another position
+    pub fn without_cyclic_strategy(mut self) -> Self {
+        self.cyclic_strategy = false;
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<MetricsRegistry>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Build the engine, failing if a strategy can't be registered, e.g. for a duplicate name
+    pub fn build(self) -> Result<ArbitrageEngine> {
+        let mut engine = if self.cyclic_strategy {
+            ArbitrageEngine::new(self.config)
+        } else {
+            ArbitrageEngine::without_strategies(self.config)
+        };
+        engine.set_inventory(self.inventory);
+        if let Some(metrics) = self.metrics {
+            engine.set_metrics(metrics);
+        }
+        for strategy in self.strategies {
+            engine.register_strategy(strategy)?;
+        }
+        Ok(engine)
     }
 }
 
@@ -262,8 +767,10 @@ mod tests {
     use super::*;
     use crate::{MockPool, PoolWrapper, Token};
     use crate::logic::graph::TokenGraph;
-    use crate::logic::types::MarketSnapshot;
-    use crate::logic::pools::PoolId;
+    use crate::logic::types::{BlockRef, MarketSnapshot, TruncationReason};
+    use crate::logic::opportunity_tracker::DisappearanceReason;
+    use crate::logic::strategy::{CrossDexSpreadStrategy, CyclicStrategy};
+    use crate::logic::pools::{CalculationErrorKind, PoolId};
     use crate::utils::constants::WMNT;
     use alloy_primitives::Address;
     use std::sync::Arc;
@@ -292,25 +799,58 @@ mod tests {
         Ok(token_graph)
     }
 
+    fn eth(amount: u64) -> U256 {
+        U256::from(amount) * U256::from(1_000_000_000_000_000_000u64)
+    }
+
+    /// Reserves in ETH of the WMNT -> TOKEN1 -> TOKEN2 -> WMNT cycle that pay out on TOKEN2/WMNT
+    const PROFITABLE_CYCLE: [(u64, u64); 3] = [(1_000, 1_000), (1_000, 1_000), (1_000, 1_200)];
+
+    /// A snapshot of the cycle of [create_test_token_graph], reserves in ETH per pool in cycle order
+    fn snapshot_with_cycle(block: u64, reserves: [(u64, u64); 3]) -> MarketSnapshot {
+        let mut snapshot = MarketSnapshot::new(block);
+        for (pool_byte, (reserve0, reserve1)) in (10..).zip(reserves) {
+            snapshot.set_pool_reserves(PoolId::Address(Address::repeat_byte(pool_byte)), eth(reserve0), eth(reserve1));
+        }
+        snapshot
+    }
+
     #[test]
-    fn test_arbitrage_engine_builder() {
+    fn test_arbitrage_engine_builder() -> Result<()> {
         let engine = ArbitrageEngineBuilder::new()
             .with_min_profit_threshold(U256::from_str_radix("10000000000000000000", 10).unwrap())
             .with_max_hops(3)
             .with_parallel_calculation(true)
-            .build();
+            .build()?;
 
         let stats = engine.get_statistics();
         assert_eq!(stats.min_profit_threshold_mnt_wei, U256::from_str_radix("10000000000000000000", 10).unwrap());
         assert_eq!(stats.max_hops, 3);
         assert_eq!(stats.parallel_calculation_enabled, true);
         assert_eq!(stats.is_initialized, false);
+
+        // A strategy that can't be registered fails the build instead of being dropped
+        let error = ArbitrageEngineBuilder::new()
+            .with_strategy(Box::new(CyclicStrategy::new(ArbitrageConfig::default())))
+            .build()
+            .err()
+            .expect("duplicate strategy name");
+        assert!(error.to_string().contains(CyclicStrategy::NAME));
+        assert!(
+            ArbitrageEngineBuilder::new()
+                .without_cyclic_strategy()
+                .with_strategy(Box::new(CyclicStrategy::new(ArbitrageConfig::default())))
+                .build()
+                .is_ok()
+        );
+
+        Ok(())
     }
 
     #[tokio::test]
     async fn test_engine_initialization() -> Result<()> {
         let token_graph = create_test_token_graph()?;
-        let mut engine = ArbitrageEngineBuilder::new().build();
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
 
         assert!(!engine.get_statistics().is_initialized);
 
@@ -328,7 +868,7 @@ mod tests {
         let token_graph = create_test_token_graph()?;
         let mut engine = ArbitrageEngineBuilder::new()
             .with_min_profit_threshold(U256::from_str_radix("1000000000000000000", 10).unwrap()) // 1 MNT threshold for testing
-            .build();
+            .build()?;
 
         engine.initialize(&token_graph)?;
 
@@ -350,9 +890,243 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_failure_stats_by_kind_and_pool() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+
+        // No data for the last pool of the cycle
+        let mut snapshot = MarketSnapshot::new(777);
+        let reserves = U256::from_str_radix("1000000000000000000000", 10).unwrap();
+        snapshot.set_pool_reserves(PoolId::Address(Address::repeat_byte(10)), reserves, reserves);
+        snapshot.set_pool_reserves(PoolId::Address(Address::repeat_byte(11)), reserves, reserves);
+
+        engine.process_market_snapshot(&snapshot)?;
+
+        let stats = engine.get_statistics().last_failure_stats.expect("stats are recorded per snapshot");
+        let missing_pool = PoolId::Address(Address::repeat_byte(12));
+        assert_eq!(stats.block_number, 777);
+        assert_eq!(stats.paths_count, engine.get_compiled_paths().len());
+        assert_eq!(stats.count(CalculationErrorKind::MissingState), stats.paths_count);
+        assert_eq!(stats.pool_count(&missing_pool, CalculationErrorKind::MissingState), stats.paths_count);
+        assert_eq!(stats.data_error_count(), stats.paths_count);
+        assert_eq!(stats.top_failing_pools(1), vec![(missing_pool, stats.paths_count)]);
+
+        // With balanced reserves every cycle loses the fee, which is not a data error
+        snapshot.set_pool_reserves(missing_pool, reserves, reserves);
+        engine.process_market_snapshot(&snapshot)?;
+
+        let stats = engine.get_last_failure_stats().unwrap();
+        assert_eq!(stats.data_error_count(), 0);
+        assert_eq!(stats.unprofitable_count(), stats.paths_count);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_opportunity_events_across_snapshots() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+
+        let mut snapshot = snapshot_with_cycle(1, PROFITABLE_CYCLE);
+
+        let events = engine.process_market_snapshot_events(&snapshot)?;
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| matches!(event, OpportunityEvent::New(_))));
+        assert_eq!(engine.tracked_opportunities_count(), events.len());
+
+        // An unchanged market produces no events
+        snapshot.block_number = 2;
+        assert!(engine.process_market_snapshot_events(&snapshot)?.is_empty());
+
+        // Balanced reserves close every opportunity
+        snapshot.block_number = 3;
+        snapshot.set_pool_reserves(PoolId::Address(Address::repeat_byte(12)), eth(1_000), eth(1_000));
+        let events = engine.process_market_snapshot_events(&snapshot)?;
+        assert!(!events.is_empty());
+        for event in &events {
+            match event {
+                OpportunityEvent::Expired { opportunity, reason } => {
+                    assert_eq!(*reason, DisappearanceReason::Unprofitable);
+                    assert_eq!(opportunity.lifetime_blocks(), 2);
+                }
+                event => panic!("unexpected event {event:?}"),
+            }
+        }
+        assert_eq!(engine.tracked_opportunities_count(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_outdated_snapshot_is_truncated() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+        let latest_block = Arc::new(AtomicU64::new(1));
+        engine.set_latest_block_signal(Arc::clone(&latest_block));
+
+        let mut snapshot = snapshot_with_cycle(1, PROFITABLE_CYCLE);
+        let tracked = engine.process_market_snapshot_events(&snapshot)?.len();
+        assert!(tracked > 0);
+        assert_eq!(engine.get_last_failure_stats().and_then(|stats| stats.truncation), None);
+
+        // Block 3 is already out while block 2 is evaluated
+        latest_block.store(3, std::sync::atomic::Ordering::Release);
+        snapshot.block_number = 2;
+        snapshot.set_pool_reserves(PoolId::Address(Address::repeat_byte(12)), eth(1_000), eth(1_000));
+        let events = engine.process_market_snapshot_events(&snapshot)?;
+        assert!(events.is_empty());
+        assert_eq!(engine.tracked_opportunities_count(), tracked);
+        assert_eq!(
+            engine.get_last_failure_stats().and_then(|stats| stats.truncation),
+            Some(TruncationReason::NewerBlock { latest_block: 3 })
+        );
+        assert_eq!(engine.metrics().counter(Metric::TruncatedEvaluationsTotal, Stage::ProfitCalculation), 1);
+
+        // Cancellation on a newer block can be turned off
+        engine.update_config(ArbitrageConfig { cancel_on_newer_block: false, ..ArbitrageConfig::default() })?;
+        engine.process_market_snapshot(&snapshot)?;
+        assert_eq!(engine.get_last_failure_stats().and_then(|stats| stats.truncation), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_execution_plan_from_found_opportunities() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+
+        let snapshot = snapshot_with_cycle(1, PROFITABLE_CYCLE);
+
+        let opportunities = engine.process_market_snapshot(&snapshot)?;
+        let plan = engine.build_execution_plan(snapshot.block_number, opportunities.clone());
+
+        // Every cycle runs through the same three pools, only one of them can be executed
+        assert_eq!(plan.candidates_count, opportunities.len());
+        assert_eq!(plan.len(), 1);
+        assert_eq!(engine.metrics().counter(Metric::BlocksTotal, Stage::ProfitCalculation), 1);
+
+        Ok(())
+    }
+
+    struct FailingStrategy;
+
+    impl ArbitrageStrategy for FailingStrategy {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn init(&mut self, _token_graph: &TokenGraph) -> Result<()> {
+            Ok(())
+        }
+
+        fn on_snapshot(&self, _market_snapshot: &MarketSnapshot) -> Result<Vec<ArbitrageOpportunity>> {
+            Err(eyre!("research strategy failed"))
+        }
+
+        fn on_config_change(&mut self, _config: &ArbitrageConfig) -> Result<()> {
+            Err(eyre!("research strategy can't be reconfigured"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strategies_are_merged_with_attribution() -> Result<()> {
+        let mut token_graph = create_test_token_graph()?;
+        // A second WMNT/TOKEN1 pool on another DEX
+        token_graph.add_pool(PoolWrapper::from(MockPool::new(WMNT, Address::repeat_byte(1), Address::repeat_byte(13))))?;
+
+        let mut engine = ArbitrageEngineBuilder::new()
+            .with_strategy(Box::new(CrossDexSpreadStrategy::new(ArbitrageConfig::default())))
+            .with_strategy(Box::new(FailingStrategy))
+            .build()?;
+        assert!(engine.register_strategy(Box::new(FailingStrategy)).is_err());
+        engine.initialize(&token_graph)?;
+        assert!(engine.register_strategy(Box::new(CrossDexSpreadStrategy::new(ArbitrageConfig::default()))).is_err());
+        assert_eq!(engine.get_statistics().strategies, vec!["cyclic", "cross_dex_spread", "failing"]);
+
+        let mut snapshot = snapshot_with_cycle(1, [(1_200, 1_000), (1_000, 1_000), (1_000, 1_000)]);
+        snapshot.set_pool_reserves(PoolId::Address(Address::repeat_byte(13)), eth(1_000), eth(1_000));
+
+        let opportunities = engine.process_market_snapshot(&snapshot)?;
+        let spreads: Vec<_> = opportunities.iter().filter(|opportunity| opportunity.strategy.as_deref() == Some("cross_dex_spread")).collect();
+        assert_eq!(spreads.len(), 1);
+        assert_eq!(spreads[0].path.len(), 2);
+        assert!(opportunities.iter().any(|opportunity| opportunity.strategy.as_deref() == Some(CyclicStrategy::NAME)));
+        assert!(opportunities.iter().all(|opportunity| opportunity.strategy.is_some()));
+
+        let metrics = engine.metrics();
+        assert_eq!(metrics.counter(Metric::PathsEvaluatedTotal, Stage::ProfitCalculation), engine.get_compiled_paths().len() as u64);
+        assert_eq!(metrics.counter(Metric::StageFailuresTotal, Stage::Strategies), 1);
+        assert_eq!(metrics.counter(Metric::OpportunitiesFoundTotal, Stage::Strategies), 1);
+        assert_eq!(metrics.histogram(Metric::StageDurationSeconds, Stage::ProfitCalculation).map(|histogram| histogram.count), Some(1));
+
+        // A strategy rejecting a configuration change keeps the engine on the current one
+        let error = engine.update_config(ArbitrageConfig { gas_price_gwei: 30.0, ..ArbitrageConfig::default() }).unwrap_err();
+        assert!(error.to_string().contains("failing"));
+        assert_eq!(engine.get_config().gas_price_gwei, ArbitrageConfig::default().gas_price_gwei);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cyclic_strategy_can_be_left_out_or_moved() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let snapshot = snapshot_with_cycle(1, PROFITABLE_CYCLE);
+
+        let mut engine = ArbitrageEngineBuilder::new()
+            .without_cyclic_strategy()
+            .with_strategy(Box::new(CrossDexSpreadStrategy::new(ArbitrageConfig::default())))
+            .build()?;
+        engine.initialize(&token_graph)?;
+        assert_eq!(engine.strategy_names(), vec!["cross_dex_spread"]);
+        assert_eq!(engine.get_statistics().precomputed_paths_count, 0);
+        assert!(engine.process_market_snapshot(&snapshot)?.is_empty());
+
+        let mut engine = ArbitrageEngine::with_strategies(
+            ArbitrageConfig::default(),
+            vec![
+                Box::new(CrossDexSpreadStrategy::new(ArbitrageConfig::default())),
+                Box::new(CyclicStrategy::new(ArbitrageConfig::default())),
+            ],
+        )?;
+        engine.initialize(&token_graph)?;
+        assert_eq!(engine.strategy_names(), vec!["cross_dex_spread", CyclicStrategy::NAME]);
+        let opportunities = engine.process_market_snapshot(&snapshot)?;
+        assert!(!opportunities.is_empty());
+        assert!(opportunities.iter().all(|opportunity| opportunity.strategy.as_deref() == Some(CyclicStrategy::NAME)));
+
+        assert!(ArbitrageEngine::with_strategies(ArbitrageConfig::default(), Vec::new())?.initialize(&token_graph).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pool_changes_rebuild_the_cycles() -> Result<()> {
+        let mut token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+        let paths_count = engine.get_statistics().precomputed_paths_count;
+
+        // A second WMNT/TOKEN1 pool opens new cycles
+        let pool_id = PoolId::Address(Address::repeat_byte(13));
+        token_graph.add_pool(PoolWrapper::from(MockPool::new(WMNT, Address::repeat_byte(1), Address::repeat_byte(13))))?;
+        engine.on_pool_change(&token_graph, PoolChange::Added(pool_id))?;
+        assert!(engine.get_statistics().precomputed_paths_count > paths_count);
+
+        token_graph.set_pool_active(pool_id, false)?;
+        engine.on_pool_change(&token_graph, PoolChange::Disabled(pool_id))?;
+        assert_eq!(engine.get_statistics().precomputed_paths_count, paths_count);
+
+        Ok(())
+    }
+
     #[test]
     fn test_config_update() -> Result<()> {
-        let mut engine = ArbitrageEngineBuilder::new().build();
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
 
         // Should be able to update non-initialization configs
         let mut new_config = ArbitrageConfig::default();
@@ -369,7 +1143,7 @@ mod tests {
     #[tokio::test]
     async fn test_config_update_after_initialization() -> Result<()> {
         let token_graph = create_test_token_graph()?;
-        let mut engine = ArbitrageEngineBuilder::new().build();
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
         engine.initialize(&token_graph)?;
 
         // Should not be able to change initialization-dependent configs
@@ -377,7 +1151,177 @@ mod tests {
         new_config.max_hops = 5; // Different from default
 
         let result = engine.update_config(new_config);
-        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("max_hops: 4 -> 5"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_config_changes_applied_between_snapshots() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+
+        let (snapshot_sender, snapshot_receiver) = mpsc::channel(4);
+        let (config_sender, config_receiver) = mpsc::channel(4);
+        let (opportunity_sender, mut opportunity_receiver) = mpsc::channel(4);
+        engine.set_market_data_receiver(snapshot_receiver);
+        engine.set_config_receiver(config_receiver);
+
+        let snapshot = snapshot_with_cycle(1, PROFITABLE_CYCLE);
+
+        // The re-initialisation change is rejected, gas now costs more than any opportunity makes
+        config_sender.send(ArbitrageConfig { max_hops: 3, ..ArbitrageConfig::default() }).await?;
+        config_sender.send(ArbitrageConfig { gas_price_gwei: 1_000_000.0, ..ArbitrageConfig::default() }).await?;
+        snapshot_sender.send(snapshot.clone()).await?;
+        drop(snapshot_sender);
+
+        engine.start_real_time_processing(opportunity_sender).await?;
+        assert!(opportunity_receiver.try_recv().is_err());
+        assert_eq!(engine.config.max_hops, 4);
+        assert_eq!(engine.config.gas_price_gwei, 1_000_000.0);
+
+        engine.update_config(ArbitrageConfig::default())?;
+        assert!(!engine.process_market_snapshot(&snapshot)?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reorg_expires_orphaned_opportunities() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+
+        let snapshot = snapshot_with_cycle(5, PROFITABLE_CYCLE);
+        let tracked = engine.process_market_snapshot_events(&snapshot)?.len();
+        assert!(tracked > 0);
+
+        let block = |number: u64, hash: &str| BlockRef { number, hash: hash.to_string() };
+        let reorg = ReorgEvent { common_ancestor: Some(4), orphaned: vec![block(5, "0x05")], new_head: block(5, "0x5b") };
+
+        // The replacement block still has the opportunities, they are expired then reported again
+        let (snapshot_sender, snapshot_receiver) = mpsc::channel(4);
+        let (reorg_sender, reorg_receiver) = broadcast::channel(4);
+        let (event_sender, mut event_receiver) = mpsc::channel(4);
+        engine.set_market_data_receiver(snapshot_receiver);
+        engine.set_reorg_receiver(reorg_receiver);
+        reorg_sender.send(reorg.clone())?;
+        snapshot_sender.send(snapshot).await?;
+        drop(snapshot_sender);
+
+        engine.start_real_time_event_processing(event_sender).await?;
+        let events = event_receiver.try_recv()?;
+        assert_eq!(events.len(), 2 * tracked);
+        for event in &events[..tracked] {
+            assert!(matches!(event, OpportunityEvent::Expired { reason: DisappearanceReason::Reorged, .. }));
+        }
+        assert!(events[tracked..].iter().all(|event| matches!(event, OpportunityEvent::New(_))));
+
+        // Nothing tracked before the fork point is touched
+        let older = ReorgEvent { common_ancestor: Some(5), orphaned: vec![block(6, "0x06")], new_head: block(6, "0x6b") };
+        assert!(engine.handle_reorg(&older)?.is_empty());
+        assert_eq!(engine.handle_reorg(&reorg)?.len(), tracked);
+        assert_eq!(engine.tracked_opportunities_count(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backfilled_snapshots_are_not_evaluated() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+
+        // Blocks 3 and 4 were backfilled after head 5 arrived, only the head is evaluated
+        let (snapshot_sender, snapshot_receiver) = mpsc::channel(4);
+        let (opportunity_sender, mut opportunity_receiver) = mpsc::channel(4);
+        engine.set_market_data_receiver(snapshot_receiver);
+        for block in 3..=5 {
+            let mut snapshot = snapshot_with_cycle(block, PROFITABLE_CYCLE);
+            snapshot.backfill = block < 5;
+            snapshot_sender.send(snapshot).await?;
+        }
+        drop(snapshot_sender);
+
+        engine.start_real_time_processing(opportunity_sender).await?;
+        assert!(!opportunity_receiver.try_recv()?.is_empty());
+        assert!(opportunity_receiver.try_recv().is_err());
+
+        let metrics = engine.metrics();
+        assert_eq!(metrics.counter(Metric::BlocksSkippedTotal, Stage::Backfill), 2);
+        assert_eq!(metrics.counter(Metric::BlocksTotal, Stage::ProfitCalculation), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reorg_onto_shorter_fork_is_not_outdated() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+        let latest_block = Arc::new(AtomicU64::new(12));
+        engine.set_latest_block_signal(Arc::clone(&latest_block));
+
+        let mut snapshot = snapshot_with_cycle(12, PROFITABLE_CYCLE);
+        let tracked = engine.process_market_snapshot_events(&snapshot)?.len();
+        assert!(tracked > 0);
+
+        // Blocks 11 and 12 are replaced by 11b only
+        let block = |number: u64, hash: &str| BlockRef { number, hash: hash.to_string() };
+        let reorg = ReorgEvent {
+            common_ancestor: Some(10),
+            orphaned: vec![block(11, "0x11"), block(12, "0x12")],
+            new_head: block(11, "0x11b"),
+        };
+        assert_eq!(engine.handle_reorg(&reorg)?.len(), tracked);
+        assert_eq!(latest_block.load(Ordering::Acquire), 11);
+
+        snapshot.block_number = 11;
+        let events = engine.process_market_snapshot_events(&snapshot)?;
+        assert_eq!(events.len(), tracked);
+        assert!(events.iter().all(|event| matches!(event, OpportunityEvent::New(_))));
+        assert_eq!(engine.get_last_failure_stats().and_then(|stats| stats.truncation), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missed_reorgs_expire_tracked_opportunities() -> Result<()> {
+        let token_graph = create_test_token_graph()?;
+        let mut engine = ArbitrageEngineBuilder::new().build()?;
+        engine.initialize(&token_graph)?;
+
+        let mut snapshot = snapshot_with_cycle(5, PROFITABLE_CYCLE);
+        let tracked = engine.process_market_snapshot_events(&snapshot)?.len();
+        assert!(tracked > 0);
+
+        // The first of two reorgs is overwritten before the engine receives it
+        let block = |number: u64, hash: &str| BlockRef { number, hash: hash.to_string() };
+        let (snapshot_sender, snapshot_receiver) = mpsc::channel(4);
+        let (reorg_sender, reorg_receiver) = broadcast::channel(1);
+        let (event_sender, mut event_receiver) = mpsc::channel(4);
+        engine.set_market_data_receiver(snapshot_receiver);
+        engine.set_reorg_receiver(reorg_receiver);
+        for number in [20, 21] {
+            let reorg = ReorgEvent {
+                common_ancestor: Some(number - 1),
+                orphaned: vec![block(number, "0xaa")],
+                new_head: block(number, "0xbb"),
+            };
+            reorg_sender.send(reorg)?;
+        }
+        snapshot.block_number = 21;
+        snapshot_sender.send(snapshot).await?;
+        drop(snapshot_sender);
+
+        engine.start_real_time_event_processing(event_sender).await?;
+        let events = event_receiver.try_recv()?;
+        assert_eq!(events.len(), 2 * tracked);
+        for event in &events[..tracked] {
+            assert!(matches!(event, OpportunityEvent::Expired { reason: DisappearanceReason::ReorgsMissed, .. }));
+        }
+        assert!(events[tracked..].iter().all(|event| matches!(event, OpportunityEvent::New(_))));
 
         Ok(())
     }
@@ -0,0 +1,241 @@
+use super::graph::MAX_COMPILED_HOPS;
+use super::opportunity_selector::{MAX_EXACT_CANDIDATES, SelectionMode};
+use super::types::ArbitrageConfig;
+use crate::utils::config_loader::{FluxConfigLoader, FluxConfigLoaderSync, LoadConfigError, load_from_file, load_from_file_sync};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct ArbitrageConfigRoot {
+    pub arbitrage: ArbitrageConfig,
+}
+
+impl ArbitrageConfig {
+    /// Check that every setting is usable, independently of a running engine
+    pub fn validate(&self) -> Result<(), LoadConfigError> {
+        let mut problems = Vec::new();
+        if !(2..=MAX_COMPILED_HOPS).contains(&(self.max_hops as usize)) {
+            problems.push(format!("max_hops must be between 2 and {}, got {}", MAX_COMPILED_HOPS, self.max_hops));
+        }
+        if !self.gas_price_gwei.is_finite() || self.gas_price_gwei < 0.0 {
+            problems.push(format!("gas_price_gwei must be a non-negative number, got {}", self.gas_price_gwei));
+        }
+        if self.slippage_tolerance_bps > 10_000 {
+            problems.push(format!("slippage_tolerance_bps must be at most 10000, got {}", self.slippage_tolerance_bps));
+        }
+        if self.flash_swap_fee_bps >= 10_000 {
+            problems.push(format!("flash_swap_fee_bps must be below 10000, got {}", self.flash_swap_fee_bps));
+        }
+        if self.max_precomputed_paths == 0 {
+            problems.push("max_precomputed_paths must be positive".to_string());
+        }
+        if self.max_tracked_opportunities == 0 {
+            problems.push("max_tracked_opportunities must be positive".to_string());
+        }
+        if let SelectionMode::Exact { max_candidates } = self.selection_mode
+            && !(1..=MAX_EXACT_CANDIDATES).contains(&max_candidates)
+        {
+            problems.push(format!("max_candidates must be between 1 and {}, got {}", MAX_EXACT_CANDIDATES, max_candidates));
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(LoadConfigError::ConfigError(problems.join("; "))) }
+    }
+
+    /// Settings that differ from `current` but are only read when the engine is initialized,
+    /// as `name: current -> new`
+    pub fn reinitialization_changes(&self, current: &ArbitrageConfig) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.max_hops != current.max_hops {
+            changes.push(format!("max_hops: {} -> {}", current.max_hops, self.max_hops));
+        }
+        if self.max_precomputed_paths != current.max_precomputed_paths {
+            changes.push(format!("max_precomputed_paths: {} -> {}", current.max_precomputed_paths, self.max_precomputed_paths));
+        }
+        changes
+    }
+}
+
+#[async_trait]
+impl FluxConfigLoader for ArbitrageConfig {
+    type SectionType = ArbitrageConfig;
+
+    async fn load_section_from_file(file_name: String) -> Result<Self::SectionType, LoadConfigError> {
+        let root: ArbitrageConfigRoot = load_from_file(file_name).await?;
+        root.arbitrage.validate()?;
+        Ok(root.arbitrage)
+    }
+}
+
+impl FluxConfigLoaderSync for ArbitrageConfig {
+    type SectionType = ArbitrageConfig;
+
+    fn load_section_from_file_sync(file_name: String) -> Result<Self::SectionType, LoadConfigError> {
+        let root: ArbitrageConfigRoot = load_from_file_sync(file_name)?;
+        root.arbitrage.validate()?;
+        Ok(root.arbitrage)
+    }
+}
+
+/// Watches the `[arbitrage]` section of a config file and forwards the changes that can be
+/// applied to a running engine
+///
+/// The file is polled for a new modification time. A changed file is reloaded and validated;
+/// invalid files and changes to settings that need re-initialisation are rejected with an
+/// error and the last accepted configuration stays in effect.
+pub struct ConfigWatcher {
+    file_name: String,
+    poll_interval: Duration,
+    current: ArbitrageConfig,
+}
+
+impl ConfigWatcher {
+    pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+    /// `current` is the configuration the engine runs with, changes are detected against it
+    pub fn new(file_name: impl Into<String>, current: ArbitrageConfig) -> Self {
+        Self { file_name: file_name.into(), poll_interval: Self::DEFAULT_POLL_INTERVAL, current }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// The last accepted configuration
+    pub fn current(&self) -> &ArbitrageConfig {
+        &self.current
+    }
+
+    /// Reload the file, returns the new configuration if it differs and can be applied live
+    pub async fn check(&mut self) -> Result<Option<ArbitrageConfig>, LoadConfigError> {
+        let new_config = ArbitrageConfig::load_section_from_file(self.file_name.clone()).await?;
+        if new_config == self.current {
+            return Ok(None);
+        }
+
+        let changes = new_config.reinitialization_changes(&self.current);
+        if !changes.is_empty() {
+            return Err(LoadConfigError::ConfigError(format!(
+                "changing {} requires re-initialising the engine, restart it to apply the file",
+                changes.join(", ")
+            )));
+        }
+
+        self.current = new_config.clone();
+        Ok(Some(new_config))
+    }
+
+    /// Poll the file in the background and send accepted configurations to `sender`,
+    /// see [super::ArbitrageEngine::set_config_receiver]
+    pub fn spawn(mut self, sender: mpsc::Sender<ArbitrageConfig>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            info!("开始监听配置文件 {}", self.file_name);
+            let mut interval = tokio::time::interval(self.poll_interval);
+            let mut last_modified: Option<SystemTime> = None;
+
+            loop {
+                interval.tick().await;
+                let modified = match tokio::fs::metadata(&self.file_name).await.and_then(|metadata| metadata.modified()) {
+                    Ok(modified) => modified,
+                    Err(e) => {
+                        warn!("无法读取配置文件 {} 的状态: {}", self.file_name, e);
+                        continue;
+                    }
+                };
+                if last_modified == Some(modified) {
+                    continue;
+                }
+                last_modified = Some(modified);
+
+                match self.check().await {
+                    Ok(Some(config)) => {
+                        info!("配置文件 {} 已变更，应用新配置", self.file_name);
+                        if sender.send(config).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => error!("拒绝配置文件 {} 的变更: {}", self.file_name, e),
+                }
+            }
+
+            warn!("配置文件监听结束: {}", self.file_name);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sync::markets::MarketConfigSection;
+    use alloy_primitives::U256;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn write_config(file: &mut NamedTempFile, contents: &str) {
+        let file = file.as_file_mut();
+        file.set_len(0).unwrap();
+        std::io::Seek::rewind(file).unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file.flush().unwrap();
+    }
+
+    #[test]
+    fn test_load_arbitrage_section() {
+        let mut file = NamedTempFile::new().unwrap();
+        write_config(
+            &mut file,
+            r#"
+            [market]
+            max_hops = 3
+
+            [arbitrage]
+            min_profit_threshold_mnt_wei = "5000000000000000"
+            gas_price_gwei = 0.05
+            selection_mode = { exact = { max_candidates = 16 } }
+            "#,
+        );
+        let file_name = file.path().to_string_lossy().to_string();
+
+        let config = ArbitrageConfig::load_section_from_file_sync(file_name.clone()).unwrap();
+        assert_eq!(config.min_profit_threshold_mnt_wei, U256::from(5_000_000_000_000_000u64));
+        assert_eq!(config.gas_price_gwei, 0.05);
+        assert_eq!(config.selection_mode, SelectionMode::Exact { max_candidates: 16 });
+        // Missing keys keep their default
+        assert_eq!(config.max_hops, ArbitrageConfig::default().max_hops);
+        // The market section is read from the same file
+        assert_eq!(MarketConfigSection::load_section_from_file_sync(file_name.clone()).unwrap().max_hops, 3);
+
+        write_config(&mut file, "[arbitrage]\nslippage_tolerance_bps = 20000\n");
+        let error = ArbitrageConfig::load_section_from_file_sync(file_name.clone()).unwrap_err();
+        assert!(error.to_string().contains("slippage_tolerance_bps"));
+
+        write_config(&mut file, "[arbitrage]\nselection_mode = { exact = { max_candidates = 64 } }\n");
+        let error = ArbitrageConfig::load_section_from_file_sync(file_name.clone()).unwrap_err();
+        assert!(error.to_string().contains("max_candidates"));
+
+        write_config(&mut file, "[arbitrage]\nmin_profit = 1\n");
+        assert!(matches!(ArbitrageConfig::load_section_from_file_sync(file_name), Err(LoadConfigError::TomlError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_watcher_rejects_reinitialization_changes() {
+        let mut file = NamedTempFile::new().unwrap();
+        write_config(&mut file, "[arbitrage]\n");
+        let mut watcher = ConfigWatcher::new(file.path().to_string_lossy(), ArbitrageConfig::default());
+        assert!(watcher.check().await.unwrap().is_none());
+
+        write_config(&mut file, "[arbitrage]\ngas_price_gwei = 0.1\n");
+        let config = watcher.check().await.unwrap().unwrap();
+        assert_eq!(config.gas_price_gwei, 0.1);
+
+        write_config(&mut file, "[arbitrage]\ngas_price_gwei = 0.2\nmax_hops = 3\n");
+        let error = watcher.check().await.unwrap_err();
+        assert!(error.to_string().contains("max_hops: 4 -> 3"));
+        assert_eq!(watcher.current().gas_price_gwei, 0.1);
+    }
+}
@@ -0,0 +1,304 @@
+use super::super::pools::PoolClass;
+use super::super::pools::pool_id::PoolId;
+use super::path_trie::PathTrie;
+use super::swap_path::SwapPath;
+use super::swap_path_hash::SwapPathHash;
+use super::token_graph::TokenGraph;
+use alloy_primitives::{Address, U256};
+use eyre::{Result, eyre};
+
+/// Maximum number of hops a [CompiledPath] can hold (one direction bit per hop in a `u8`)
+pub const MAX_COMPILED_HOPS: usize = 8;
+
+/// Compact representation of a [SwapPath] for the hot path.
+///
+/// Pools and tokens are stored as dense indices into the [CompiledPathSet] table the path
+/// was compiled into. Every hop carries a direction bit, so the profit loop neither hashes
+/// pool ids nor compares token addresses. The struct is `Copy` and fits in a cache line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CompiledPath {
+    pools: [u32; MAX_COMPILED_HOPS],
+    tokens: [u32; MAX_COMPILED_HOPS + 1],
+    // bit i is set if hop i swaps reserve0 -> reserve1
+    directions: u8,
+    len: u8,
+}
+
+impl CompiledPath {
+    /// The hop count of the compiled path
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Dense pool indices of all hops
+    pub fn pools(&self) -> &[u32] {
+        &self.pools[..self.len()]
+    }
+
+    /// Dense token indices (graph node indices), one more than the number of hops
+    pub fn tokens(&self) -> &[u32] {
+        if self.is_empty() { &[] } else { &self.tokens[..self.len() + 1] }
+    }
+
+    /// Dense pool index of the given hop
+    pub fn pool_index(&self, hop: usize) -> usize {
+        self.pools[hop] as usize
+    }
+
+    /// Returns true if the given hop swaps reserve0 for reserve1
+    pub fn zero_for_one(&self, hop: usize) -> bool {
+        self.directions & (1 << hop) != 0
+    }
+
+    /// Iterate over the hops as (dense pool index, zero_for_one)
+    pub fn hops(&self) -> impl Iterator<Item = (usize, bool)> + '_ {
+        (0..self.len()).map(move |hop| (self.pool_index(hop), self.zero_for_one(hop)))
+    }
+}
+
+/// A table of compiled paths together with the per-market lookup data they index into.
+///
+/// The original [SwapPath]s are kept so that results can reference a path by index or hash
+/// and only materialise the full path for the few opportunities that are reported.
+#[derive(Clone, Debug, Default)]
+pub struct CompiledPathSet {
+    paths: Vec<CompiledPath>,
+    hashes: Vec<SwapPathHash>,
+    swap_paths: Vec<SwapPath>,
+    // dense pool index -> pool id / fee / class / flash swap support
+    pool_ids: Vec<PoolId>,
+    pool_fees: Vec<U256>,
+    pool_classes: Vec<PoolClass>,
+    pool_flash_swaps: Vec<bool>,
+    trie: PathTrie,
+}
+
+impl CompiledPathSet {
+    /// Compile paths against the dense pool and token indices of the given graph
+    pub fn compile(token_graph: &TokenGraph, swap_paths: Vec<SwapPath>) -> Result<Self> {
+        let pool_ids = token_graph.pool_dense_index.pool_ids().to_vec();
+        let mut pool_fees = Vec::with_capacity(pool_ids.len());
+        let mut pool_classes = Vec::with_capacity(pool_ids.len());
+        let mut pool_flash_swaps = Vec::with_capacity(pool_ids.len());
+        for pool_id in pool_ids.iter() {
+            let pool = token_graph.pools.get(pool_id).ok_or_else(|| eyre!("Pool not found in graph: {:?}", pool_id))?;
+            pool_fees.push(pool.get_fee());
+            pool_classes.push(pool.get_class());
+            pool_flash_swaps.push(pool.can_flash_swap());
+        }
+
+        let mut paths = Vec::with_capacity(swap_paths.len());
+        let mut hashes = Vec::with_capacity(swap_paths.len());
+        for swap_path in swap_paths.iter() {
+            paths.push(Self::compile_path(token_graph, swap_path)?);
+            hashes.push(swap_path.swap_path_hash.clone());
+        }
+
+        let trie = PathTrie::build(&paths);
+
+        Ok(Self { paths, hashes, swap_paths, pool_ids, pool_fees, pool_classes, pool_flash_swaps, trie })
+    }
+
+    /// Compile paths that are not backed by a market graph. A private index is built from the pools of the paths.
+    pub fn from_paths(swap_paths: Vec<SwapPath>) -> Result<Self> {
+        let mut token_graph = TokenGraph::new();
+        for swap_path in swap_paths.iter() {
+            for token in swap_path.tokens.iter() {
+                token_graph.add_or_get_token_idx_by_token(token.clone());
+            }
+            for pool in swap_path.pools.iter() {
+                for token_address in pool.get_tokens() {
+                    token_graph.add_or_get_token_idx_by_address(token_address);
+                }
+                token_graph.add_pool(pool.clone())?;
+            }
+        }
+        Self::compile(&token_graph, swap_paths)
+    }
+
+    fn compile_path(token_graph: &TokenGraph, swap_path: &SwapPath) -> Result<CompiledPath> {
+        if swap_path.len() > MAX_COMPILED_HOPS {
+            return Err(eyre!("Swap path has {} hops, at most {} are supported", swap_path.len(), MAX_COMPILED_HOPS));
+        }
+        if swap_path.tokens_count() != swap_path.len() + 1 {
+            return Err(eyre!("Swap path has {} tokens for {} hops", swap_path.tokens_count(), swap_path.len()));
+        }
+
+        let mut compiled = CompiledPath { len: swap_path.len() as u8, ..CompiledPath::default() };
+
+        for (i, token) in swap_path.tokens.iter().enumerate() {
+            let node_index =
+                token_graph.token_index.get(&token.get_address()).ok_or_else(|| eyre!("Token not found in graph: {:?}", token.get_address()))?;
+            compiled.tokens[i] = node_index.index() as u32;
+        }
+
+        for (i, pool) in swap_path.pools.iter().enumerate() {
+            let pool_id = pool.get_pool_id();
+            let dense_index = token_graph.get_pool_dense_index(&pool_id).ok_or_else(|| eyre!("Pool not found in graph: {:?}", pool_id))?;
+            compiled.pools[i] = dense_index as u32;
+
+            // Same ordering rule as UniswapV2: token0 is the token with the lower address
+            if swap_path.tokens[i].get_address() < swap_path.tokens[i + 1].get_address() {
+                compiled.directions |= 1 << i;
+            }
+        }
+
+        Ok(compiled)
+    }
+
+    /// The number of compiled paths
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Returns true if there are no compiled paths
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// All compiled paths, in the same order as [CompiledPathSet::swap_paths]
+    pub fn paths(&self) -> &[CompiledPath] {
+        &self.paths
+    }
+
+    pub fn get(&self, path_index: usize) -> Option<&CompiledPath> {
+        self.paths.get(path_index)
+    }
+
+    /// The compiled paths organised by shared hop prefixes
+    pub fn trie(&self) -> &PathTrie {
+        &self.trie
+    }
+
+    /// The original swap paths
+    pub fn swap_paths(&self) -> &[SwapPath] {
+        &self.swap_paths
+    }
+
+    /// The original swap path for a path index
+    pub fn swap_path(&self, path_index: usize) -> Option<&SwapPath> {
+        self.swap_paths.get(path_index)
+    }
+
+    /// The token a path starts with, i.e. the token the input amount is spent in
+    pub fn input_token(&self, path_index: usize) -> Option<Address> {
+        self.swap_paths.get(path_index)?.tokens.first().map(|token| token.get_address())
+    }
+
+    /// The swap path hash for a path index
+    pub fn hash(&self, path_index: usize) -> Option<&SwapPathHash> {
+        self.hashes.get(path_index)
+    }
+
+    /// The number of pools in the dense pool table
+    pub fn pools_count(&self) -> usize {
+        self.pool_ids.len()
+    }
+
+    /// Pool id for a dense pool index
+    pub fn pool_id(&self, pool_index: usize) -> PoolId {
+        self.pool_ids[pool_index]
+    }
+
+    /// Pool ids in dense pool index order
+    pub fn pool_ids(&self) -> &[PoolId] {
+        &self.pool_ids
+    }
+
+    /// Pool fee for a dense pool index
+    pub fn pool_fee(&self, pool_index: usize) -> U256 {
+        self.pool_fees[pool_index]
+    }
+
+    /// Pool class for a dense pool index
+    pub fn pool_class(&self, pool_index: usize) -> PoolClass {
+        self.pool_classes[pool_index]
+    }
+
+    /// Returns true if the pool at a dense pool index supports flash swaps
+    pub fn can_flash_swap(&self, pool_index: usize) -> bool {
+        self.pool_flash_swaps[pool_index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockPool, PoolWrapper, Token};
+    use alloy_primitives::Address;
+    use std::sync::Arc;
+
+    fn create_test_path() -> SwapPath {
+        let token1 = Arc::new(Token::repeat_byte(1));
+        let token2 = Arc::new(Token::repeat_byte(2));
+        let token3 = Arc::new(Token::repeat_byte(3));
+
+        let pool_1_2 = PoolWrapper::from(MockPool::new(token1.get_address(), token2.get_address(), Address::repeat_byte(4)));
+        let pool_2_3 = PoolWrapper::from(MockPool::new(token2.get_address(), token3.get_address(), Address::repeat_byte(5)));
+        let pool_3_1 = PoolWrapper::from(MockPool::new(token3.get_address(), token1.get_address(), Address::repeat_byte(6)));
+
+        SwapPath::new(vec![token1.clone(), token2, token3, token1], vec![pool_1_2, pool_2_3, pool_3_1])
+    }
+
+    #[test]
+    fn test_compile_from_paths() -> Result<()> {
+        let swap_path = create_test_path();
+        let compiled_paths = CompiledPathSet::from_paths(vec![swap_path.clone(), swap_path.invert()])?;
+
+        assert_eq!(compiled_paths.len(), 2);
+        assert_eq!(compiled_paths.pools_count(), 3);
+        assert_eq!(compiled_paths.hash(0), Some(&swap_path.swap_path_hash));
+
+        let compiled = compiled_paths.get(0).unwrap();
+        assert_eq!(compiled.len(), 3);
+        assert_eq!(compiled.tokens().len(), 4);
+        for (hop, (pool_index, _)) in compiled.hops().enumerate() {
+            assert_eq!(compiled_paths.pool_id(pool_index), swap_path.pools[hop].get_pool_id());
+        }
+
+        // 0x01 -> 0x02 and 0x02 -> 0x03 go token0 -> token1, 0x03 -> 0x01 goes the other way
+        assert!(compiled.zero_for_one(0));
+        assert!(compiled.zero_for_one(1));
+        assert!(!compiled.zero_for_one(2));
+
+        // The inverted path uses the same pool table with flipped directions
+        let inverted = compiled_paths.get(1).unwrap();
+        assert_eq!(inverted.pool_index(0), compiled.pool_index(2));
+        assert!(inverted.zero_for_one(0));
+        assert!(!inverted.zero_for_one(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_uses_graph_dense_indices() -> Result<()> {
+        let swap_path = create_test_path();
+        let mut token_graph = TokenGraph::new();
+        for token in swap_path.tokens.iter() {
+            token_graph.add_or_get_token_idx_by_token(token.clone());
+        }
+        // Insert the pools in reverse order so the dense indices differ from the hop order
+        for pool in swap_path.pools.iter().rev() {
+            token_graph.add_pool(pool.clone())?;
+        }
+
+        let compiled_paths = CompiledPathSet::compile(&token_graph, vec![swap_path.clone()])?;
+        let compiled = compiled_paths.get(0).unwrap();
+        assert_eq!(compiled.pools(), &[2, 1, 0]);
+        assert_eq!(token_graph.get_pool_dense_index(&swap_path.pools[0].get_pool_id()), Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_unknown_pool_fails() {
+        let swap_path = create_test_path();
+        let token_graph = TokenGraph::new();
+
+        assert!(CompiledPathSet::compile(&token_graph, vec![swap_path]).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+pub mod compiled_path;
+pub mod path_trie;
 pub mod swap_path;
 pub mod swap_path_hash;
 pub mod swap_path_set;
@@ -5,6 +7,8 @@ pub mod swap_paths_container;
 pub mod spfa_path_builder;
 pub mod token_graph;
 
+pub use compiled_path::{CompiledPath, CompiledPathSet, MAX_COMPILED_HOPS};
+pub use path_trie::{PathTrie, PathTrieNode};
 pub use spfa_path_builder::{find_all_paths_spfa, SPFAPathBuilder};
 pub use token_graph::TokenGraph;
 pub use swap_path::SwapPath;
@@ -0,0 +1,131 @@
+use super::compiled_path::CompiledPath;
+use super::token_graph::FastHashMap;
+
+/// A node of the [PathTrie]: one hop reached through a unique (pool, direction) prefix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathTrieNode {
+    /// Dense pool index of the hop
+    pub pool_index: u32,
+    /// True if the hop swaps reserve0 for reserve1
+    pub zero_for_one: bool,
+    /// Parent node, `None` for first hops
+    pub parent: Option<u32>,
+    /// First-hop node of the branch this node belongs to
+    pub root: u32,
+    /// Zero-based hop number
+    pub depth: u8,
+}
+
+/// Compiled paths organised by their (pool, direction) prefix.
+///
+/// Paths that start with the same hops end in the same branch of the trie, so the output of a
+/// shared prefix only has to be simulated once for a given input amount. Every path maps to the
+/// node of its last hop.
+#[derive(Clone, Debug, Default)]
+pub struct PathTrie {
+    nodes: Vec<PathTrieNode>,
+    // path index -> node of the last hop
+    leaves: Vec<u32>,
+    // path indices ordered so that paths sharing a prefix are adjacent
+    evaluation_order: Vec<usize>,
+}
+
+impl PathTrie {
+    pub fn build(paths: &[CompiledPath]) -> Self {
+        let mut nodes: Vec<PathTrieNode> = Vec::new();
+        let mut children: FastHashMap<(Option<u32>, u32, bool), u32> = FastHashMap::default();
+        let mut leaves = Vec::with_capacity(paths.len());
+
+        for path in paths.iter() {
+            let mut parent: Option<u32> = None;
+            for (depth, (pool_index, zero_for_one)) in path.hops().enumerate() {
+                let pool_index = pool_index as u32;
+                let node_id = *children.entry((parent, pool_index, zero_for_one)).or_insert_with(|| {
+                    let node_id = nodes.len() as u32;
+                    let root = parent.map_or(node_id, |parent| nodes[parent as usize].root);
+                    nodes.push(PathTrieNode { pool_index, zero_for_one, parent, root, depth: depth as u8 });
+                    node_id
+                });
+                parent = Some(node_id);
+            }
+            // Empty paths have no hops to share, they are mapped to a sentinel leaf
+            leaves.push(parent.unwrap_or(u32::MAX));
+        }
+
+        // Sorting by hop sequence is a depth-first walk of the trie
+        let mut evaluation_order: Vec<usize> = (0..paths.len()).collect();
+        evaluation_order.sort_by(|a, b| paths[*a].hops().cmp(paths[*b].hops()));
+
+        Self { nodes, leaves, evaluation_order }
+    }
+
+    /// The number of trie nodes, i.e. the number of distinct hop prefixes
+    pub fn nodes_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[inline]
+    pub fn node(&self, node_id: u32) -> &PathTrieNode {
+        &self.nodes[node_id as usize]
+    }
+
+    /// The node of the last hop of a path
+    #[inline]
+    pub fn leaf(&self, path_index: usize) -> Option<u32> {
+        self.leaves.get(path_index).copied().filter(|leaf| *leaf != u32::MAX)
+    }
+
+    /// Path indices in depth-first order, paths sharing a prefix are adjacent
+    pub fn evaluation_order(&self) -> &[usize] {
+        &self.evaluation_order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::graph::{CompiledPathSet, SwapPath};
+    use crate::{MockPool, PoolWrapper, Token};
+    use alloy_primitives::Address;
+    use eyre::Result;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_trie_shares_prefixes() -> Result<()> {
+        let token1 = Arc::new(Token::repeat_byte(1));
+        let token2 = Arc::new(Token::repeat_byte(2));
+        let token3 = Arc::new(Token::repeat_byte(3));
+
+        let pool_1_2 = PoolWrapper::from(MockPool::new(token1.get_address(), token2.get_address(), Address::repeat_byte(10)));
+        let pool_2_3 = PoolWrapper::from(MockPool::new(token2.get_address(), token3.get_address(), Address::repeat_byte(11)));
+        let pool_3_1 = PoolWrapper::from(MockPool::new(token3.get_address(), token1.get_address(), Address::repeat_byte(12)));
+        let pool_2_1 = PoolWrapper::from(MockPool::new(token2.get_address(), token1.get_address(), Address::repeat_byte(13)));
+
+        let triangle = SwapPath::new(
+            vec![token1.clone(), token2.clone(), token3.clone(), token1.clone()],
+            vec![pool_1_2.clone(), pool_2_3.clone(), pool_3_1.clone()],
+        );
+        let direct = SwapPath::new(vec![token1.clone(), token2.clone(), token1.clone()], vec![pool_1_2.clone(), pool_2_1]);
+        let inverted = triangle.invert();
+
+        let compiled_paths = CompiledPathSet::from_paths(vec![triangle, inverted, direct])?;
+        let trie = compiled_paths.trie();
+
+        // The triangle and the direct path share their first hop: 3 + 3 + 2 - 1 nodes
+        assert_eq!(trie.nodes_count(), 7);
+
+        let triangle_leaf = trie.node(trie.leaf(0).unwrap());
+        let direct_leaf = trie.node(trie.leaf(2).unwrap());
+        assert_eq!(triangle_leaf.depth, 2);
+        assert_eq!(direct_leaf.depth, 1);
+        assert_eq!(triangle_leaf.root, direct_leaf.root);
+        assert_ne!(trie.node(trie.leaf(1).unwrap()).root, direct_leaf.root);
+
+        // Paths with a shared prefix are evaluated next to each other
+        let order = trie.evaluation_order();
+        let triangle_pos = order.iter().position(|path_index| *path_index == 0).unwrap();
+        let direct_pos = order.iter().position(|path_index| *path_index == 2).unwrap();
+        assert_eq!(triangle_pos.abs_diff(direct_pos), 1);
+
+        Ok(())
+    }
+}
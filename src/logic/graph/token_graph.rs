@@ -1,6 +1,7 @@
 use crate::utils::constants::{NATIVE, WMNT};
 use super::spfa_path_builder::SPFAPathBuilder;
 use super::super::pools::pool_id::PoolId;
+use super::super::pools::pool_index::DensePoolIndex;
 use super::swap_path_set::SwapPathSet;
 use crate::{PoolWrapper, Token};
 use super::swap_path::SwapPath;
@@ -30,6 +31,8 @@ pub struct TokenGraph {
     pub token_index: FastHashMap<Address, NodeIndex<usize>>,
     // pool -> edge index (in an edge is a hashmap of pools where the pool is part of)
     pub pool_index: FastHashMap<PoolId, EdgeIndex<usize>>,
+    // pool <-> dense index. Assigned in insertion order and never reused, shared with market snapshots
+    pub pool_dense_index: Arc<DensePoolIndex>,
 }
 
 impl TokenGraph {
@@ -40,9 +43,15 @@ impl TokenGraph {
             tokens: HashMap::default(),
             token_index: FastHashMap::default(),
             pool_index: FastHashMap::default(),
+            pool_dense_index: Arc::new(DensePoolIndex::new()),
         }
     }
 
+    /// Get the dense index of a pool. Dense indices are stable for the lifetime of the graph.
+    pub fn get_pool_dense_index(&self, pool_id: &PoolId) -> Option<usize> {
+        self.pool_dense_index.get(pool_id)
+    }
+
     pub fn set_pool_active(&mut self, pool_id: PoolId, is_active: bool) -> eyre::Result<()> {
         if let Some(edge_index) = self.pool_index.get(&pool_id) {
             let Some(edge) = self.graph.edge_weight_mut(*edge_index) else {
@@ -105,7 +114,11 @@ impl TokenGraph {
             }
         }
 
-        self.pools.insert(pool_wrapper.get_pool_id(), pool_wrapper);
+        let pool_id = pool_wrapper.get_pool_id();
+        if self.pool_dense_index.get(&pool_id).is_none() {
+            Arc::make_mut(&mut self.pool_dense_index).insert(pool_id);
+        }
+        self.pools.insert(pool_id, pool_wrapper);
 
         Ok(())
     }
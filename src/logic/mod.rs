@@ -10,22 +10,38 @@
 /// as described in the design document (方案B).
 
 pub mod arbitrage_engine;
+pub mod engine_config;
+pub mod opportunity_selector;
+pub mod opportunity_tracker;
 pub mod pathfinder;
 pub mod profit_calculator;
+pub mod profit_curve;
+pub mod snapshot_channel;
+pub mod strategy;
 pub mod types;
 pub mod graph;
 pub mod pools;
 
 // Re-export key components from the logic layer
 pub use arbitrage_engine::{ArbitrageEngine, ArbitrageEngineBuilder};
+pub use engine_config::{ArbitrageConfigRoot, ConfigWatcher};
+pub use opportunity_selector::{ExecutionPlan, OpportunitySelector, SelectionMode};
+pub use opportunity_tracker::{DisappearanceReason, OpportunityEvent, OpportunityTracker, TrackedOpportunity};
 pub use pathfinder::Pathfinder;
 pub use profit_calculator::ProfitCalculator;
-pub use types::{ArbitrageOpportunity, MarketSnapshot, ProfitCalculationResult};
+pub use snapshot_channel::{snapshot_channel, DeliveryStats, SendOutcome, SnapshotChannelClosed, SnapshotDelivery, SnapshotReceiver, SnapshotSender};
+pub use strategy::{ArbitrageStrategy, CrossDexSpreadStrategy, CyclicStrategy, PoolChange, StrategyEvaluation, CYCLIC_STRATEGY_NAME};
+pub use profit_curve::{CurveSampling, CurveScale, ProfitCurve, ProfitCurvePoint};
+pub use types::{
+    ArbitrageOpportunity, BlockRef, CalculationCutoff, CalculationFailure, CalculationFailureStats, ExactOutQuote, ExecutionDetails, HopTrace, InventoryCap, InventoryView,
+    MarketSnapshot,
+    ProfitCalculationBatch, ProfitCalculationResult, ReorgEvent, TruncationReason,
+};
 pub use graph::{
-    SwapPath, SwapPathHash, SwapPathsContainer, add_swap_path, remove_pool,
+    CompiledPath, CompiledPathSet, SwapPath, SwapPathHash, SwapPathsContainer, add_swap_path, remove_pool,
     find_all_paths_spfa, SPFAPathBuilder, TokenGraph
 };
 pub use pools::{
-    AbiSwapEncoder, CalculationError, Pool, PoolClass, PoolProtocol, PoolWrapper, 
+    AbiSwapEncoder, CalculationError, CalculationErrorKind, Pool, PoolClass, PoolProtocol, PoolWrapper, 
     PreswapRequirement, get_protocol_by_factory, PoolId, MockPool
 };
@@ -0,0 +1,255 @@
+use super::pools::PoolId;
+use super::types::ArbitrageOpportunity;
+use alloy_primitives::U256;
+use serde::Deserialize;
+use std::collections::HashSet;
+use tracing::debug;
+
+/// Upper bound for the exact search, its worst case is exponential in the number of candidates
+/// and it runs for every block
+pub const MAX_EXACT_CANDIDATES: usize = 20;
+
+/// How the opportunities of a block are combined into an execution plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionMode {
+    /// Take opportunities by descending robust net profit, skipping those that touch a pool already taken
+    #[default]
+    Greedy,
+    /// Branch and bound over the `max_candidates` most profitable opportunities, the rest is
+    /// filled in greedily. Never worse than [SelectionMode::Greedy]. At most
+    /// [MAX_EXACT_CANDIDATES].
+    Exact { max_candidates: usize },
+}
+
+/// Pool-disjoint opportunities of one block, in execution order
+///
+/// Executing an opportunity moves the reserves of its pools and invalidates every other
+/// opportunity that shares one of them. Opportunities of a plan never share a pool, so each
+/// one is still valid after the previous ones were executed. They are ordered by descending
+/// robust net profit, the profit under the slippage tolerance that filtered them, so that the
+/// most valuable trade lands first.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionPlan {
+    pub block_number: u64,
+    pub mode: SelectionMode,
+    pub steps: Vec<ArbitrageOpportunity>,
+    pub total_net_profit_mnt_wei: U256,
+    /// Number of opportunities the plan was selected from
+    pub candidates_count: usize,
+}
+
+impl ExecutionPlan {
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Number of candidates left out because they conflicted with the plan
+    pub fn dropped_count(&self) -> usize {
+        self.candidates_count - self.steps.len()
+    }
+}
+
+/// Picks a pool-disjoint subset of opportunities maximising the total net profit
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpportunitySelector {
+    mode: SelectionMode,
+}
+
+impl OpportunitySelector {
+    pub fn new(mode: SelectionMode) -> Self {
+        Self { mode }
+    }
+
+    pub fn mode(&self) -> SelectionMode {
+        self.mode
+    }
+
+    pub fn select(&self, block_number: u64, mut opportunities: Vec<ArbitrageOpportunity>) -> ExecutionPlan {
+        let candidates_count = opportunities.len();
+        opportunities.sort_by_key(|opportunity| std::cmp::Reverse(opportunity.execution.robust_net_profit_mnt_wei));
+        let profits: Vec<U256> = opportunities.iter().map(|opportunity| opportunity.execution.robust_net_profit_mnt_wei).collect();
+        let pools: Vec<Vec<PoolId>> = opportunities.iter().map(|opportunity| opportunity.path.pools_map.iter().copied().collect()).collect();
+
+        let mut selected = match self.mode {
+            SelectionMode::Greedy => select_greedy(&pools, &[]),
+            SelectionMode::Exact { max_candidates } => {
+                // ArbitrageConfig::validate rejects larger windows, the bound only guards other callers
+                let exact = select_exact(&profits, &pools, max_candidates.min(MAX_EXACT_CANDIDATES));
+                let exact = select_greedy(&pools, &exact);
+                let greedy = select_greedy(&pools, &[]);
+                if total_profit(&profits, &exact) >= total_profit(&profits, &greedy) { exact } else { greedy }
+            }
+        };
+
+        let total_net_profit_mnt_wei = selected
+            .iter()
+            .fold(U256::ZERO, |total, &index| total.saturating_add(opportunities[index].net_profit_mnt_wei));
+        // Candidates are sorted by profit, so sorted indices keep the execution order
+        selected.sort_unstable();
+        let mut selected = selected.into_iter().peekable();
+        let steps: Vec<ArbitrageOpportunity> = opportunities
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, opportunity)| selected.next_if_eq(&index).map(|_| opportunity))
+            .collect();
+
+        debug!(
+            "区块 {} 选择了 {}/{} 个互不冲突的套利机会，总净利润 {} wei",
+            block_number,
+            steps.len(),
+            candidates_count,
+            total_net_profit_mnt_wei
+        );
+
+        ExecutionPlan { block_number, mode: self.mode, steps, total_net_profit_mnt_wei, candidates_count }
+    }
+}
+
+fn total_profit(profits: &[U256], selected: &[usize]) -> U256 {
+    selected.iter().fold(U256::ZERO, |total, &index| total.saturating_add(profits[index]))
+}
+
+/// Extend `preselected` with every candidate, in order, that does not share a pool with the selection
+fn select_greedy(pools: &[Vec<PoolId>], preselected: &[usize]) -> Vec<usize> {
+    let mut used_pools: HashSet<PoolId> = preselected.iter().flat_map(|&index| pools[index].iter().copied()).collect();
+    let mut selected = preselected.to_vec();
+
+    for (index, candidate_pools) in pools.iter().enumerate() {
+        if preselected.contains(&index) || candidate_pools.iter().any(|pool_id| used_pools.contains(pool_id)) {
+            continue;
+        }
+        used_pools.extend(candidate_pools.iter().copied());
+        selected.push(index);
+    }
+
+    selected
+}
+
+/// Best pool-disjoint subset of the first `max_candidates` candidates by `profits`
+fn select_exact(profits: &[U256], pools: &[Vec<PoolId>], max_candidates: usize) -> Vec<usize> {
+    let n = profits.len().min(max_candidates);
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let profits = &profits[..n];
+    let conflicts: Vec<u64> = (0..n)
+        .map(|i| {
+            (0..n)
+                .filter(|&j| i != j && pools[i].iter().any(|pool_id| pools[j].contains(pool_id)))
+                .fold(0u64, |mask, j| mask | (1 << j))
+        })
+        .collect();
+
+    // remaining[i] bounds what candidates i.. can still add
+    let mut remaining = vec![U256::ZERO; n + 1];
+    for i in (0..n).rev() {
+        remaining[i] = remaining[i + 1].saturating_add(profits[i]);
+    }
+
+    let mut search = ExactSearch { profits, conflicts: &conflicts, remaining: &remaining, best_mask: 0, best_profit: U256::ZERO };
+    search.branch(0, 0, U256::ZERO);
+
+    (0..n).filter(|i| search.best_mask & (1 << i) != 0).collect()
+}
+
+struct ExactSearch<'a> {
+    profits: &'a [U256],
+    conflicts: &'a [u64],
+    remaining: &'a [U256],
+    best_mask: u64,
+    best_profit: U256,
+}
+
+impl ExactSearch<'_> {
+    fn branch(&mut self, index: usize, mask: u64, profit: U256) {
+        if profit > self.best_profit {
+            self.best_profit = profit;
+            self.best_mask = mask;
+        }
+        if index == self.profits.len() || profit.saturating_add(self.remaining[index]) <= self.best_profit {
+            return;
+        }
+
+        // Taking the candidate first finds good solutions early and tightens the bound
+        if self.conflicts[index] & mask == 0 {
+            self.branch(index + 1, mask | (1 << index), profit.saturating_add(self.profits[index]));
+        }
+        self.branch(index + 1, mask, profit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::graph::SwapPath;
+    use crate::logic::types::ExecutionDetails;
+    use crate::{MockPool, PoolWrapper, Token};
+    use alloy_primitives::Address;
+    use std::sync::Arc;
+
+    fn opportunity(pool_bytes: &[u8], net_profit: u64) -> ArbitrageOpportunity {
+        let tokens: Vec<Arc<Token>> = (0..=pool_bytes.len()).map(|i| Arc::new(Token::repeat_byte(i as u8 + 1))).collect();
+        let pools: Vec<PoolWrapper> = pool_bytes
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| PoolWrapper::from(MockPool::new(tokens[i].get_address(), tokens[i + 1].get_address(), Address::repeat_byte(byte))))
+            .collect();
+        let path = SwapPath::new(tokens, pools);
+        ArbitrageOpportunity::new(path, U256::from(1000), U256::from(1000 + net_profit), U256::from(net_profit), U256::ZERO)
+    }
+
+    /// A conflicts with both B and C, which are disjoint: greedy takes A, the optimum is B + C
+    fn greedy_trap() -> Vec<ArbitrageOpportunity> {
+        vec![opportunity(&[20, 21], 7), opportunity(&[10, 20], 10), opportunity(&[10, 30], 6), opportunity(&[40], 1)]
+    }
+
+    #[test]
+    fn test_greedy_selection_is_pool_disjoint() {
+        let plan = OpportunitySelector::new(SelectionMode::Greedy).select(7, greedy_trap());
+
+        assert_eq!(plan.block_number, 7);
+        assert_eq!(plan.candidates_count, 4);
+        let profits: Vec<U256> = plan.steps.iter().map(|step| step.net_profit_mnt_wei).collect();
+        assert_eq!(profits, vec![U256::from(10), U256::from(1)]);
+        assert_eq!(plan.total_net_profit_mnt_wei, U256::from(11));
+        assert_eq!(plan.dropped_count(), 2);
+    }
+
+    #[test]
+    fn test_exact_selection_beats_greedy() {
+        let plan = OpportunitySelector::new(SelectionMode::Exact { max_candidates: 16 }).select(7, greedy_trap());
+
+        // Ordered by profit, and the opportunity beyond the exact window is still filled in
+        let profits: Vec<U256> = plan.steps.iter().map(|step| step.net_profit_mnt_wei).collect();
+        assert_eq!(profits, vec![U256::from(7), U256::from(6), U256::from(1)]);
+        assert_eq!(plan.total_net_profit_mnt_wei, U256::from(14));
+
+        let mut used_pools = HashSet::new();
+        for step in &plan.steps {
+            assert!(step.path.pools_map.iter().all(|pool_id| used_pools.insert(*pool_id)));
+        }
+
+        // A window too small to see the trap falls back to the greedy plan
+        let plan = OpportunitySelector::new(SelectionMode::Exact { max_candidates: 1 }).select(7, greedy_trap());
+        assert_eq!(plan.total_net_profit_mnt_wei, U256::from(11));
+    }
+
+    #[test]
+    fn test_selection_ranks_by_robust_profit() {
+        // The larger net profit only holds without slippage, the other one survives it
+        let fragile = opportunity(&[10, 20], 10).with_execution(ExecutionDetails::exact(U256::from(1000), U256::from(1002), U256::ZERO));
+        let candidates = vec![fragile, opportunity(&[20, 30], 7)];
+
+        for mode in [SelectionMode::Greedy, SelectionMode::Exact { max_candidates: 16 }] {
+            let plan = OpportunitySelector::new(mode).select(7, candidates.clone());
+            let profits: Vec<U256> = plan.steps.iter().map(|step| step.net_profit_mnt_wei).collect();
+            assert_eq!(profits, vec![U256::from(7)]);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use super::graph::SwapPathHash;
+use super::pools::CalculationErrorKind;
+use super::types::{ArbitrageOpportunity, ProfitCalculationResult};
+use alloy_primitives::U256;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Why a tracked opportunity stopped being reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisappearanceReason {
+    /// The path was evaluated successfully but no longer clears the profit threshold
+    BelowThreshold,
+    /// The path was evaluated and has no profitable input amount any more
+    Unprofitable,
+    /// The path could not be evaluated, e.g. reserves were missing from the snapshot
+    CalculationFailed(CalculationErrorKind),
+    /// The path was not part of the evaluated results
+    NotEvaluated,
+    /// Dropped to keep the tracker within its capacity
+    Evicted,
+    /// Last reported in a block that was orphaned by a chain reorganization
+    Reorged,
+    /// Dropped after reorg notifications were missed, any tracked block may have been orphaned
+    ReorgsMissed,
+}
+
+/// An opportunity followed across blocks, identified by the hash of its path
+#[derive(Debug, Clone)]
+pub struct TrackedOpportunity {
+    pub id: SwapPathHash,
+    pub first_seen_block: u64,
+    pub last_seen_block: u64,
+    pub first_seen_at: Instant,
+    pub last_seen_at: Instant,
+    /// Number of blocks the opportunity was reported in, gaps are not counted
+    pub blocks_seen: u64,
+    pub peak_net_profit_mnt_wei: U256,
+    /// The opportunity as reported in `last_seen_block`
+    pub latest: ArbitrageOpportunity,
+}
+
+impl TrackedOpportunity {
+    fn new(block_number: u64, opportunity: ArbitrageOpportunity) -> Self {
+        let now = Instant::now();
+        Self {
+            id: opportunity.path.swap_path_hash.clone(),
+            first_seen_block: block_number,
+            last_seen_block: block_number,
+            first_seen_at: now,
+            last_seen_at: now,
+            blocks_seen: 1,
+            peak_net_profit_mnt_wei: opportunity.net_profit_mnt_wei,
+            latest: opportunity,
+        }
+    }
+
+    /// Number of blocks between the first and the last sighting, both included
+    pub fn lifetime_blocks(&self) -> u64 {
+        self.last_seen_block - self.first_seen_block + 1
+    }
+
+    /// Wall-clock time between the first and the last sighting
+    pub fn lifetime(&self) -> Duration {
+        self.last_seen_at.duration_since(self.first_seen_at)
+    }
+}
+
+/// Change of the tracked opportunities caused by one block
+#[derive(Debug, Clone)]
+pub enum OpportunityEvent {
+    /// Reported for the first time, or again after it expired
+    New(TrackedOpportunity),
+    /// Reported again with a different net profit or input amount
+    Updated(TrackedOpportunity),
+    /// No longer reported
+    Expired { opportunity: TrackedOpportunity, reason: DisappearanceReason },
+}
+
+impl OpportunityEvent {
+    pub fn opportunity(&self) -> &TrackedOpportunity {
+        match self {
+            OpportunityEvent::New(opportunity)
+            | OpportunityEvent::Updated(opportunity)
+            | OpportunityEvent::Expired { opportunity, .. } => opportunity,
+        }
+    }
+}
+
+/// Follows opportunities from block to block and reports only what changed
+///
+/// Opportunities are keyed by their [SwapPathHash]. An opportunity reported in consecutive
+/// blocks stays the same tracked opportunity; one missing from a block expires with the reason
+/// taken from that block's profit results. The number of tracked opportunities is bounded, the
+/// least recently seen one is evicted first.
+#[derive(Debug)]
+pub struct OpportunityTracker {
+    tracked: HashMap<SwapPathHash, TrackedOpportunity>,
+    max_tracked: usize,
+}
+
+impl OpportunityTracker {
+    pub fn new(max_tracked: usize) -> Self {
+        Self { tracked: HashMap::new(), max_tracked: max_tracked.max(1) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracked.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracked.is_empty()
+    }
+
+    pub fn max_tracked(&self) -> usize {
+        self.max_tracked
+    }
+
+    /// Change the capacity, opportunities above it are evicted as new ones arrive
+    pub fn set_max_tracked(&mut self, max_tracked: usize) {
+        self.max_tracked = max_tracked.max(1);
+    }
+
+    pub fn get(&self, id: &SwapPathHash) -> Option<&TrackedOpportunity> {
+        self.tracked.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TrackedOpportunity> {
+        self.tracked.values()
+    }
+
+    /// Record the opportunities of a block and return the resulting events
+    ///
+    /// `results` are the profit results the opportunities were selected from, they explain why a
+    /// tracked opportunity disappeared. Expired events come first, then new and updated ones.
+    pub fn update(
+        &mut self,
+        block_number: u64,
+        opportunities: Vec<ArbitrageOpportunity>,
+        results: &[ProfitCalculationResult],
+    ) -> Vec<OpportunityEvent> {
+        self.apply(block_number, opportunities, results, true)
+    }
+
+    /// Like [OpportunityTracker::update] for a block whose evaluation was truncated
+    ///
+    /// Tracked opportunities without a result in `results` were not evaluated in this block,
+    /// they are kept as they are instead of expiring.
+    pub fn update_truncated(
+        &mut self,
+        block_number: u64,
+        opportunities: Vec<ArbitrageOpportunity>,
+        results: &[ProfitCalculationResult],
+    ) -> Vec<OpportunityEvent> {
+        self.apply(block_number, opportunities, results, false)
+    }
+
+    fn apply(
+        &mut self,
+        block_number: u64,
+        opportunities: Vec<ArbitrageOpportunity>,
+        results: &[ProfitCalculationResult],
+        expire_unevaluated: bool,
+    ) -> Vec<OpportunityEvent> {
+        let reported: HashMap<SwapPathHash, ArbitrageOpportunity> =
+            opportunities.into_iter().map(|opportunity| (opportunity.path.swap_path_hash.clone(), opportunity)).collect();
+
+        let disappeared: Vec<SwapPathHash> = self.tracked.keys().filter(|id| !reported.contains_key(*id)).cloned().collect();
+        let mut events = Vec::with_capacity(disappeared.len() + reported.len());
+        if !disappeared.is_empty() {
+            let reasons: HashMap<&SwapPathHash, DisappearanceReason> = results
+                .iter()
+                .filter(|result| self.tracked.contains_key(&result.swap_path_hash))
+                .map(|result| (&result.swap_path_hash, disappearance_reason(result)))
+                .collect();
+            for id in disappeared {
+                let reason = match reasons.get(&id) {
+                    Some(reason) => *reason,
+                    None if expire_unevaluated => DisappearanceReason::NotEvaluated,
+                    None => continue,
+                };
+                if let Some(opportunity) = self.tracked.remove(&id) {
+                    events.push(OpportunityEvent::Expired { opportunity, reason });
+                }
+            }
+        }
+
+        // Refresh known opportunities first, eviction then only picks one seen in this block if all were
+        let now = Instant::now();
+        let (known, new): (Vec<_>, Vec<_>) = reported.into_iter().partition(|(id, _)| self.tracked.contains_key(id));
+        for (id, opportunity) in known {
+            let Some(tracked) = self.tracked.get_mut(&id) else { continue };
+            let changed = tracked.latest.net_profit_mnt_wei != opportunity.net_profit_mnt_wei
+                || tracked.latest.optimal_input_amount != opportunity.optimal_input_amount;
+            tracked.last_seen_block = block_number;
+            tracked.last_seen_at = now;
+            tracked.blocks_seen += 1;
+            tracked.peak_net_profit_mnt_wei = tracked.peak_net_profit_mnt_wei.max(opportunity.net_profit_mnt_wei);
+            tracked.latest = opportunity;
+            if changed {
+                events.push(OpportunityEvent::Updated(tracked.clone()));
+            }
+        }
+        for (id, opportunity) in new {
+            while self.tracked.len() >= self.max_tracked
+                && let Some(evicted) = self.evict_least_recently_seen()
+            {
+                events.push(OpportunityEvent::Expired { opportunity: evicted, reason: DisappearanceReason::Evicted });
+            }
+            let tracked = TrackedOpportunity::new(block_number, opportunity);
+            events.push(OpportunityEvent::New(tracked.clone()));
+            self.tracked.insert(id, tracked);
+        }
+
+        debug!("区块 {} 机会跟踪: {} 个事件, 跟踪中 {}", block_number, events.len(), self.tracked.len());
+        events
+    }
+
+    /// Expire the opportunities last reported in `first_orphaned_block` or later, they may
+    /// only exist on an orphaned fork and are reported as new if the canonical chain still has them
+    pub fn invalidate_from(&mut self, first_orphaned_block: u64) -> Vec<OpportunityEvent> {
+        let orphaned: Vec<SwapPathHash> = self
+            .tracked
+            .values()
+            .filter(|tracked| tracked.last_seen_block >= first_orphaned_block)
+            .map(|tracked| tracked.id.clone())
+            .collect();
+        orphaned
+            .into_iter()
+            .filter_map(|id| self.tracked.remove(&id))
+            .map(|opportunity| OpportunityEvent::Expired { opportunity, reason: DisappearanceReason::Reorged })
+            .collect()
+    }
+
+    /// Drop every tracked opportunity, the returned events report them
+    pub fn expire_all(&mut self, reason: DisappearanceReason) -> Vec<OpportunityEvent> {
+        self.tracked
+            .drain()
+            .map(|(_, opportunity)| OpportunityEvent::Expired { opportunity, reason })
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.tracked.clear();
+    }
+
+    fn evict_least_recently_seen(&mut self) -> Option<TrackedOpportunity> {
+        let id = self.tracked.values().min_by_key(|tracked| (tracked.last_seen_block, tracked.first_seen_block))?.id.clone();
+        self.tracked.remove(&id)
+    }
+}
+
+fn disappearance_reason(result: &ProfitCalculationResult) -> DisappearanceReason {
+    match &result.error {
+        Some(failure) if failure.kind == CalculationErrorKind::Unprofitable => DisappearanceReason::Unprofitable,
+        Some(failure) => DisappearanceReason::CalculationFailed(failure.kind),
+        None => DisappearanceReason::BelowThreshold,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::graph::SwapPath;
+    use crate::logic::pools::CalculationError;
+    use crate::{MockPool, PoolId, PoolWrapper, Token};
+    use alloy_primitives::Address;
+    use std::sync::Arc;
+
+    fn opportunity(pool_byte: u8, net_profit: u64) -> ArbitrageOpportunity {
+        let token1 = Arc::new(Token::repeat_byte(1));
+        let token2 = Arc::new(Token::repeat_byte(2));
+        let pool_a = PoolWrapper::from(MockPool::new(token1.get_address(), token2.get_address(), Address::repeat_byte(pool_byte)));
+        let pool_b = PoolWrapper::from(MockPool::new(token2.get_address(), token1.get_address(), Address::repeat_byte(pool_byte + 1)));
+        let path = SwapPath::new(vec![token1.clone(), token2, token1], vec![pool_a, pool_b]);
+        ArbitrageOpportunity::new(path, U256::from(1000), U256::from(1000 + net_profit), U256::from(net_profit), U256::ZERO)
+    }
+
+    #[test]
+    fn test_tracker_lifecycle() {
+        let mut tracker = OpportunityTracker::new(10);
+        let first = opportunity(10, 5);
+        let second = opportunity(20, 7);
+        let first_id = first.path.swap_path_hash.clone();
+        let second_id = second.path.swap_path_hash.clone();
+
+        let events = tracker.update(100, vec![first.clone(), second.clone()], &[]);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| matches!(event, OpportunityEvent::New(_))));
+
+        // Same numbers again: nothing to report
+        assert!(tracker.update(101, vec![first.clone(), second.clone()], &[]).is_empty());
+
+        // The first one gets better, the second one turns unprofitable
+        let unprofitable = CalculationError::Unprofitable { amount_in: U256::from(1), amount_out: U256::ZERO };
+        let results = vec![ProfitCalculationResult::failure(1, second_id.clone(), &unprofitable)];
+        let events = tracker.update(102, vec![opportunity(10, 9)], &results);
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            OpportunityEvent::Expired { opportunity, reason } => {
+                assert_eq!(opportunity.id, second_id);
+                assert_eq!(*reason, DisappearanceReason::Unprofitable);
+                assert_eq!(opportunity.lifetime_blocks(), 2);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        match &events[1] {
+            OpportunityEvent::Updated(tracked) => {
+                assert_eq!(tracked.id, first_id);
+                assert_eq!(tracked.first_seen_block, 100);
+                assert_eq!(tracked.blocks_seen, 3);
+                assert_eq!(tracked.peak_net_profit_mnt_wei, U256::from(9));
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        // Missing reserves are reported as a data problem, a path without results as not evaluated
+        let missing = CalculationError::MissingState(PoolId::Address(Address::repeat_byte(11)));
+        let events = tracker.update(103, vec![], &[ProfitCalculationResult::failure(0, first_id.clone(), &missing)]);
+        assert!(matches!(
+            events[0],
+            OpportunityEvent::Expired { reason: DisappearanceReason::CalculationFailed(CalculationErrorKind::MissingState), .. }
+        ));
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_tracker_evicts_least_recently_seen() {
+        let mut tracker = OpportunityTracker::new(2);
+        tracker.update(1, vec![opportunity(10, 1)], &[]);
+        tracker.update(2, vec![opportunity(10, 1), opportunity(20, 1)], &[]);
+
+        let events = tracker.update(2, vec![opportunity(10, 1), opportunity(20, 1), opportunity(30, 1)], &[]);
+        let evicted: Vec<_> = events
+            .iter()
+            .filter(|event| matches!(event, OpportunityEvent::Expired { reason: DisappearanceReason::Evicted, .. }))
+            .collect();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(tracker.len(), 2);
+    }
+
+    #[test]
+    fn test_tracker_invalidates_orphaned_blocks() {
+        let mut tracker = OpportunityTracker::new(8);
+        tracker.update(10, vec![opportunity(10, 1), opportunity(20, 1)], &[]);
+        tracker.update_truncated(11, vec![opportunity(20, 1)], &[]);
+        tracker.update_truncated(12, vec![opportunity(30, 1)], &[]);
+
+        // Block 11 onwards was orphaned: only the opportunity last seen at block 10 survives
+        let events = tracker.invalidate_from(11);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| matches!(event, OpportunityEvent::Expired { reason: DisappearanceReason::Reorged, .. })));
+        assert_eq!(tracker.iter().map(|tracked| tracked.last_seen_block).collect::<Vec<_>>(), vec![10]);
+    }
+}
@@ -1,10 +1,12 @@
 pub mod pool;
 pub mod pool_id;
+pub mod pool_index;
 pub mod mock_pool;
 
 pub use pool::{
-    AbiSwapEncoder, CalculationError, Pool, PoolClass, PoolProtocol, PoolWrapper, 
+    AbiSwapEncoder, CalculationError, CalculationErrorKind, Pool, PoolClass, PoolProtocol, PoolWrapper, 
     PreswapRequirement, get_protocol_by_factory,
 };
 pub use pool_id::PoolId;
+pub use pool_index::{DensePoolIndex, PoolBitSet};
 pub use mock_pool::MockPool;
\ No newline at end of file
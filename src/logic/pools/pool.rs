@@ -121,6 +121,68 @@ pub enum CalculationError {
     Error(#[from] eyre::Report),
     #[error("Not implemented")]
     NotImplemented,
+    #[error("Missing state for pool {0:?}")]
+    MissingState(PoolId),
+    #[error("Zero liquidity in pool {0:?}")]
+    ZeroLiquidity(PoolId),
+    #[error("Arithmetic overflow in pool {0:?}")]
+    Overflow(PoolId),
+    #[error("Unsupported pool {0:?}")]
+    UnsupportedPool(PoolId),
+    #[error("Unprofitable: amount in {amount_in}, amount out {amount_out}")]
+    Unprofitable { amount_in: U256, amount_out: U256 },
+    #[error("Amount out {amount_out} exceeds reserve {reserve_out} of pool {pool_id:?}")]
+    ExceedsReserves { pool_id: PoolId, amount_out: U256, reserve_out: U256 },
+    #[error("Missing executor balance of token {0:?}")]
+    MissingBalance(Address),
+}
+
+impl CalculationError {
+    pub fn kind(&self) -> CalculationErrorKind {
+        match self {
+            CalculationError::MissingState(_) => CalculationErrorKind::MissingState,
+            CalculationError::ZeroLiquidity(_) => CalculationErrorKind::ZeroLiquidity,
+            CalculationError::Overflow(_) => CalculationErrorKind::Overflow,
+            CalculationError::UnsupportedPool(_) => CalculationErrorKind::UnsupportedPool,
+            CalculationError::Unprofitable { .. } => CalculationErrorKind::Unprofitable,
+            CalculationError::ExceedsReserves { .. } => CalculationErrorKind::ExceedsReserves,
+            CalculationError::MissingBalance(_) => CalculationErrorKind::MissingBalance,
+            CalculationError::AlloySolError(_) | CalculationError::Error(_) | CalculationError::NotImplemented => CalculationErrorKind::Other,
+        }
+    }
+
+    /// The pool the error was raised for, if it is specific to one pool
+    pub fn pool_id(&self) -> Option<PoolId> {
+        match self {
+            CalculationError::MissingState(pool_id)
+            | CalculationError::ZeroLiquidity(pool_id)
+            | CalculationError::Overflow(pool_id)
+            | CalculationError::UnsupportedPool(pool_id)
+            | CalculationError::ExceedsReserves { pool_id, .. } => Some(*pool_id),
+            _ => None,
+        }
+    }
+}
+
+/// Fieldless discriminant of [CalculationError], used for aggregation
+#[derive(Copy, Clone, Debug, Display, PartialEq, Eq, Hash, PartialOrd, Ord, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum CalculationErrorKind {
+    MissingState,
+    ZeroLiquidity,
+    Overflow,
+    UnsupportedPool,
+    Unprofitable,
+    ExceedsReserves,
+    MissingBalance,
+    Other,
+}
+
+impl CalculationErrorKind {
+    /// Returns true if the failure points at bad or missing market data rather than a path that is simply not profitable
+    pub fn is_data_error(&self) -> bool {
+        !matches!(self, CalculationErrorKind::Unprofitable)
+    }
 }
 
 #[typetag::serde(tag = "type")]
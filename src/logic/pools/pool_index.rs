@@ -0,0 +1,143 @@
+use super::pool_id::PoolId;
+use ahash::RandomState;
+use std::collections::HashMap;
+
+/// Stable dense pool index layout.
+///
+/// Every pool gets the next free index when it is first inserted and keeps it for the lifetime
+/// of the index, so flat vectors (compiled paths, snapshot pool states, bitsets) can be addressed
+/// by it. The layout is shared between the market and the snapshots it produces via `Arc`.
+#[derive(Clone, Debug, Default)]
+pub struct DensePoolIndex {
+    // dense index -> pool id
+    pool_ids: Vec<PoolId>,
+    // pool id -> dense index
+    index: HashMap<PoolId, usize, RandomState>,
+}
+
+impl DensePoolIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a pool and return its dense index. Existing pools keep their index.
+    pub fn insert(&mut self, pool_id: PoolId) -> usize {
+        if let Some(&index) = self.index.get(&pool_id) {
+            return index;
+        }
+        let index = self.pool_ids.len();
+        self.pool_ids.push(pool_id);
+        self.index.insert(pool_id, index);
+        index
+    }
+
+    /// Get the dense index of a pool
+    pub fn get(&self, pool_id: &PoolId) -> Option<usize> {
+        self.index.get(pool_id).copied()
+    }
+
+    /// Get the pool id at a dense index
+    pub fn pool_id(&self, index: usize) -> Option<PoolId> {
+        self.pool_ids.get(index).copied()
+    }
+
+    /// All pool ids in dense index order
+    pub fn pool_ids(&self) -> &[PoolId] {
+        &self.pool_ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.pool_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool_ids.is_empty()
+    }
+}
+
+/// Fixed-size bitset addressed by dense pool index
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolBitSet {
+    words: Vec<u64>,
+}
+
+impl PoolBitSet {
+    /// Create a bitset with room for `len` pools, all bits cleared
+    pub fn with_len(len: usize) -> Self {
+        Self { words: vec![0; len.div_ceil(64)] }
+    }
+
+    /// Set a bit, growing the bitset if needed
+    pub fn insert(&mut self, index: usize) {
+        let word = index / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (index % 64);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if let Some(word) = self.words.get_mut(index / 64) {
+            *word &= !(1 << (index % 64));
+        }
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.words.get(index / 64).is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|word| *word = 0);
+    }
+
+    /// Number of set bits
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Iterate over the indices of all set bits in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(word_idx, &word)| {
+            (0..64).filter(move |bit| word & (1 << bit) != 0).map(move |bit| word_idx * 64 + bit)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::Address;
+
+    #[test]
+    fn test_dense_pool_index_is_stable() {
+        let mut index = DensePoolIndex::new();
+        let pool_a = PoolId::Address(Address::repeat_byte(1));
+        let pool_b = PoolId::Address(Address::repeat_byte(2));
+
+        assert_eq!(index.insert(pool_a), 0);
+        assert_eq!(index.insert(pool_b), 1);
+        assert_eq!(index.insert(pool_a), 0);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(&pool_b), Some(1));
+        assert_eq!(index.pool_id(1), Some(pool_b));
+        assert_eq!(index.pool_ids(), &[pool_a, pool_b]);
+    }
+
+    #[test]
+    fn test_pool_bitset() {
+        let mut bits = PoolBitSet::with_len(10);
+        bits.insert(3);
+        bits.insert(130);
+
+        assert!(bits.contains(3));
+        assert!(bits.contains(130));
+        assert!(!bits.contains(4));
+        assert!(!bits.contains(1000));
+        assert_eq!(bits.count(), 2);
+        assert_eq!(bits.iter().collect::<Vec<_>>(), vec![3, 130]);
+
+        bits.remove(3);
+        assert!(!bits.contains(3));
+        assert_eq!(bits.count(), 1);
+    }
+}
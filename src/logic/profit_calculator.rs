@@ -1,9 +1,14 @@
-use super::graph::SwapPath;
-use super::types::{ArbitrageConfig, MarketSnapshot, ProfitCalculationResult};
-use super::pools::CalculationError;
-use alloy_primitives::{Address, U256};
+use super::graph::{CompiledPath, CompiledPathSet, MAX_COMPILED_HOPS, SwapPath};
+use super::types::{
+    ArbitrageConfig, CalculationCutoff, ExactOutQuote, ExecutionDetails, HopTrace, InventoryCap, InventoryView, MarketSnapshot, PoolState, ProfitCalculationBatch,
+    ProfitCalculationResult,
+};
+use super::pools::{CalculationError, PoolClass, PoolId};
+use super::profit_curve::{CurveSampling, ProfitCurve};
+use alloy_primitives::{I256, U256};
 use eyre::Result;
 use rayon::prelude::*;
+use std::sync::OnceLock;
 use tracing::debug;
 
 /// ProfitCalculator is the "hot path" component responsible for high-speed profit evaluation
@@ -13,62 +18,117 @@ use tracing::debug;
 /// maximum performance with parallel processing capabilities.
 pub struct ProfitCalculator {
     config: ArbitrageConfig,
+    /// Executor balances that cap the input amount, unlimited if not set
+    inventory: Option<InventoryView>,
 }
 
 impl ProfitCalculator {
     pub fn new(config: ArbitrageConfig) -> Self {
-        Self { config }
+        Self { config, inventory: None }
+    }
+
+    /// Cap input amounts at the executor's inventory
+    pub fn with_inventory(mut self, inventory: InventoryView) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    pub fn set_inventory(&mut self, inventory: Option<InventoryView>) {
+        self.inventory = inventory;
+    }
+
+    pub fn inventory(&self) -> Option<&InventoryView> {
+        self.inventory.as_ref()
     }
 
     /// Calculate profits for all paths in parallel
     /// 
-    /// This is the main entry point for profit calculation. It takes a list of 
+    /// This is the main entry point for profit calculation. It takes the compiled
     /// pre-computed paths and the latest market snapshot, then calculates profits
     /// for all paths in parallel using Rayon.
     pub fn calculate_profits_parallel(
         &self,
-        paths: &[SwapPath],
+        paths: &CompiledPathSet,
         market_snapshot: &MarketSnapshot,
     ) -> Vec<ProfitCalculationResult> {
+        self.calculate_profits_with_cutoff(paths, market_snapshot, &CalculationCutoff::default()).results
+    }
+
+    /// Calculate profits for all paths, giving up once `cutoff` is reached
+    ///
+    /// The cutoff is checked before each chunk of paths. A truncated batch holds the results of
+    /// the chunks evaluated until then, the other paths have no result.
+    pub fn calculate_profits_with_cutoff(
+        &self,
+        paths: &CompiledPathSet,
+        market_snapshot: &MarketSnapshot,
+        cutoff: &CalculationCutoff,
+    ) -> ProfitCalculationBatch {
         if !self.config.enable_parallel_calculation {
-            return self.calculate_profits_sequential(paths, market_snapshot);
+            return self.calculate_profits_sequential(paths, market_snapshot, cutoff);
         }
 
         debug!("开始并行利润计算，路径数量: {}", paths.len());
 
-        let results: Vec<ProfitCalculationResult> = paths
-            .par_iter() // <-- Rayon's parallel iterator
-            .map(|path| {
-                self.calculate_path_profit(path, market_snapshot)
+        let pool_states = PoolStateView::new(paths, market_snapshot);
+        let truncation = OnceLock::new();
+
+        // Chunks follow the trie order so that paths sharing a prefix share a hop cache
+        let mut results: Vec<ProfitCalculationResult> = paths
+            .trie()
+            .evaluation_order()
+            .par_chunks(PREFIX_CHUNK_SIZE) // <-- Rayon's parallel iterator
+            .flat_map_iter(|chunk| {
+                if truncation.get().is_some() {
+                    return Vec::new();
+                }
+                if let Some(reason) = cutoff.check(market_snapshot.block_number) {
+                    truncation.get_or_init(|| reason);
+                    return Vec::new();
+                }
+                let mut evaluator = PrefixEvaluator::new(paths, &pool_states);
+                chunk
+                    .iter()
+                    .map(|&path_index| self.calculate_path_profit(&mut evaluator, path_index, &paths.paths()[path_index]))
+                    .collect::<Vec<_>>()
             })
             .collect();
+        results.sort_unstable_by_key(|result| result.path_index);
 
         let profitable_count = results.iter()
-            .filter(|r| r.calculation_successful && r.net_profit_mnt_wei > self.config.min_profit_threshold_mnt_wei)
+            .filter(|r| r.calculation_successful && r.execution.robust_net_profit_mnt_wei > self.config.min_profit_threshold_mnt_wei)
             .count();
 
         debug!("并行利润计算完成，有利可图的路径: {}/{}", profitable_count, results.len());
 
-        results
+        ProfitCalculationBatch { results, paths_count: paths.len(), truncation: truncation.into_inner() }
     }
 
     /// Sequential profit calculation (fallback for debugging)
     fn calculate_profits_sequential(
         &self,
-        paths: &[SwapPath],
+        paths: &CompiledPathSet,
         market_snapshot: &MarketSnapshot,
-    ) -> Vec<ProfitCalculationResult> {
+        cutoff: &CalculationCutoff,
+    ) -> ProfitCalculationBatch {
         debug!("开始顺序利润计算，路径数量: {}", paths.len());
 
-        let results: Vec<ProfitCalculationResult> = paths
-            .iter()
-            .map(|path| {
-                self.calculate_path_profit(path, market_snapshot)
-            })
-            .collect();
+        let pool_states = PoolStateView::new(paths, market_snapshot);
+
+        let mut evaluator = PrefixEvaluator::new(paths, &pool_states);
+        let mut results = Vec::with_capacity(paths.len());
+        let mut truncation = None;
+        for chunk in paths.trie().evaluation_order().chunks(PREFIX_CHUNK_SIZE) {
+            truncation = cutoff.check(market_snapshot.block_number);
+            if truncation.is_some() {
+                break;
+            }
+            results.extend(chunk.iter().map(|&path_index| self.calculate_path_profit(&mut evaluator, path_index, &paths.paths()[path_index])));
+        }
+        results.sort_unstable_by_key(|result| result.path_index);
 
         debug!("顺序利润计算完成");
-        results
+        ProfitCalculationBatch { results, paths_count: paths.len(), truncation }
     }
 
     /// Calculate profit for a single path
@@ -80,72 +140,169 @@ impl ProfitCalculator {
     /// 4. Returns detailed profit calculation result
     fn calculate_path_profit(
         &self,
-        path: &SwapPath,
-        market_snapshot: &MarketSnapshot,
+        evaluator: &mut PrefixEvaluator,
+        path_index: usize,
+        path: &CompiledPath,
     ) -> ProfitCalculationResult {
-        // Verify we have reserve data for all pools in this path
-        for pool in &path.pools {
-            let pool_id = pool.get_pool_id();
-            if market_snapshot.get_pool_reserves(&pool_id).is_none() {
-                return ProfitCalculationResult::failure(
-                    path.clone(),
-                    format!("Missing reserve data for pool {:?}", pool_id),
-                );
+        let paths = evaluator.paths;
+        let swap_path_hash = paths.hash(path_index).cloned().unwrap_or_default();
+
+        // Verify we can evaluate and have reserve data for all pools in this path
+        for &pool_index in path.pools() {
+            if let Err(e) = evaluator.check_pool(pool_index as usize) {
+                return ProfitCalculationResult::failure(path_index, swap_path_hash, &e);
             }
         }
 
         // Find optimal input amount using ternary search
-        match self.find_optimal_input_amount(path, market_snapshot) {
-            Ok((optimal_input, expected_output)) => {
+        let Some(leaf) = evaluator.begin_path(path_index) else {
+            return ProfitCalculationResult::failure(path_index, swap_path_hash, &CalculationError::NotImplemented);
+        };
+
+        let flash_financed = self.is_flash_financed(paths, path);
+        let plan = PathPlan { leaf, flash_financed };
+
+        // Borrowed input is not limited by what we hold, otherwise trade at most the inventory
+        let available = match (&self.inventory, paths.input_token(path_index)) {
+            (Some(inventory), Some(token)) if !flash_financed => match inventory.available(token) {
+                Some(available) => Some(available),
+                None => return ProfitCalculationResult::failure(path_index, swap_path_hash, &CalculationError::MissingBalance(token)),
+            },
+            _ => None,
+        };
+
+        match self.find_optimal_input_amount(evaluator, plan) {
+            Ok((uncapped_input, uncapped_output)) => {
                 // Calculate gas cost in MNT Wei
-                let gas_cost_mnt_wei = self.calculate_gas_cost_mnt_wei(path);
-                
-                // Calculate gross profit in MNT Wei (expected output - input)
-                let gross_profit_mnt_wei = if expected_output > optimal_input {
-                    expected_output - optimal_input
-                } else {
-                    U256::ZERO
+                let gas_cost_mnt_wei = self.gas_cost_mnt_wei(flash_financed);
+
+                let (optimal_input, expected_output) = match available {
+                    // The profit is unimodal in the input, so the capped optimum is at the cap
+                    Some(available) if available < uncapped_input => match evaluator.simulate(self, leaf, available) {
+                        Ok(output) => (available, output),
+                        Err(e) => return ProfitCalculationResult::failure(path_index, swap_path_hash, &e),
+                    },
+                    _ => (uncapped_input, uncapped_output),
+                };
+                let inventory_cap = (optimal_input != uncapped_input).then(|| InventoryCap {
+                    uncapped_optimal_input_amount: uncapped_input,
+                    uncapped_net_profit_mnt_wei: uncapped_output
+                        .saturating_sub(uncapped_input)
+                        .saturating_sub(gas_cost_mnt_wei),
+                });
+
+                // A flash swap is repaid from the output of the last hop, including its fee
+                let flash_fee_mnt_wei = flash_financed.then(|| self.flash_fee(optimal_input));
+
+                // Calculate gross profit in MNT Wei (expected output - input - flash fee)
+                let gross_profit_mnt_wei = expected_output
+                    .saturating_sub(optimal_input)
+                    .saturating_sub(flash_fee_mnt_wei.unwrap_or_default());
+
+                let hops = match self.trace_hops(evaluator, path, optimal_input) {
+                    Ok(hops) => hops,
+                    Err(e) => return ProfitCalculationResult::failure(path_index, swap_path_hash, &e),
                 };
 
+                // The final hop's worst-case output bounds the whole path
+                let min_amount_out = hops.last().map_or(expected_output, |hop| hop.min_amount_out);
+
+                let execution =
+                    ExecutionDetails::new(optimal_input, min_amount_out, gas_cost_mnt_wei, flash_fee_mnt_wei, inventory_cap);
+
                 ProfitCalculationResult::success(
-                    path.clone(),
+                    path_index,
+                    swap_path_hash,
                     optimal_input,
                     expected_output,
                     gross_profit_mnt_wei,
                     gas_cost_mnt_wei,
                 )
+                .with_hops(hops)
+                .with_execution(execution)
             }
-            Err(e) => ProfitCalculationResult::failure(
-                path.clone(),
-                format!("Failed to calculate optimal input: {}", e),
-            ),
+            Err(e) => ProfitCalculationResult::failure(path_index, swap_path_hash, &e),
         }
     }
 
+    /// Borrow the input from the first pool if it supports flash swaps
+    fn is_flash_financed(&self, paths: &CompiledPathSet, path: &CompiledPath) -> bool {
+        self.config.enable_flash_swaps
+            && path.pools().first().is_some_and(|&pool_index| paths.can_flash_swap(pool_index as usize))
+    }
+
+    /// Sample the profit of a path across an input range
+    ///
+    /// Unlike [ProfitCalculator::calculate_profits_parallel], which settles on one input amount,
+    /// this evaluates every sampled input so the whole curve can be inspected when tuning the
+    /// search range or the gas settings. Inputs that cannot be simulated are left out.
+    pub fn profit_curve(
+        &self,
+        paths: &CompiledPathSet,
+        path_index: usize,
+        market_snapshot: &MarketSnapshot,
+        sampling: CurveSampling,
+    ) -> Result<ProfitCurve, CalculationError> {
+        let path = paths.get(path_index).ok_or(CalculationError::NotImplemented)?;
+        let pool_states = PoolStateView::new(paths, market_snapshot);
+        let mut evaluator = PrefixEvaluator::new(paths, &pool_states);
+
+        for &pool_index in path.pools() {
+            evaluator.check_pool(pool_index as usize)?;
+        }
+        let leaf = evaluator.begin_path(path_index).ok_or(CalculationError::NotImplemented)?;
+        let flash_financed = self.is_flash_financed(paths, path);
+        let plan = PathPlan { leaf, flash_financed };
+
+        let samples = sampling
+            .inputs()
+            .into_iter()
+            .filter_map(|input| {
+                let output = evaluator.simulate(self, leaf, input).ok()?;
+                let cost = if flash_financed { input + self.flash_fee(input) } else { input };
+                Some((input, output, I256::from_raw(output) - I256::from_raw(cost)))
+            })
+            .collect();
+        let search_optimal_input = self.find_optimal_input_amount(&mut evaluator, plan).ok().map(|(input, _)| input);
+
+        Ok(ProfitCurve::from_samples(
+            path_index,
+            market_snapshot.block_number,
+            self.gas_cost_mnt_wei(flash_financed),
+            flash_financed,
+            samples,
+            search_optimal_input,
+        ))
+    }
+
     /// Find the optimal input amount using ternary search
     /// 
     /// The profit function is generally unimodal (single peak) due to slippage,
     /// so ternary search is an efficient way to find the maximum.
     fn find_optimal_input_amount(
         &self,
-        path: &SwapPath,
-        market_snapshot: &MarketSnapshot,
-    ) -> Result<(U256, U256)> {
-        // Define search range (0.01 ETH to 100 ETH in Wei)
+        evaluator: &mut PrefixEvaluator,
+        plan: PathPlan,
+    ) -> Result<(U256, U256), CalculationError> {
+        // Define search range (0.01 ETH to 100 ETH in Wei, 10,000 ETH when the input is borrowed)
         let min_input = U256::from(10_000_000_000_000_000u64); // 0.01 ETH
-        let max_input = U256::from_str_radix("100000000000000000000", 10).unwrap(); // 100 ETH
+        let max_input = if plan.flash_financed {
+            U256::from_str_radix("10000000000000000000000", 10).unwrap() // 10,000 ETH
+        } else {
+            U256::from_str_radix("100000000000000000000", 10).unwrap() // 100 ETH
+        };
 
-        self.ternary_search_optimal_input(path, market_snapshot, min_input, max_input)
+        self.ternary_search_optimal_input(evaluator, plan, min_input, max_input)
     }
 
     /// Ternary search implementation for finding optimal input amount
     fn ternary_search_optimal_input(
         &self,
-        path: &SwapPath,
-        market_snapshot: &MarketSnapshot,
+        evaluator: &mut PrefixEvaluator,
+        plan: PathPlan,
         mut left: U256,
         mut right: U256,
-    ) -> Result<(U256, U256)> {
+    ) -> Result<(U256, U256), CalculationError> {
         let precision = U256::from(1_000_000_000_000_000u64); // 0.001 ETH precision
         let mut iterations = 0;
         const MAX_ITERATIONS: usize = 50;
@@ -159,8 +316,8 @@ impl ProfitCalculator {
             let mid1 = left + one_third;
             let mid2 = right - one_third;
 
-            let profit1 = self.calculate_profit_for_input(path, market_snapshot, mid1);
-            let profit2 = self.calculate_profit_for_input(path, market_snapshot, mid2);
+            let profit1 = self.calculate_profit_for_input(evaluator, plan, mid1);
+            let profit2 = self.calculate_profit_for_input(evaluator, plan, mid2);
 
             match (profit1, profit2) {
                 (Ok((profit1_val, output1)), Ok((profit2_val, output2))) => {
@@ -210,16 +367,19 @@ impl ProfitCalculator {
             Ok((best_input, best_output))
         } else {
             // Fallback: try a few fixed amounts
-            self.try_fixed_input_amounts(path, market_snapshot)
+            self.try_fixed_input_amounts(evaluator, plan)
         }
     }
 
     /// Try a few common input amounts as fallback
+    ///
+    /// If none of them is profitable the most telling error is returned: a data error
+    /// takes precedence over the path being unprofitable.
     fn try_fixed_input_amounts(
         &self,
-        path: &SwapPath,
-        market_snapshot: &MarketSnapshot,
-    ) -> Result<(U256, U256)> {
+        evaluator: &mut PrefixEvaluator,
+        plan: PathPlan,
+    ) -> Result<(U256, U256), CalculationError> {
         let test_amounts = [
             U256::from(100_000_000_000_000_000u64), // 0.1 ETH
             U256::from(500_000_000_000_000_000u64), // 0.5 ETH
@@ -227,78 +387,175 @@ impl ProfitCalculator {
             U256::from_str_radix("5000000000000000000", 10).unwrap(), // 5 ETH
         ];
 
-        let mut best_input = test_amounts[0];
-        let mut best_output = U256::ZERO;
+        let mut best: Option<(U256, U256)> = None;
         let mut best_profit = 0.0f64;
+        let mut last_error: Option<CalculationError> = None;
 
         for &amount in &test_amounts {
-            if let Ok((profit, output)) = self.calculate_profit_for_input(path, market_snapshot, amount) {
-                if profit > best_profit {
-                    best_input = amount;
-                    best_output = output;
-                    best_profit = profit;
+            match self.calculate_profit_for_input(evaluator, plan, amount) {
+                Ok((profit, output)) => {
+                    if profit > best_profit {
+                        best = Some((amount, output));
+                        best_profit = profit;
+                    }
+                }
+                Err(e) => {
+                    if last_error.as_ref().is_none_or(|last| !last.kind().is_data_error()) {
+                        last_error = Some(e);
+                    }
                 }
             }
         }
 
-        Ok((best_input, best_output))
+        best.ok_or_else(|| last_error.unwrap_or(CalculationError::NotImplemented))
     }
 
     /// Calculate profit for a specific input amount
     fn calculate_profit_for_input(
         &self,
-        path: &SwapPath,
-        market_snapshot: &MarketSnapshot,
+        evaluator: &mut PrefixEvaluator,
+        plan: PathPlan,
         input_amount: U256,
     ) -> Result<(f64, U256), CalculationError> {
-        let output_amount = self.simulate_swap_path(path, market_snapshot, input_amount)?;
-        
-        if output_amount <= input_amount {
-            return Err(CalculationError::NotImplemented);
+        let output_amount = evaluator.simulate(self, plan.leaf, input_amount)?;
+
+        // Borrowed input has to be paid back with the flash swap fee
+        let cost = if plan.flash_financed { input_amount + self.flash_fee(input_amount) } else { input_amount };
+        if output_amount <= cost {
+            return Err(CalculationError::Unprofitable { amount_in: input_amount, amount_out: output_amount });
         }
 
-        let profit_mnt_wei = output_amount - input_amount;
+        let profit_mnt_wei = output_amount - cost;
         
         Ok((profit_mnt_wei.to_string().parse::<f64>().unwrap_or(0.0), output_amount))
     }
 
-    /// Simulate executing a swap path with given input amount
-    /// 
-    /// This chains getAmountOut calls across all pools in the path
-    fn simulate_swap_path(
+    /// Simulate the path hop by hop for the chosen input amount and record each hop
+    ///
+    /// Alongside the expected execution the path is re-simulated in a worst case where every
+    /// pool price moved against the trade by `slippage_tolerance_bps`. The worst-case output of
+    /// each hop becomes its `min_amount_out`.
+    fn trace_hops(
+        &self,
+        evaluator: &PrefixEvaluator,
+        path: &CompiledPath,
+        mut amount: U256,
+    ) -> Result<Vec<HopTrace>, CalculationError> {
+        let paths = evaluator.paths;
+        let mut hops = Vec::with_capacity(path.len());
+        let mut worst_case_amount = amount;
+
+        for (pool_index, zero_for_one) in path.hops() {
+            let pool_id = paths.pool_id(pool_index);
+            let fee = paths.pool_fee(pool_index);
+            let (reserve0, reserve1) = evaluator.check_pool(pool_index)?.reserves();
+            let (reserve_in, reserve_out) = if zero_for_one { (reserve0, reserve1) } else { (reserve1, reserve0) };
+
+            let amount_out = self.simple_constant_product_formula(pool_id, amount, zero_for_one, reserve0, reserve1, fee)?;
+            let fee_paid = amount * fee / U256::from(10000);
+
+            let (worst_reserve0, worst_reserve1) = self.worst_case_reserves(zero_for_one, reserve0, reserve1);
+            worst_case_amount =
+                self.simple_constant_product_formula(pool_id, worst_case_amount, zero_for_one, worst_reserve0, worst_reserve1, fee)?;
+
+            let spot_price_before = u256_to_f64(reserve_out) / u256_to_f64(reserve_in);
+            let spot_price_after = u256_to_f64(reserve_out - amount_out) / u256_to_f64(reserve_in + amount);
+            let amount_in_after_fee = u256_to_f64(amount - fee_paid);
+            let price_impact = if amount_in_after_fee > 0.0 {
+                1.0 - (u256_to_f64(amount_out) / amount_in_after_fee) / spot_price_before
+            } else {
+                0.0
+            };
+
+            hops.push(HopTrace {
+                pool_id,
+                zero_for_one,
+                amount_in: amount,
+                amount_out,
+                fee_paid,
+                price_impact,
+                spot_price_before,
+                spot_price_after,
+                min_amount_out: worst_case_amount,
+            });
+            amount = amount_out;
+        }
+
+        Ok(hops)
+    }
+
+    /// Simulate a path backwards: the input required to receive `amount_out` of its final token
+    ///
+    /// Each hop is inverted from the last one to the first, so the quote can size exact-out
+    /// trades. The required input is the smallest one for which the forward simulation yields at
+    /// least `amount_out`.
+    pub fn calculate_required_input(
         &self,
         path: &SwapPath,
+        amount_out: U256,
         market_snapshot: &MarketSnapshot,
-        mut amount: U256,
-    ) -> Result<U256, CalculationError> {
-        for (i, pool) in path.pools.iter().enumerate() {
-            let pool_id = pool.get_pool_id();
-            let (reserve0, reserve1) = market_snapshot.get_pool_reserves(&pool_id)
-                .ok_or(CalculationError::NotImplemented)?;
+    ) -> Result<ExactOutQuote, CalculationError> {
+        if path.tokens.len() != path.pools.len() + 1 {
+            return Err(CalculationError::NotImplemented);
+        }
 
-            // Determine which token we're swapping from and to
-            let token_in = path.tokens.get(i).unwrap();
-            let token_out = path.tokens.get(i + 1).unwrap();
+        let mut amounts = vec![U256::ZERO; path.tokens.len()];
+        amounts[path.pools.len()] = amount_out;
 
-            // For now, use a simplified calculation (constant product formula)
-            // This should be replaced with proper pool-specific calculations
-            amount = self.simple_constant_product_formula(
-                amount,
-                token_in.get_address(),
-                token_out.get_address(),
-                reserve0,
-                reserve1,
-                pool.get_fee(),
-            )?;
+        for (hop, pool) in path.pools.iter().enumerate().rev() {
+            let pool_id = pool.get_pool_id();
+            if pool.get_class() == PoolClass::UniswapV3 || !pool.can_calculate_in_amount() {
+                return Err(CalculationError::UnsupportedPool(pool_id));
+            }
+            let (reserve0, reserve1) = market_snapshot.get_pool_reserves(&pool_id).ok_or(CalculationError::MissingState(pool_id))?;
+            let zero_for_one = path.tokens[hop].get_address() < path.tokens[hop + 1].get_address();
+
+            amounts[hop] =
+                self.simple_constant_product_in_amount(pool_id, amounts[hop + 1], zero_for_one, reserve0, reserve1, pool.get_fee())?;
         }
 
-        Ok(amount)
+        Ok(ExactOutQuote { amount_in: amounts[0], amount_out, amounts })
+    }
+
+    /// Check a forward result against the backward simulation of the same path
+    ///
+    /// Returns true if the expected output of `result` can be bought for no more than its input.
+    pub fn verify_forward_result(
+        &self,
+        result: &ProfitCalculationResult,
+        paths: &CompiledPathSet,
+        market_snapshot: &MarketSnapshot,
+    ) -> Result<bool, CalculationError> {
+        let path = paths.swap_path(result.path_index).ok_or(CalculationError::NotImplemented)?;
+        let quote = self.calculate_required_input(path, result.expected_output_amount, market_snapshot)?;
+        Ok(quote.amount_in <= result.optimal_input_amount)
+    }
+
+    /// Reserves of a pool whose price moved against the trade by the slippage tolerance
+    fn worst_case_reserves(&self, zero_for_one: bool, reserve0: U256, reserve1: U256) -> (U256, U256) {
+        let tolerance_bps = U256::from(self.config.slippage_tolerance_bps.min(10_000));
+        let shrink = |reserve_out: U256| reserve_out * (U256::from(10_000) - tolerance_bps) / U256::from(10_000);
+        if zero_for_one { (reserve0, shrink(reserve1)) } else { (shrink(reserve0), reserve1) }
+    }
+
+    /// Fee for flash-borrowing `amount`
+    fn flash_fee(&self, amount: U256) -> U256 {
+        amount * U256::from(self.config.flash_swap_fee_bps) / U256::from(10000)
     }
 
     /// Calculate gas cost in MNT Wei for executing a path
     pub fn calculate_gas_cost_mnt_wei(&self, _path: &SwapPath) -> U256 {
+        self.gas_cost_mnt_wei(false)
+    }
+
+    /// Gas cost of a transaction, which is the same for every path apart from the flash swap callback
+    fn gas_cost_mnt_wei(&self, flash_financed: bool) -> U256 {
         // Use the total gas per transaction as configured
-        let total_gas = self.config.gas_per_transaction;
+        let total_gas = if flash_financed {
+            self.config.gas_per_transaction + self.config.flash_swap_callback_gas
+        } else {
+            self.config.gas_per_transaction
+        };
         
         // Convert gas price from Gwei to Wei
         // gas_price_gwei is f64, so we need to handle fractional gwei
@@ -317,9 +574,9 @@ impl ProfitCalculator {
     /// This is a temporary implementation until proper pool-specific calculations are implemented
     fn simple_constant_product_formula(
         &self,
+        pool_id: PoolId,
         amount_in: U256,
-        token_in: Address,
-        token_out: Address,
+        zero_for_one: bool,
         reserve0: U256,
         reserve1: U256,
         fee: U256,
@@ -328,46 +585,248 @@ impl ProfitCalculator {
             return Ok(U256::ZERO);
         }
 
-        // Determine which reserve is for which token
-        // This is simplified and assumes token ordering - in real implementation,
-        // we'd need to check the actual token addresses against the pool's token0/token1
-        let (reserve_in, reserve_out) = if token_in < token_out {
+        // The direction bit is derived from the token ordering when the path is compiled
+        let (reserve_in, reserve_out) = if zero_for_one {
             (reserve0, reserve1)
         } else {
             (reserve1, reserve0)
         };
 
         if reserve_in.is_zero() || reserve_out.is_zero() {
-            return Err(CalculationError::NotImplemented);
+            return Err(CalculationError::ZeroLiquidity(pool_id));
         }
 
         // Apply fee (assuming fee is in basis points, e.g., 30 for 0.3%)
-        let fee_multiplier = U256::from(10000) - fee;
-        let amount_in_with_fee = amount_in * fee_multiplier / U256::from(10000);
+        let fee_multiplier = U256::from(10000).checked_sub(fee).ok_or(CalculationError::Overflow(pool_id))?;
+        let amount_in_with_fee = amount_in.checked_mul(fee_multiplier).ok_or(CalculationError::Overflow(pool_id))? / U256::from(10000);
 
         // Constant product formula: x * y = k
         // amount_out = (amount_in_with_fee * reserve_out) / (reserve_in + amount_in_with_fee)
-        let numerator = amount_in_with_fee * reserve_out;
-        let denominator = reserve_in + amount_in_with_fee;
+        let numerator = amount_in_with_fee.checked_mul(reserve_out).ok_or(CalculationError::Overflow(pool_id))?;
+        let denominator = reserve_in.checked_add(amount_in_with_fee).ok_or(CalculationError::Overflow(pool_id))?;
 
         if denominator.is_zero() {
-            return Err(CalculationError::NotImplemented);
+            return Err(CalculationError::ZeroLiquidity(pool_id));
         }
 
-        Ok(numerator / denominator)
+        let amount_out = numerator / denominator;
+        if amount_out >= reserve_out {
+            return Err(CalculationError::ExceedsReserves { pool_id, amount_out, reserve_out });
+        }
+
+        Ok(amount_out)
+    }
+
+    /// Inverse of [ProfitCalculator::simple_constant_product_formula]: the smallest input yielding `amount_out`
+    ///
+    /// Both roundings of the forward formula are undone by rounding up.
+    fn simple_constant_product_in_amount(
+        &self,
+        pool_id: PoolId,
+        amount_out: U256,
+        zero_for_one: bool,
+        reserve0: U256,
+        reserve1: U256,
+        fee: U256,
+    ) -> Result<U256, CalculationError> {
+        if amount_out.is_zero() {
+            return Ok(U256::ZERO);
+        }
+
+        let (reserve_in, reserve_out) = if zero_for_one {
+            (reserve0, reserve1)
+        } else {
+            (reserve1, reserve0)
+        };
+
+        if reserve_in.is_zero() || reserve_out.is_zero() {
+            return Err(CalculationError::ZeroLiquidity(pool_id));
+        }
+        if amount_out >= reserve_out {
+            return Err(CalculationError::ExceedsReserves { pool_id, amount_out, reserve_out });
+        }
+
+        // amount_in_with_fee * (reserve_out - amount_out) >= amount_out * reserve_in
+        let numerator = amount_out.checked_mul(reserve_in).ok_or(CalculationError::Overflow(pool_id))?;
+        let amount_in_with_fee = numerator.div_ceil(reserve_out - amount_out);
+
+        // amount_in * (10000 - fee) >= amount_in_with_fee * 10000
+        let fee_multiplier = U256::from(10000).checked_sub(fee).filter(|multiplier| !multiplier.is_zero()).ok_or(CalculationError::Overflow(pool_id))?;
+        let amount_in = amount_in_with_fee.checked_mul(U256::from(10000)).ok_or(CalculationError::Overflow(pool_id))?.div_ceil(fee_multiplier);
+
+        Ok(amount_in)
+    }
+}
+
+/// How a path is evaluated: its trie leaf and whether the input is flash-borrowed
+#[derive(Clone, Copy, Debug)]
+struct PathPlan {
+    leaf: u32,
+    flash_financed: bool,
+}
+
+/// Lossy conversion for ratios and reporting, never for amounts that are traded
+pub(crate) fn u256_to_f64(value: U256) -> f64 {
+    value.to_string().parse::<f64>().unwrap_or(0.0)
+}
+
+/// Number of paths evaluated per parallel task. Each task owns one hop cache.
+const PREFIX_CHUNK_SIZE: usize = 256;
+
+/// Simulates compiled paths through the [super::graph::PathTrie], caching hop outputs.
+///
+/// The output of a trie node only depends on the amount put into the first hop. The outcome of
+/// the last walk through each depth is kept with its node and input amount, so a path reuses
+/// the outputs of a prefix it shares with the previously simulated path and a failing prefix is
+/// simulated only once.
+struct PrefixEvaluator<'a> {
+    paths: &'a CompiledPathSet,
+    pool_states: &'a PoolStateView<'a>,
+    hop_stack: [Option<CachedHop>; MAX_COMPILED_HOPS],
+}
+
+/// Outcome of a trie node for an input amount of the first hop
+struct CachedHop {
+    node: u32,
+    amount_in: U256,
+    output: Result<U256, CalculationError>,
+}
+
+impl<'a> PrefixEvaluator<'a> {
+    fn new(paths: &'a CompiledPathSet, pool_states: &'a PoolStateView<'a>) -> Self {
+        Self { paths, pool_states, hop_stack: [const { None }; MAX_COMPILED_HOPS] }
+    }
+
+    /// Check that a pool can be evaluated and has state in the snapshot
+    fn check_pool(&self, pool_index: usize) -> Result<&'a PoolState, CalculationError> {
+        if self.paths.pool_class(pool_index) == PoolClass::UniswapV3 {
+            // Concentrated liquidity cannot be simulated with the constant product formula
+            return Err(CalculationError::UnsupportedPool(self.paths.pool_id(pool_index)));
+        }
+        self.pool_states.get(pool_index).ok_or_else(|| CalculationError::MissingState(self.paths.pool_id(pool_index)))
+    }
+
+    /// Leaf node of a path
+    fn begin_path(&self, path_index: usize) -> Option<u32> {
+        self.paths.trie().leaf(path_index)
+    }
+
+    /// Simulate the path ending at `leaf` for the given input amount
+    ///
+    /// This chains getAmountOut calls across all pools in the path, starting from the
+    /// deepest cached prefix.
+    fn simulate(&mut self, calculator: &ProfitCalculator, leaf: u32, amount_in: U256) -> Result<U256, CalculationError> {
+        let trie = self.paths.trie();
+
+        // Walk up to the deepest prefix with a cached outcome
+        let mut pending = [0u32; MAX_COMPILED_HOPS];
+        let mut pending_len = 0;
+        let mut amount = amount_in;
+        let mut node_id = Some(leaf);
+        while let Some(id) = node_id {
+            let node = trie.node(id);
+            if let Some(cached) = &self.hop_stack[node.depth as usize]
+                && cached.node == id
+                && cached.amount_in == amount_in
+            {
+                match &cached.output {
+                    Ok(output) => {
+                        amount = *output;
+                        break;
+                    }
+                    Err(e) => return Err(replay_failure(e)),
+                }
+            }
+            pending[pending_len] = id;
+            pending_len += 1;
+            node_id = node.parent;
+        }
+
+        // Simulate the remaining hops top-down
+        for &id in pending[..pending_len].iter().rev() {
+            let node = trie.node(id);
+            let pool_index = node.pool_index as usize;
+            // For now, use a simplified calculation (constant product formula)
+            // This should be replaced with proper pool-specific calculations
+            let output = self.check_pool(pool_index).and_then(|state| {
+                let (reserve0, reserve1) = state.reserves();
+                calculator.simple_constant_product_formula(
+                    self.paths.pool_id(pool_index),
+                    amount,
+                    node.zero_for_one,
+                    reserve0,
+                    reserve1,
+                    self.paths.pool_fee(pool_index),
+                )
+            });
+            let result = output.as_ref().copied().map_err(replay_failure);
+            self.hop_stack[node.depth as usize] = Some(CachedHop { node: id, amount_in, output });
+            amount = result?;
+        }
+
+        Ok(amount)
+    }
+}
+
+/// Copy of a cached hop failure, hop failures only carry pool data
+fn replay_failure(error: &CalculationError) -> CalculationError {
+    match error {
+        CalculationError::MissingState(pool_id) => CalculationError::MissingState(*pool_id),
+        CalculationError::ZeroLiquidity(pool_id) => CalculationError::ZeroLiquidity(*pool_id),
+        CalculationError::Overflow(pool_id) => CalculationError::Overflow(*pool_id),
+        CalculationError::UnsupportedPool(pool_id) => CalculationError::UnsupportedPool(*pool_id),
+        CalculationError::ExceedsReserves { pool_id, amount_out, reserve_out } => {
+            CalculationError::ExceedsReserves { pool_id: *pool_id, amount_out: *amount_out, reserve_out: *reserve_out }
+        }
+        other => CalculationError::Error(eyre::eyre!("{}", other)),
+    }
+}
+
+/// Pool states of a snapshot addressed by the dense pool indices of a [CompiledPathSet]
+///
+/// When the snapshot was produced with the same pool index the paths were compiled against
+/// (the market's index), states are read directly. Otherwise the states of the compiled pools
+/// are looked up once per calculation instead of once per hop.
+pub(crate) struct PoolStateView<'a> {
+    snapshot: &'a MarketSnapshot,
+    remap: Option<Vec<Option<&'a PoolState>>>,
+}
+
+impl<'a> PoolStateView<'a> {
+    pub(crate) fn new(paths: &CompiledPathSet, snapshot: &'a MarketSnapshot) -> Self {
+        let pool_ids = paths.pool_ids();
+        let shares_layout = snapshot.pool_index.pool_ids().get(..pool_ids.len()) == Some(pool_ids);
+        let remap = if shares_layout {
+            None
+        } else {
+            Some(pool_ids.iter().map(|pool_id| snapshot.pool_state(pool_id)).collect())
+        };
+        Self { snapshot, remap }
+    }
+
+    /// Get the state of a pool by its dense index in the compiled path set
+    #[inline]
+    pub(crate) fn get(&self, pool_index: usize) -> Option<&'a PoolState> {
+        match &self.remap {
+            None => self.snapshot.get_pool_state(pool_index),
+            Some(remap) => *remap.get(pool_index)?,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::logic::graph::SwapPath;
+    use crate::logic::graph::{CompiledPathSet, SwapPath};
     use crate::logic::types::MarketSnapshot;
     use crate::{MockPool, PoolWrapper, Token};
-    use crate::logic::pools::PoolId;
+    use crate::logic::pools::{CalculationErrorKind, PoolId};
     use crate::utils::constants::WMNT;
+    use crate::logic::types::TruncationReason;
+    use crate::utils::StateCache;
     use alloy_primitives::Address;
     use std::sync::Arc;
+    use std::time::Instant;
 
     fn create_test_path() -> SwapPath {
         let wmnt_token = Arc::new(Token::new_with_data(WMNT, Some("WMNT".to_string()), None, Some(18)));
@@ -423,20 +882,24 @@ mod tests {
     fn test_profit_calculation_with_missing_reserves() {
         let config = ArbitrageConfig::default();
         let calculator = ProfitCalculator::new(config);
-        let path = create_test_path();
+        let paths = CompiledPathSet::from_paths(vec![create_test_path()]).unwrap();
         let empty_snapshot = MarketSnapshot::new(12345); // No reserves
 
-        let result = calculator.calculate_path_profit(&path, &empty_snapshot);
+        let pool_states = PoolStateView::new(&paths, &empty_snapshot);
+        let mut evaluator = PrefixEvaluator::new(&paths, &pool_states);
+
+        let result = calculator.calculate_path_profit(&mut evaluator, 0, &paths.paths()[0]);
         
         assert!(!result.calculation_successful);
-        assert!(result.error_message.is_some());
+        assert_eq!(result.error.as_ref().map(|error| error.kind), Some(CalculationErrorKind::MissingState));
+        assert_eq!(result.error.as_ref().and_then(|error| error.pool_id), Some(PoolId::Address(Address::repeat_byte(10))));
     }
 
     #[test]
     fn test_parallel_vs_sequential_calculation() {
         let mut config = ArbitrageConfig::default();
         let path = create_test_path();
-        let paths = vec![path; 100]; // 100 identical paths
+        let paths = CompiledPathSet::from_paths(vec![path; 100]).unwrap(); // 100 identical paths
         let snapshot = create_test_market_snapshot();
 
         // Test parallel calculation
@@ -453,4 +916,433 @@ mod tests {
         assert_eq!(parallel_results.len(), sequential_results.len());
         assert_eq!(parallel_results.len(), 100);
     }
+
+    #[test]
+    fn test_calculation_cutoff() {
+        let paths = CompiledPathSet::from_paths(vec![create_test_path(); 600]).unwrap();
+        let snapshot = create_test_market_snapshot();
+        let latest_block = Arc::new(std::sync::atomic::AtomicU64::new(snapshot.block_number));
+        let cutoff = CalculationCutoff::new().with_latest_block(Arc::clone(&latest_block));
+
+        for enable_parallel_calculation in [true, false] {
+            let calculator = ProfitCalculator::new(ArbitrageConfig { enable_parallel_calculation, ..ArbitrageConfig::default() });
+            latest_block.store(snapshot.block_number, std::sync::atomic::Ordering::Release);
+
+            // Nothing newer than the snapshot: every path is evaluated
+            let batch = calculator.calculate_profits_with_cutoff(&paths, &snapshot, &cutoff);
+            assert!(!batch.is_truncated());
+            assert_eq!((batch.results.len(), batch.paths_count), (600, 600));
+
+            latest_block.store(snapshot.block_number + 1, std::sync::atomic::Ordering::Release);
+            let batch = calculator.calculate_profits_with_cutoff(&paths, &snapshot, &cutoff);
+            assert_eq!(batch.truncation, Some(TruncationReason::NewerBlock { latest_block: snapshot.block_number + 1 }));
+            assert!(batch.results.is_empty());
+
+            let expired = CalculationCutoff::new().with_deadline(Instant::now());
+            let batch = calculator.calculate_profits_with_cutoff(&paths, &snapshot, &expired);
+            assert_eq!(batch.truncation, Some(TruncationReason::DeadlineExceeded));
+        }
+    }
+
+    #[test]
+    fn test_pool_state_view_remaps_foreign_layout() {
+        let config = ArbitrageConfig::default();
+        let calculator = ProfitCalculator::new(config);
+        let paths = CompiledPathSet::from_paths(vec![create_test_path()]).unwrap();
+
+        // Shares the layout of the compiled paths, read without remapping
+        let mut shared = MarketSnapshot::new(12345);
+        shared.set_pool_reserves(paths.pool_id(0), U256::from(1_000_000u64), U256::from(2_000_000u64));
+        assert!(PoolStateView::new(&paths, &shared).remap.is_none());
+
+        // An unrelated pool takes dense index 0, so the compiled index has to be remapped
+        let mut foreign = MarketSnapshot::new(12345);
+        foreign.set_pool_reserves(PoolId::Address(Address::repeat_byte(99)), U256::from(1u64), U256::from(1u64));
+        foreign.set_pool_reserves(paths.pool_id(0), U256::from(1_000_000u64), U256::from(2_000_000u64));
+        let view = PoolStateView::new(&paths, &foreign);
+        assert!(view.remap.is_some());
+        assert_eq!(view.get(0).map(|state| state.reserves()), Some((U256::from(1_000_000u64), U256::from(2_000_000u64))));
+
+        let shared_results = calculator.calculate_profits_parallel(&paths, &shared);
+        let foreign_results = calculator.calculate_profits_parallel(&paths, &foreign);
+        assert_eq!(shared_results[0].expected_output_amount, foreign_results[0].expected_output_amount);
+        assert_eq!(shared_results[0].calculation_successful, foreign_results[0].calculation_successful);
+    }
+
+    #[test]
+    fn test_prefix_sharing_matches_independent_evaluation() {
+        let wmnt_token = Arc::new(Token::new_with_data(WMNT, Some("WMNT".to_string()), None, Some(18)));
+        let token1 = Arc::new(Token::repeat_byte(1));
+        let token2 = Arc::new(Token::repeat_byte(2));
+
+        let pool_w_1 = PoolWrapper::from(MockPool::new(WMNT, token1.get_address(), Address::repeat_byte(10)));
+        let pool_1_w = PoolWrapper::from(MockPool::new(token1.get_address(), WMNT, Address::repeat_byte(11)));
+        let pool_1_2 = PoolWrapper::from(MockPool::new(token1.get_address(), token2.get_address(), Address::repeat_byte(12)));
+        let pool_2_w = PoolWrapper::from(MockPool::new(token2.get_address(), WMNT, Address::repeat_byte(13)));
+
+        // Both cycles start with WMNT -> TOKEN1 through the same pool
+        let direct = SwapPath::new(vec![wmnt_token.clone(), token1.clone(), wmnt_token.clone()], vec![pool_w_1.clone(), pool_1_w.clone()]);
+        let triangle = SwapPath::new(
+            vec![wmnt_token.clone(), token1.clone(), token2.clone(), wmnt_token.clone()],
+            vec![pool_w_1.clone(), pool_1_2.clone(), pool_2_w.clone()],
+        );
+
+        let eth = |amount: u64| U256::from(amount) * U256::from(1_000_000_000_000_000_000u64);
+        let mut snapshot = MarketSnapshot::new(12345);
+        snapshot.set_pool_reserves(pool_w_1.get_pool_id(), eth(1_200), eth(1_000));
+        snapshot.set_pool_reserves(pool_1_w.get_pool_id(), eth(1_000), eth(1_000));
+        snapshot.set_pool_reserves(pool_1_2.get_pool_id(), eth(2_000), eth(2_100));
+        snapshot.set_pool_reserves(pool_2_w.get_pool_id(), eth(900), eth(1_000));
+
+        let calculator = ProfitCalculator::new(ArbitrageConfig::default());
+        let shared_paths = CompiledPathSet::from_paths(vec![direct.clone(), triangle.clone()]).unwrap();
+        assert_eq!(shared_paths.trie().nodes_count(), 4);
+        let shared_results = calculator.calculate_profits_parallel(&shared_paths, &snapshot);
+
+        // The direct cycle is profitable, its optimum comes from a real search over the shared prefix
+        let direct_result = &shared_results[0];
+        assert!(direct_result.optimal_input_amount > U256::ZERO);
+        assert!(direct_result.expected_output_amount > direct_result.optimal_input_amount);
+        assert!(direct_result.gross_profit_mnt_wei > U256::ZERO);
+
+        for (path_index, path) in [direct, triangle].into_iter().enumerate() {
+            let alone = CompiledPathSet::from_paths(vec![path]).unwrap();
+            let alone_result = &calculator.calculate_profits_parallel(&alone, &snapshot)[0];
+            let shared_result = &shared_results[path_index];
+
+            assert_eq!(shared_result.path_index, path_index);
+            assert!(shared_result.calculation_successful);
+            assert!(alone_result.calculation_successful);
+            assert_eq!(shared_result.optimal_input_amount, alone_result.optimal_input_amount);
+            assert_eq!(shared_result.expected_output_amount, alone_result.expected_output_amount);
+            assert_eq!(shared_result.gross_profit_mnt_wei, alone_result.gross_profit_mnt_wei);
+        }
+    }
+
+    #[test]
+    fn test_failures_are_typed_per_path_under_shared_prefix() {
+        let wmnt_token = Arc::new(Token::new_with_data(WMNT, Some("WMNT".to_string()), None, Some(18)));
+        let token1 = Arc::new(Token::repeat_byte(1));
+        let token2 = Arc::new(Token::repeat_byte(2));
+
+        let pool_w_1 = PoolWrapper::from(MockPool::new(WMNT, token1.get_address(), Address::repeat_byte(10)));
+        let pool_1_w = PoolWrapper::from(MockPool::new(token1.get_address(), WMNT, Address::repeat_byte(11)));
+        let pool_1_2 = PoolWrapper::from(MockPool::new(token1.get_address(), token2.get_address(), Address::repeat_byte(12)));
+        let pool_2_w = PoolWrapper::from(MockPool::new(token2.get_address(), WMNT, Address::repeat_byte(13)));
+
+        let direct = SwapPath::new(vec![wmnt_token.clone(), token1.clone(), wmnt_token.clone()], vec![pool_w_1.clone(), pool_1_w.clone()]);
+        let triangle = SwapPath::new(
+            vec![wmnt_token.clone(), token1.clone(), token2.clone(), wmnt_token.clone()],
+            vec![pool_w_1.clone(), pool_1_2.clone(), pool_2_w.clone()],
+        );
+
+        // Only the second hop of the triangle has no state
+        let eth = |amount: u64| U256::from(amount) * U256::from(1_000_000_000_000_000_000u64);
+        let mut snapshot = MarketSnapshot::new(12345);
+        snapshot.set_pool_reserves(pool_w_1.get_pool_id(), eth(1_200), eth(1_000));
+        snapshot.set_pool_reserves(pool_1_w.get_pool_id(), eth(1_000), eth(1_000));
+        snapshot.set_pool_reserves(pool_2_w.get_pool_id(), eth(900), eth(1_000));
+
+        let calculator = ProfitCalculator::new(ArbitrageConfig::default());
+        let paths = CompiledPathSet::from_paths(vec![direct, triangle]).unwrap();
+        let results = calculator.calculate_profits_parallel(&paths, &snapshot);
+
+        assert!(results[0].calculation_successful);
+        assert!(results[0].error.is_none());
+        assert!(!results[1].calculation_successful);
+        let error = results[1].error.as_ref().unwrap();
+        assert_eq!(error.kind, CalculationErrorKind::MissingState);
+        assert_eq!(error.pool_id, Some(pool_1_2.get_pool_id()));
+    }
+
+    #[test]
+    fn test_failing_prefix_is_simulated_once() {
+        let wmnt_token = Arc::new(Token::new_with_data(WMNT, Some("WMNT".to_string()), None, Some(18)));
+        let token1 = Arc::new(Token::repeat_byte(1));
+        let token2 = Arc::new(Token::repeat_byte(2));
+
+        let pool_w_1 = PoolWrapper::from(MockPool::new(WMNT, token1.get_address(), Address::repeat_byte(10)));
+        let pool_1_w = PoolWrapper::from(MockPool::new(token1.get_address(), WMNT, Address::repeat_byte(11)));
+        let pool_1_2 = PoolWrapper::from(MockPool::new(token1.get_address(), token2.get_address(), Address::repeat_byte(12)));
+        let pool_2_w = PoolWrapper::from(MockPool::new(token2.get_address(), WMNT, Address::repeat_byte(13)));
+
+        let direct = SwapPath::new(vec![wmnt_token.clone(), token1.clone(), wmnt_token.clone()], vec![pool_w_1.clone(), pool_1_w.clone()]);
+        let triangle = SwapPath::new(
+            vec![wmnt_token.clone(), token1.clone(), token2.clone(), wmnt_token.clone()],
+            vec![pool_w_1.clone(), pool_1_2.clone(), pool_2_w.clone()],
+        );
+
+        // The shared first hop has no liquidity
+        let eth = |amount: u64| U256::from(amount) * U256::from(1_000_000_000_000_000_000u64);
+        let mut snapshot = MarketSnapshot::new(12345);
+        snapshot.set_pool_reserves(pool_w_1.get_pool_id(), U256::ZERO, eth(1_000));
+        snapshot.set_pool_reserves(pool_1_w.get_pool_id(), eth(1_000), eth(1_000));
+        snapshot.set_pool_reserves(pool_1_2.get_pool_id(), eth(2_000), eth(2_100));
+        snapshot.set_pool_reserves(pool_2_w.get_pool_id(), eth(900), eth(1_000));
+
+        let calculator = ProfitCalculator::new(ArbitrageConfig::default());
+        let paths = CompiledPathSet::from_paths(vec![direct, triangle]).unwrap();
+        let pool_states = PoolStateView::new(&paths, &snapshot);
+        let mut evaluator = PrefixEvaluator::new(&paths, &pool_states);
+        let direct_leaf = evaluator.begin_path(0).unwrap();
+        let triangle_leaf = evaluator.begin_path(1).unwrap();
+
+        // The failure stops the walk at the first hop and is kept for it
+        let error = evaluator.simulate(&calculator, triangle_leaf, eth(1)).unwrap_err();
+        assert_eq!(error.kind(), CalculationErrorKind::ZeroLiquidity);
+        let cached = evaluator.hop_stack[0].as_ref().unwrap();
+        assert_eq!((cached.node, cached.amount_in), (paths.trie().node(triangle_leaf).root, eth(1)));
+        assert!(cached.output.is_err());
+        assert!(evaluator.hop_stack[1].is_none());
+
+        // The sibling path reuses the cached failure, another amount is simulated again
+        let error = evaluator.simulate(&calculator, direct_leaf, eth(1)).unwrap_err();
+        assert_eq!(error.pool_id(), Some(pool_w_1.get_pool_id()));
+        evaluator.hop_stack[0].as_mut().unwrap().output = Err(CalculationError::Overflow(pool_1_w.get_pool_id()));
+        let error = evaluator.simulate(&calculator, direct_leaf, eth(1)).unwrap_err();
+        assert_eq!(error.kind(), CalculationErrorKind::Overflow);
+        let error = evaluator.simulate(&calculator, direct_leaf, eth(2)).unwrap_err();
+        assert_eq!(error.kind(), CalculationErrorKind::ZeroLiquidity);
+    }
+
+    /// A WMNT -> TOKEN1 -> WMNT cycle that is profitable without fees
+    fn create_profitable_two_hop_case() -> (SwapPath, MarketSnapshot) {
+        let wmnt_token = Arc::new(Token::new_with_data(WMNT, Some("WMNT".to_string()), None, Some(18)));
+        let token1 = Arc::new(Token::repeat_byte(1));
+
+        let pool_w_1 = PoolWrapper::from(MockPool::new(WMNT, token1.get_address(), Address::repeat_byte(10)));
+        let pool_1_w = PoolWrapper::from(MockPool::new(token1.get_address(), WMNT, Address::repeat_byte(11)));
+
+        // TOKEN1 is token0 of both pools: cheap in the first pool, expensive in the second
+        let eth = |amount: u64| U256::from(amount) * U256::from(1_000_000_000_000_000_000u64);
+        let mut snapshot = MarketSnapshot::new(12345);
+        snapshot.set_pool_reserves(pool_w_1.get_pool_id(), eth(1_200), eth(1_000));
+        snapshot.set_pool_reserves(pool_1_w.get_pool_id(), eth(1_000), eth(1_000));
+
+        (SwapPath::new(vec![wmnt_token.clone(), token1, wmnt_token], vec![pool_w_1, pool_1_w]), snapshot)
+    }
+
+    #[test]
+    fn test_hop_trace_at_optimal_input() {
+        let (path, snapshot) = create_profitable_two_hop_case();
+        let first_pool_id = path.pools[0].get_pool_id();
+
+        let calculator = ProfitCalculator::new(ArbitrageConfig::default());
+        let paths = CompiledPathSet::from_paths(vec![path]).unwrap();
+        let result = &calculator.calculate_profits_parallel(&paths, &snapshot)[0];
+        assert!(result.calculation_successful);
+
+        let hops = &result.hops;
+        assert_eq!(hops.len(), 2);
+        assert_eq!(hops[0].pool_id, first_pool_id);
+        assert!(!hops[0].zero_for_one);
+        assert!(hops[1].zero_for_one);
+        assert_eq!(hops[0].amount_in, result.optimal_input_amount);
+        assert_eq!(hops[0].amount_out, hops[1].amount_in);
+        assert_eq!(hops[1].amount_out, result.expected_output_amount);
+
+        for hop in hops {
+            // Mock pools have no fee, so the whole shortfall is price impact
+            assert_eq!(hop.fee_paid, U256::ZERO);
+            assert!(hop.price_impact > 0.0 && hop.price_impact < 1.0);
+            assert!(hop.spot_price_after < hop.spot_price_before);
+        }
+        assert!((hops[0].spot_price_before - 1.2).abs() < 1e-9);
+
+        // The opportunity carries the same breakdown
+        let opportunity = result.to_opportunity(&paths).unwrap();
+        assert_eq!(&opportunity.hops, hops);
+    }
+
+    #[test]
+    fn test_slippage_bounds() {
+        let (path, snapshot) = create_profitable_two_hop_case();
+        let paths = CompiledPathSet::from_paths(vec![path]).unwrap();
+
+        // Without a tolerance the bounds are the expected amounts
+        let mut config = ArbitrageConfig { slippage_tolerance_bps: 0, ..ArbitrageConfig::default() };
+        let exact = &ProfitCalculator::new(config.clone()).calculate_profits_parallel(&paths, &snapshot)[0];
+        assert_eq!(exact.execution.min_amount_out, exact.expected_output_amount);
+        assert_eq!(exact.execution.robust_net_profit_mnt_wei, exact.net_profit_mnt_wei);
+        assert!(exact.hops.iter().all(|hop| hop.min_amount_out == hop.amount_out));
+
+        // With a 1% tolerance every hop is bounded below its expected output
+        config.slippage_tolerance_bps = 100;
+        let tolerant = &ProfitCalculator::new(config).calculate_profits_parallel(&paths, &snapshot)[0];
+        assert_eq!(tolerant.optimal_input_amount, exact.optimal_input_amount);
+        assert!(tolerant.hops.iter().all(|hop| hop.min_amount_out < hop.amount_out));
+        assert_eq!(tolerant.execution.min_amount_out, tolerant.hops[1].min_amount_out);
+        assert!(tolerant.execution.min_amount_out < tolerant.expected_output_amount);
+        assert!(tolerant.execution.robust_net_profit_mnt_wei < tolerant.net_profit_mnt_wei);
+
+        // Two pools each moved by 1% cost roughly 2% of the output
+        let expected = u256_to_f64(tolerant.expected_output_amount);
+        let worst = u256_to_f64(tolerant.execution.min_amount_out);
+        assert!(((expected - worst) / expected - 0.0199).abs() < 1e-3);
+
+        let opportunity = tolerant.to_opportunity(&paths).unwrap();
+        assert_eq!(opportunity.execution.min_amount_out, tolerant.execution.min_amount_out);
+        assert_eq!(opportunity.execution.robust_net_profit_mnt_wei, tolerant.execution.robust_net_profit_mnt_wei);
+    }
+
+    #[test]
+    fn test_flash_financed_evaluation() {
+        let (path, snapshot) = create_profitable_two_hop_case();
+        let paths = CompiledPathSet::from_paths(vec![path]).unwrap();
+
+        let wallet = &ProfitCalculator::new(ArbitrageConfig::default()).calculate_profits_parallel(&paths, &snapshot)[0];
+        assert!(!wallet.execution.is_flash_financed());
+        assert_eq!(wallet.execution.flash_fee_mnt_wei, None);
+
+        let config = ArbitrageConfig {
+            enable_flash_swaps: true,
+            flash_swap_fee_bps: 9,
+            flash_swap_callback_gas: 100_000_000,
+            ..ArbitrageConfig::default()
+        };
+        let calculator = ProfitCalculator::new(config);
+        let flash = &calculator.calculate_profits_parallel(&paths, &snapshot)[0];
+
+        assert!(flash.calculation_successful);
+        let flash_fee_mnt_wei = flash.execution.flash_fee_mnt_wei.unwrap();
+        assert_eq!(flash_fee_mnt_wei, flash.optimal_input_amount * U256::from(9) / U256::from(10000));
+        assert_eq!(
+            flash.gross_profit_mnt_wei,
+            flash.expected_output_amount - flash.optimal_input_amount - flash_fee_mnt_wei
+        );
+        // The callback adds 100M gas at 0.02 gwei
+        assert_eq!(flash.gas_cost_mnt_wei, wallet.gas_cost_mnt_wei + U256::from(2_000_000_000_000_000u64));
+        assert!(flash.execution.robust_net_profit_mnt_wei < flash.net_profit_mnt_wei);
+        assert_eq!(
+            flash.execution.robust_net_profit_mnt_wei,
+            flash.execution.min_amount_out - flash.optimal_input_amount - flash_fee_mnt_wei - flash.gas_cost_mnt_wei
+        );
+
+        let opportunity = flash.to_opportunity(&paths).unwrap();
+        assert_eq!(opportunity.execution, flash.execution);
+    }
+
+    #[test]
+    fn test_inventory_capped_sizing() {
+        let (path, snapshot) = create_profitable_two_hop_case();
+        let paths = CompiledPathSet::from_paths(vec![path]).unwrap();
+        let uncapped = &ProfitCalculator::new(ArbitrageConfig::default()).calculate_profits_parallel(&paths, &snapshot)[0];
+        assert!(!uncapped.execution.is_inventory_capped());
+
+        let executor = Address::repeat_byte(0x42);
+        let cache = Arc::new(StateCache::new_default());
+        let one_eth = U256::from(1_000_000_000_000_000_000u64);
+        cache.set_balance(executor, WMNT, one_eth);
+
+        let calculator = ProfitCalculator::new(ArbitrageConfig::default()).with_inventory(InventoryView::new(executor, cache.clone()));
+        let capped = &calculator.calculate_profits_parallel(&paths, &snapshot)[0];
+
+        assert!(capped.calculation_successful);
+        assert_eq!(capped.optimal_input_amount, one_eth);
+        assert_eq!(capped.hops[0].amount_in, one_eth);
+        let inventory_cap = capped.execution.inventory_cap.unwrap();
+        assert_eq!(inventory_cap.uncapped_optimal_input_amount, uncapped.optimal_input_amount);
+        assert_eq!(inventory_cap.uncapped_net_profit_mnt_wei, uncapped.net_profit_mnt_wei);
+        assert!(capped.net_profit_mnt_wei < inventory_cap.uncapped_net_profit_mnt_wei);
+
+        let opportunity = capped.to_opportunity(&paths).unwrap();
+        assert_eq!(opportunity.execution.inventory_cap, Some(inventory_cap));
+
+        // Enough inventory leaves the optimum untouched
+        cache.set_balance(executor, WMNT, uncapped.optimal_input_amount);
+        let covered = &calculator.calculate_profits_parallel(&paths, &snapshot)[0];
+        assert!(!covered.execution.is_inventory_capped());
+        assert_eq!(covered.optimal_input_amount, uncapped.optimal_input_amount);
+
+        // An unknown balance fails the path instead of sizing it at zero
+        cache.remove_balance(executor, WMNT);
+        let unknown = &calculator.calculate_profits_parallel(&paths, &snapshot)[0];
+        assert!(!unknown.calculation_successful);
+        assert_eq!(unknown.error.as_ref().map(|failure| failure.kind), Some(CalculationErrorKind::MissingBalance));
+    }
+
+    #[test]
+    fn test_constant_product_in_amount_inverts_forward() {
+        let calculator = ProfitCalculator::new(ArbitrageConfig::default());
+        let pool_id = PoolId::Address(Address::repeat_byte(10));
+        let reserve0 = U256::from(1_000_000_007u64);
+        let reserve1 = U256::from(2_000_000_011u64);
+        let fee = U256::from(30);
+
+        for amount_out in [1u64, 999, 123_456, 50_000_000] {
+            for zero_for_one in [true, false] {
+                let amount_out = U256::from(amount_out);
+                let amount_in =
+                    calculator.simple_constant_product_in_amount(pool_id, amount_out, zero_for_one, reserve0, reserve1, fee).unwrap();
+                let forward = |amount_in| {
+                    calculator.simple_constant_product_formula(pool_id, amount_in, zero_for_one, reserve0, reserve1, fee).unwrap()
+                };
+
+                // The smallest input that buys the output
+                assert!(forward(amount_in) >= amount_out);
+                assert!(forward(amount_in - U256::from(1)) < amount_out);
+            }
+        }
+
+        let error = calculator.simple_constant_product_in_amount(pool_id, reserve1, true, reserve0, reserve1, fee).unwrap_err();
+        assert_eq!(error.kind(), CalculationErrorKind::ExceedsReserves);
+    }
+
+    #[test]
+    fn test_required_input_verifies_forward_result() {
+        let (path, snapshot) = create_profitable_two_hop_case();
+        let calculator = ProfitCalculator::new(ArbitrageConfig::default());
+        let paths = CompiledPathSet::from_paths(vec![path.clone()]).unwrap();
+        let result = &calculator.calculate_profits_parallel(&paths, &snapshot)[0];
+
+        let quote = calculator.calculate_required_input(&path, result.expected_output_amount, &snapshot).unwrap();
+        assert_eq!(quote.amounts.len(), 3);
+        assert_eq!(quote.amounts[0], quote.amount_in);
+        assert_eq!(quote.amounts[2], result.expected_output_amount);
+        assert!(quote.amount_in <= result.optimal_input_amount);
+        assert!(quote.surplus() >= result.gross_profit_mnt_wei);
+        assert!(calculator.verify_forward_result(result, &paths, &snapshot).unwrap());
+
+        // The intermediate amount never exceeds what the forward simulation produced
+        assert!(quote.amounts[1] <= result.hops[0].amount_out);
+
+        let mut missing = MarketSnapshot::new(1);
+        missing.set_pool_reserves(path.pools[0].get_pool_id(), U256::from(1000), U256::from(1000));
+        let error = calculator.calculate_required_input(&path, U256::from(1), &missing).unwrap_err();
+        assert_eq!(error.kind(), CalculationErrorKind::MissingState);
+        assert_eq!(error.pool_id(), Some(path.pools[1].get_pool_id()));
+    }
+
+    #[test]
+    fn test_profit_curve_of_path() {
+        let (path, snapshot) = create_profitable_two_hop_case();
+        let calculator = ProfitCalculator::new(ArbitrageConfig::default());
+        let paths = CompiledPathSet::from_paths(vec![path]).unwrap();
+        let result = &calculator.calculate_profits_parallel(&paths, &snapshot)[0];
+
+        let curve = calculator.profit_curve(&paths, 0, &snapshot, CurveSampling::default()).unwrap();
+        assert_eq!(curve.points.len(), 100);
+        assert_eq!(curve.search_optimal_input, Some(result.optimal_input_amount));
+        assert_eq!(curve.gas_cost_mnt_wei, result.gas_cost_mnt_wei);
+
+        // The sampled optimum is within one log step of the searched one
+        let sampled = u256_to_f64(curve.sampled_optimal_input.unwrap());
+        let searched = u256_to_f64(result.optimal_input_amount);
+        assert!((sampled / searched).ln().abs() < (10_000f64).ln() / 99.0);
+
+        // Gas makes the smallest trade a loss, so the curve crosses zero on its way up
+        let first = &curve.points[0];
+        assert!(first.net_profit_mnt_wei.is_negative());
+        assert!(first.marginal_profit > 0.0);
+        let break_even = curve.break_even_inputs[0];
+        assert!(break_even > first.input_amount && break_even < result.optimal_input_amount);
+
+        let csv = curve.to_csv().unwrap();
+        assert_eq!(csv.lines().count(), 101);
+        assert!(csv.starts_with("input_amount,output_amount,gross_profit_mnt_wei,net_profit_mnt_wei,marginal_profit"));
+
+        let json = curve.to_json();
+        assert_eq!(json["points"].as_array().unwrap().len(), 100);
+        assert_eq!(json["search_optimal_input"], result.optimal_input_amount.to_string());
+    }
 }
@@ -0,0 +1,276 @@
+use super::profit_calculator::u256_to_f64;
+use alloy_primitives::{I256, U256};
+use eyre::Result;
+use std::io::Write;
+
+/// Spacing of the sampled input amounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CurveScale {
+    Linear,
+    /// Geometric spacing, dense near `min_input` where most of the curvature is
+    #[default]
+    Log,
+}
+
+/// Input range and resolution of a [ProfitCurve]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurveSampling {
+    pub min_input: U256,
+    pub max_input: U256,
+    pub samples: usize,
+    pub scale: CurveScale,
+}
+
+impl Default for CurveSampling {
+    fn default() -> Self {
+        Self {
+            // Same range the optimizer searches for non flash-financed paths
+            min_input: U256::from(10_000_000_000_000_000u64), // 0.01 ETH
+            max_input: U256::from(100_000_000_000_000_000_000u128), // 100 ETH
+            samples: 100,
+            scale: CurveScale::Log,
+        }
+    }
+}
+
+impl CurveSampling {
+    /// The sampled input amounts, in increasing order and without duplicates
+    pub fn inputs(&self) -> Vec<U256> {
+        if self.samples == 0 || self.max_input < self.min_input {
+            return Vec::new();
+        }
+        if self.samples == 1 || self.max_input == self.min_input {
+            return vec![self.min_input];
+        }
+
+        let steps = self.samples - 1;
+        let mut inputs: Vec<U256> = (0..self.samples)
+            .map(|i| match self.scale {
+                CurveScale::Linear => {
+                    interpolate(self.min_input, self.max_input - self.min_input, U256::from(i), U256::from(steps))
+                }
+                CurveScale::Log => {
+                    // A log scale cannot start at zero, start at 1 wei instead
+                    let start = self.min_input.max(U256::from(1));
+                    let growth = (u256_to_f64(self.max_input) / u256_to_f64(start)).ln();
+                    // Only the growth factor is a float, the amounts stay in U256
+                    scale(start, (growth * i as f64 / steps as f64).exp()).min(self.max_input)
+                }
+            })
+            .collect();
+        // Pin the ends exactly, rounding must not move them
+        inputs[0] = self.min_input;
+        inputs[self.samples - 1] = self.max_input;
+        inputs.dedup();
+        inputs
+    }
+}
+
+/// `start + span * numerator / denominator` rounded to the nearest wei, for `numerator <= denominator`
+///
+/// Exact in U256 for denominators up to 128 bits, larger ones lose their low bits instead of overflowing.
+fn interpolate(start: U256, span: U256, numerator: U256, denominator: U256) -> U256 {
+    // Keep the remainder product below 2^256
+    let shift = denominator.bit_len().saturating_sub(128);
+    let (numerator, denominator) = (numerator >> shift, denominator >> shift);
+    if denominator.is_zero() {
+        return start;
+    }
+
+    let whole = span / denominator * numerator;
+    let remainder = ((span % denominator) * numerator + denominator / U256::from(2)) / denominator;
+    start + whole + remainder
+}
+
+/// `amount * factor` rounded to the nearest wei, for `factor >= 1` and with its 53 bits of precision
+fn scale(amount: U256, factor: f64) -> U256 {
+    let exponent = factor.max(1.0).log2().floor() as i32;
+    // factor = mantissa * 2^shift with a 53 bit integer mantissa
+    let shift = exponent - 52;
+    let mantissa = (factor.max(1.0) / 2f64.powi(shift)).round() as u64;
+    let product = amount.saturating_mul(U256::from(mantissa));
+    if shift >= 0 {
+        let shift = shift as usize;
+        if product.bit_len() + shift > 256 { U256::MAX } else { product << shift }
+    } else {
+        let shift = shift.unsigned_abs() as usize;
+        (product >> (shift - 1)).saturating_add(U256::from(1)) >> 1
+    }
+}
+
+/// A single sample of the profit curve
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfitCurvePoint {
+    pub input_amount: U256,
+    pub output_amount: U256,
+    /// Output minus input (and flash fee), negative for losing trades
+    pub gross_profit_mnt_wei: I256,
+    /// Gross profit minus gas cost
+    pub net_profit_mnt_wei: I256,
+    /// Slope of the net profit against the input around this point, 1.0 would mean every extra wei in is a wei of profit
+    pub marginal_profit: f64,
+}
+
+/// Profit as a function of the input amount for one path
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfitCurve {
+    pub path_index: usize,
+    pub block_number: u64,
+    pub gas_cost_mnt_wei: U256,
+    pub flash_financed: bool,
+    /// Samples that could be simulated, by increasing input
+    pub points: Vec<ProfitCurvePoint>,
+    /// Input of the sample with the highest net profit
+    pub sampled_optimal_input: Option<U256>,
+    /// Optimal input found by the calculator's own search, for comparison with the samples
+    pub search_optimal_input: Option<U256>,
+    /// Inputs where the net profit changes sign, interpolated between samples
+    pub break_even_inputs: Vec<U256>,
+}
+
+impl ProfitCurve {
+    pub(crate) fn from_samples(
+        path_index: usize,
+        block_number: u64,
+        gas_cost_mnt_wei: U256,
+        flash_financed: bool,
+        samples: Vec<(U256, U256, I256)>,
+        search_optimal_input: Option<U256>,
+    ) -> Self {
+        let gas_cost = I256::from_raw(gas_cost_mnt_wei);
+        let net_profits: Vec<I256> = samples.iter().map(|(_, _, gross)| *gross - gas_cost).collect();
+
+        // Central differences inside the range, one-sided ones at its ends
+        let slope = |from: usize, to: usize| {
+            let run = u256_to_f64(samples[to].0) - u256_to_f64(samples[from].0);
+            if run > 0.0 { (i256_to_f64(net_profits[to]) - i256_to_f64(net_profits[from])) / run } else { 0.0 }
+        };
+        let last = samples.len().saturating_sub(1);
+        let points = samples
+            .iter()
+            .enumerate()
+            .map(|(i, &(input_amount, output_amount, gross_profit_mnt_wei))| ProfitCurvePoint {
+                input_amount,
+                output_amount,
+                gross_profit_mnt_wei,
+                net_profit_mnt_wei: net_profits[i],
+                marginal_profit: slope(i.saturating_sub(1), (i + 1).min(last)),
+            })
+            .collect::<Vec<_>>();
+
+        let sampled_optimal_input = points.iter().max_by_key(|point| point.net_profit_mnt_wei).map(|point| point.input_amount);
+
+        let break_even_inputs = points
+            .windows(2)
+            .filter(|pair| pair[0].net_profit_mnt_wei.is_negative() != pair[1].net_profit_mnt_wei.is_negative())
+            .map(|pair| {
+                // The profits have opposite signs, the zero is |y0| / (|y0| + |y1|) of the way along
+                let (y0, y1) = (pair[0].net_profit_mnt_wei.unsigned_abs(), pair[1].net_profit_mnt_wei.unsigned_abs());
+                interpolate(pair[0].input_amount, pair[1].input_amount - pair[0].input_amount, y0, y0.saturating_add(y1))
+            })
+            .collect();
+
+        Self {
+            path_index,
+            block_number,
+            gas_cost_mnt_wei,
+            flash_financed,
+            points,
+            sampled_optimal_input,
+            search_optimal_input,
+            break_even_inputs,
+        }
+    }
+
+    /// Write the samples as CSV, one row per point with amounts as decimal wei
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["input_amount", "output_amount", "gross_profit_mnt_wei", "net_profit_mnt_wei", "marginal_profit"])?;
+        for point in &self.points {
+            writer.write_record([
+                point.input_amount.to_string(),
+                point.output_amount.to_string(),
+                point.gross_profit_mnt_wei.to_string(),
+                point.net_profit_mnt_wei.to_string(),
+                point.marginal_profit.to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn to_csv(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        self.write_csv(&mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// The curve and its summary as JSON, with amounts as decimal wei strings
+    pub fn to_json(&self) -> serde_json::Value {
+        let points: Vec<serde_json::Value> = self
+            .points
+            .iter()
+            .map(|point| {
+                serde_json::json!({
+                    "input_amount": point.input_amount.to_string(),
+                    "output_amount": point.output_amount.to_string(),
+                    "gross_profit_mnt_wei": point.gross_profit_mnt_wei.to_string(),
+                    "net_profit_mnt_wei": point.net_profit_mnt_wei.to_string(),
+                    "marginal_profit": point.marginal_profit,
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "path_index": self.path_index,
+            "block_number": self.block_number,
+            "gas_cost_mnt_wei": self.gas_cost_mnt_wei.to_string(),
+            "flash_financed": self.flash_financed,
+            "sampled_optimal_input": self.sampled_optimal_input.map(|input| input.to_string()),
+            "search_optimal_input": self.search_optimal_input.map(|input| input.to_string()),
+            "break_even_inputs": self.break_even_inputs.iter().map(|input| input.to_string()).collect::<Vec<_>>(),
+            "points": points,
+        })
+    }
+}
+
+fn i256_to_f64(value: I256) -> f64 {
+    value.to_string().parse::<f64>().unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_scales() {
+        let linear = CurveSampling { min_input: U256::from(100), max_input: U256::from(500), samples: 5, scale: CurveScale::Linear };
+        assert_eq!(linear.inputs(), [100, 200, 300, 400, 500].map(U256::from).to_vec());
+
+        let log = CurveSampling { scale: CurveScale::Log, ..linear };
+        let inputs = log.inputs();
+        assert_eq!(inputs.first(), Some(&U256::from(100)));
+        assert_eq!(inputs.last(), Some(&U256::from(500)));
+        // Geometric spacing: each step multiplies by 5^(1/4)
+        assert_eq!(inputs[1], U256::from(150));
+        assert!(inputs.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_sampling_keeps_wei_precision() {
+        // One wei apart at the 10k ETH flash bound, f64 cannot tell them apart
+        let max_input = U256::from(10_000u64) * U256::from(1_000_000_000_000_000_000u64);
+        let narrow = CurveSampling { min_input: max_input - U256::from(4), max_input, samples: 5, scale: CurveScale::Linear };
+        assert_eq!(narrow.inputs(), (0..5u64).rev().map(|wei| max_input - U256::from(wei)).collect::<Vec<_>>());
+
+        // Beyond u128 nothing saturates
+        let huge = CurveSampling { min_input: U256::ZERO, max_input: U256::MAX, samples: 3, scale: CurveScale::Linear };
+        assert_eq!(huge.inputs()[1], U256::MAX / U256::from(2) + U256::from(1));
+        let huge = CurveSampling { min_input: U256::from(1), scale: CurveScale::Log, ..huge };
+        let inputs = huge.inputs();
+        assert_eq!(inputs.len(), 3);
+        // The geometric middle of 1 and 2^256 is 2^128
+        let middle = U256::from(1) << 128;
+        assert!(inputs[1].abs_diff(middle) < middle >> 40);
+    }
+}
@@ -0,0 +1,279 @@
+use super::types::MarketSnapshot;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, mpsc};
+
+/// How market snapshots are handed from the data layer to the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotDelivery {
+    /// Bounded FIFO queue, a new snapshot is dropped while the queue is full
+    Queue,
+    /// A single slot holding the most recent snapshot, an unread snapshot is replaced by the
+    /// next block so that a slow consumer always picks up the freshest state
+    #[default]
+    Latest,
+}
+
+impl std::str::FromStr for SnapshotDelivery {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> eyre::Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "queue" => Ok(SnapshotDelivery::Queue),
+            "latest" => Ok(SnapshotDelivery::Latest),
+            other => Err(eyre::eyre!("Unknown snapshot delivery mode: {} (expected queue or latest)", other)),
+        }
+    }
+}
+
+/// What happened to a snapshot handed to [SnapshotSender::send]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    Delivered,
+    /// Delivered, the unread snapshot of `skipped_block` it replaced will never be seen
+    Replaced { skipped_block: u64 },
+    /// Not delivered because the queue was full
+    Dropped,
+}
+
+impl SendOutcome {
+    pub fn skipped_block(&self, sent_block: u64) -> Option<u64> {
+        match self {
+            SendOutcome::Delivered => None,
+            SendOutcome::Replaced { skipped_block } => Some(*skipped_block),
+            SendOutcome::Dropped => Some(sent_block),
+        }
+    }
+}
+
+/// The receiving side is gone, the snapshot was not delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("snapshot channel is closed")]
+pub struct SnapshotChannelClosed;
+
+/// Counts shared by both ends of a snapshot channel
+#[derive(Debug, Default)]
+pub struct DeliveryStats {
+    delivered: AtomicU64,
+    skipped: AtomicU64,
+    latest_block: Arc<AtomicU64>,
+}
+
+impl DeliveryStats {
+    /// Snapshots the consumer received
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Blocks the consumer will never see, replaced by a newer snapshot or dropped on a full queue
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    /// Highest block number sent so far, whether or not it was received yet
+    pub fn latest_block(&self) -> u64 {
+        self.latest_block.load(Ordering::Acquire)
+    }
+
+    /// Shared handle to [DeliveryStats::latest_block], e.g. to stop evaluating an outdated
+    /// snapshot with a [super::types::CalculationCutoff]
+    pub fn latest_block_signal(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.latest_block)
+    }
+}
+
+#[derive(Debug, Default)]
+struct LatestState {
+    snapshot: Option<MarketSnapshot>,
+    sender_closed: bool,
+    receiver_closed: bool,
+}
+
+#[derive(Debug, Default)]
+struct LatestSlot {
+    state: Mutex<LatestState>,
+    notify: Notify,
+}
+
+#[derive(Debug)]
+enum SenderKind {
+    Queue(mpsc::Sender<MarketSnapshot>),
+    Latest(Arc<LatestSlot>),
+}
+
+/// Sending side of a channel created by [snapshot_channel]
+#[derive(Debug)]
+pub struct SnapshotSender {
+    kind: SenderKind,
+    stats: Arc<DeliveryStats>,
+}
+
+impl SnapshotSender {
+    /// Hand over a snapshot without waiting for the consumer
+    pub fn send(&self, snapshot: MarketSnapshot) -> Result<SendOutcome, SnapshotChannelClosed> {
+        self.stats.latest_block.fetch_max(snapshot.block_number, Ordering::AcqRel);
+        let outcome = match &self.kind {
+            SenderKind::Queue(sender) => match sender.try_send(snapshot) {
+                Ok(()) => SendOutcome::Delivered,
+                Err(mpsc::error::TrySendError::Full(_)) => SendOutcome::Dropped,
+                Err(mpsc::error::TrySendError::Closed(_)) => return Err(SnapshotChannelClosed),
+            },
+            SenderKind::Latest(slot) => {
+                let mut state = slot.state.lock().map_err(|_| SnapshotChannelClosed)?;
+                if state.receiver_closed {
+                    return Err(SnapshotChannelClosed);
+                }
+                let replaced = state.snapshot.replace(snapshot);
+                drop(state);
+                slot.notify.notify_one();
+                match replaced {
+                    Some(replaced) => SendOutcome::Replaced { skipped_block: replaced.block_number },
+                    None => SendOutcome::Delivered,
+                }
+            }
+        };
+
+        if outcome != SendOutcome::Delivered {
+            self.stats.skipped.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(outcome)
+    }
+
+    pub fn stats(&self) -> Arc<DeliveryStats> {
+        Arc::clone(&self.stats)
+    }
+}
+
+impl Drop for SnapshotSender {
+    fn drop(&mut self) {
+        if let SenderKind::Latest(slot) = &self.kind {
+            if let Ok(mut state) = slot.state.lock() {
+                state.sender_closed = true;
+            }
+            slot.notify.notify_one();
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ReceiverKind {
+    Queue(mpsc::Receiver<MarketSnapshot>),
+    Latest(Arc<LatestSlot>),
+}
+
+/// Receiving side of a channel created by [snapshot_channel]
+#[derive(Debug)]
+pub struct SnapshotReceiver {
+    kind: ReceiverKind,
+    stats: Arc<DeliveryStats>,
+}
+
+impl SnapshotReceiver {
+    /// Wait for the next snapshot, `None` once the sender is gone and nothing is left to read
+    ///
+    /// Cancel safe: a snapshot is only taken out of the channel when it is returned.
+    pub async fn recv(&mut self) -> Option<MarketSnapshot> {
+        let snapshot = self.recv_inner().await;
+        if snapshot.is_some() {
+            self.stats.delivered.fetch_add(1, Ordering::Relaxed);
+        }
+        snapshot
+    }
+
+    async fn recv_inner(&mut self) -> Option<MarketSnapshot> {
+        match &mut self.kind {
+            ReceiverKind::Queue(receiver) => receiver.recv().await,
+            ReceiverKind::Latest(slot) => loop {
+                {
+                    let mut state = slot.state.lock().ok()?;
+                    if let Some(snapshot) = state.snapshot.take() {
+                        return Some(snapshot);
+                    }
+                    if state.sender_closed {
+                        return None;
+                    }
+                }
+                slot.notify.notified().await;
+            },
+        }
+    }
+
+    pub fn stats(&self) -> Arc<DeliveryStats> {
+        Arc::clone(&self.stats)
+    }
+}
+
+impl Drop for SnapshotReceiver {
+    fn drop(&mut self) {
+        if let ReceiverKind::Latest(slot) = &self.kind
+            && let Ok(mut state) = slot.state.lock()
+        {
+            state.receiver_closed = true;
+        }
+    }
+}
+
+impl From<mpsc::Receiver<MarketSnapshot>> for SnapshotReceiver {
+    fn from(receiver: mpsc::Receiver<MarketSnapshot>) -> Self {
+        Self { kind: ReceiverKind::Queue(receiver), stats: Arc::default() }
+    }
+}
+
+/// Create a snapshot channel, `buffer_size` is only used by [SnapshotDelivery::Queue]
+pub fn snapshot_channel(delivery: SnapshotDelivery, buffer_size: usize) -> (SnapshotSender, SnapshotReceiver) {
+    let stats = Arc::new(DeliveryStats::default());
+    let (sender, receiver) = match delivery {
+        SnapshotDelivery::Queue => {
+            let (sender, receiver) = mpsc::channel(buffer_size.max(1));
+            (SenderKind::Queue(sender), ReceiverKind::Queue(receiver))
+        }
+        SnapshotDelivery::Latest => {
+            let slot = Arc::new(LatestSlot::default());
+            (SenderKind::Latest(Arc::clone(&slot)), ReceiverKind::Latest(slot))
+        }
+    };
+    (SnapshotSender { kind: sender, stats: Arc::clone(&stats) }, SnapshotReceiver { kind: receiver, stats })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_latest_delivery_keeps_newest_block() {
+        let (sender, mut receiver) = snapshot_channel(SnapshotDelivery::Latest, 1);
+        assert_eq!(sender.send(MarketSnapshot::new(1)), Ok(SendOutcome::Delivered));
+        assert_eq!(sender.send(MarketSnapshot::new(2)), Ok(SendOutcome::Replaced { skipped_block: 1 }));
+        assert_eq!(sender.send(MarketSnapshot::new(3)), Ok(SendOutcome::Replaced { skipped_block: 2 }));
+
+        assert_eq!(receiver.recv().await.map(|snapshot| snapshot.block_number), Some(3));
+        assert_eq!(sender.send(MarketSnapshot::new(4)), Ok(SendOutcome::Delivered));
+        assert_eq!(receiver.recv().await.map(|snapshot| snapshot.block_number), Some(4));
+
+        let stats = receiver.stats();
+        assert_eq!((stats.delivered(), stats.skipped()), (2, 2));
+
+        // A waiting receiver is woken up by the next block, and sees the end of the stream
+        let waiting = tokio::spawn(async move { (receiver.recv().await.map(|snapshot| snapshot.block_number), receiver.recv().await) });
+        tokio::task::yield_now().await;
+        sender.send(MarketSnapshot::new(5)).unwrap();
+        drop(sender);
+        let (next, end) = waiting.await.unwrap();
+        assert_eq!(next, Some(5));
+        assert!(end.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_queue_delivery_drops_when_full() {
+        let (sender, mut receiver) = snapshot_channel(SnapshotDelivery::Queue, 1);
+        assert_eq!(sender.send(MarketSnapshot::new(1)), Ok(SendOutcome::Delivered));
+        assert_eq!(sender.send(MarketSnapshot::new(2)), Ok(SendOutcome::Dropped));
+        assert_eq!(receiver.recv().await.map(|snapshot| snapshot.block_number), Some(1));
+        assert_eq!(sender.stats().skipped(), 1);
+
+        drop(receiver);
+        assert_eq!(sender.send(MarketSnapshot::new(3)), Err(SnapshotChannelClosed));
+    }
+}
@@ -0,0 +1,321 @@
+use super::graph::{CompiledPathSet, SwapPath, TokenGraph};
+use super::pathfinder::Pathfinder;
+use super::pools::PoolId;
+use super::profit_calculator::ProfitCalculator;
+use super::types::{
+    ArbitrageConfig, ArbitrageOpportunity, CalculationCutoff, InventoryView, MarketSnapshot, ProfitCalculationBatch,
+    ProfitCalculationResult,
+};
+use crate::utils::constants::WMNT;
+use alloy_primitives::U256;
+use eyre::{Result, eyre};
+use petgraph::visit::EdgeRef;
+use tracing::{info, warn};
+
+/// Name of the [CyclicStrategy], the precomputed cycle detection every engine starts with
+pub const CYCLIC_STRATEGY_NAME: &str = CyclicStrategy::NAME;
+
+/// A change to the pools of the token graph a strategy was initialized with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolChange {
+    Added(PoolId),
+    Removed(PoolId),
+    Enabled(PoolId),
+    Disabled(PoolId),
+}
+
+impl PoolChange {
+    pub fn pool_id(&self) -> PoolId {
+        match self {
+            PoolChange::Added(pool_id) | PoolChange::Removed(pool_id) | PoolChange::Enabled(pool_id) | PoolChange::Disabled(pool_id) => {
+                *pool_id
+            }
+        }
+    }
+}
+
+/// What a strategy found in a snapshot
+#[derive(Debug, Clone, Default)]
+pub struct StrategyEvaluation {
+    pub opportunities: Vec<ArbitrageOpportunity>,
+    /// Profit results of the evaluated paths, `None` for strategies that don't report them
+    pub batch: Option<ProfitCalculationBatch>,
+}
+
+/// An opportunity detector run by the [super::ArbitrageEngine] on every market snapshot
+///
+/// Strategies are initialized once with the token graph, then asked for opportunities on each
+/// snapshot in the order they were registered. Opportunities are attributed to the strategy by
+/// the engine, a strategy does not have to set [ArbitrageOpportunity::strategy] itself.
+pub trait ArbitrageStrategy: Send + Sync {
+    /// Unique name used for attribution
+    fn name(&self) -> &str;
+
+    /// Prepare the strategy for the pools of the token graph, e.g. precompute its paths
+    fn init(&mut self, token_graph: &TokenGraph) -> Result<()>;
+
+    /// Find the opportunities of a snapshot
+    fn on_snapshot(&self, market_snapshot: &MarketSnapshot) -> Result<Vec<ArbitrageOpportunity>>;
+
+    /// Find the opportunities of a snapshot with the profit results they were selected from,
+    /// stopping early once `cutoff` is reached
+    ///
+    /// This is what the engine calls. The results feed the failure statistics and explain to the
+    /// opportunity tracker why an opportunity disappeared. The default runs
+    /// [ArbitrageStrategy::on_snapshot] to completion and reports no results.
+    fn evaluate(&self, market_snapshot: &MarketSnapshot, _cutoff: &CalculationCutoff) -> Result<StrategyEvaluation> {
+        Ok(StrategyEvaluation { opportunities: self.on_snapshot(market_snapshot)?, batch: None })
+    }
+
+    /// The paths evaluated on every snapshot, for strategies working on a fixed set of paths
+    fn compiled_paths(&self) -> Option<&CompiledPathSet> {
+        None
+    }
+
+    /// Cap trade sizes at the executor's inventory, or lift the cap with `None`
+    fn set_inventory(&mut self, _inventory: Option<InventoryView>) {}
+
+    /// React to a pool change, `token_graph` already reflects it
+    fn on_pool_change(&mut self, _token_graph: &TokenGraph, _change: PoolChange) -> Result<()> {
+        Ok(())
+    }
+
+    /// Apply a configuration changed at runtime, see [super::ArbitrageEngine::update_config]
+    ///
+    /// Strategies that keep settings of the configuration they were created with, e.g. the
+    /// profit threshold, have to take them over here. An error rejects the configuration.
+    fn on_config_change(&mut self, _config: &ArbitrageConfig) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Profitable results of `paths` as opportunities
+fn profitable_opportunities(
+    results: &[ProfitCalculationResult],
+    paths: &CompiledPathSet,
+    min_profit_threshold_mnt_wei: U256,
+) -> Vec<ArbitrageOpportunity> {
+    results
+        .iter()
+        .filter(|result| result.calculation_successful && result.execution.robust_net_profit_mnt_wei > min_profit_threshold_mnt_wei)
+        .filter_map(|result| result.to_opportunity(paths))
+        .collect()
+}
+
+/// Cycles from WMNT back to WMNT, precomputed from the token graph
+///
+/// Every snapshot evaluates all precomputed cycles with the profit calculator. The cycles are
+/// found by the [Pathfinder] when the strategy is initialized and found again when the pools
+/// of the token graph change. [super::ArbitrageEngine::new] registers it as its only strategy.
+pub struct CyclicStrategy {
+    config: ArbitrageConfig,
+    calculator: ProfitCalculator,
+    paths: CompiledPathSet,
+}
+
+impl CyclicStrategy {
+    pub const NAME: &'static str = "cyclic";
+
+    pub fn new(config: ArbitrageConfig) -> Self {
+        Self { calculator: ProfitCalculator::new(config.clone()), config, paths: CompiledPathSet::default() }
+    }
+
+    /// The compiled cycles
+    pub fn paths(&self) -> &CompiledPathSet {
+        &self.paths
+    }
+
+    fn precompute(&self, token_graph: &TokenGraph) -> Result<CompiledPathSet> {
+        let pathfinder = Pathfinder::new(self.config.max_hops, self.config.max_precomputed_paths);
+        let paths = pathfinder.precompute_arbitrage_paths(token_graph)?;
+        CompiledPathSet::compile(token_graph, paths)
+    }
+}
+
+impl ArbitrageStrategy for CyclicStrategy {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn init(&mut self, token_graph: &TokenGraph) -> Result<()> {
+        info!("开始预计算套利路径...");
+        let paths = self.precompute(token_graph)?;
+        if paths.is_empty() {
+            return Err(eyre!("未找到任何套利路径，请检查代币图配置"));
+        }
+        self.paths = paths;
+        info!("循环套利策略初始化完成，路径数量: {}", self.paths.len());
+        Ok(())
+    }
+
+    fn on_snapshot(&self, market_snapshot: &MarketSnapshot) -> Result<Vec<ArbitrageOpportunity>> {
+        Ok(self.evaluate(market_snapshot, &CalculationCutoff::new())?.opportunities)
+    }
+
+    fn evaluate(&self, market_snapshot: &MarketSnapshot, cutoff: &CalculationCutoff) -> Result<StrategyEvaluation> {
+        let batch = self.calculator.calculate_profits_with_cutoff(&self.paths, market_snapshot, cutoff);
+        let opportunities = profitable_opportunities(&batch.results, &self.paths, self.config.min_profit_threshold_mnt_wei);
+        Ok(StrategyEvaluation { opportunities, batch: Some(batch) })
+    }
+
+    fn on_pool_change(&mut self, token_graph: &TokenGraph, change: PoolChange) -> Result<()> {
+        self.paths = self.precompute(token_graph)?;
+        if self.paths.is_empty() {
+            warn!("池子变更 {:?} 后没有剩余的循环套利路径", change);
+        } else {
+            info!("池子变更 {:?}，重新预计算循环套利路径: {}", change, self.paths.len());
+        }
+        Ok(())
+    }
+
+    fn on_config_change(&mut self, config: &ArbitrageConfig) -> Result<()> {
+        let inventory = self.calculator.inventory().cloned();
+        self.calculator = ProfitCalculator::new(config.clone());
+        self.calculator.set_inventory(inventory);
+        self.config = config.clone();
+        Ok(())
+    }
+
+    fn compiled_paths(&self) -> Option<&CompiledPathSet> {
+        Some(&self.paths)
+    }
+
+    fn set_inventory(&mut self, inventory: Option<InventoryView>) {
+        self.calculator.set_inventory(inventory);
+    }
+}
+
+/// Two-pool spreads between pools of the same WMNT pair
+///
+/// Buys a token in one pool and sells it back to WMNT in another pool of the same pair,
+/// typically on another DEX. The precomputed cycles of the engine start at three hops, so these
+/// two-hop paths are not covered by them. Only WMNT pairs are considered because profits and
+/// gas are accounted in MNT.
+pub struct CrossDexSpreadStrategy {
+    calculator: ProfitCalculator,
+    min_profit_threshold_mnt_wei: U256,
+    paths: CompiledPathSet,
+}
+
+impl CrossDexSpreadStrategy {
+    pub const NAME: &'static str = "cross_dex_spread";
+
+    pub fn new(config: ArbitrageConfig) -> Self {
+        Self {
+            min_profit_threshold_mnt_wei: config.min_profit_threshold_mnt_wei,
+            calculator: ProfitCalculator::new(config),
+            paths: CompiledPathSet::default(),
+        }
+    }
+
+    /// The compiled spread paths, one per ordered pair of pools of a WMNT pair
+    pub fn paths(&self) -> &CompiledPathSet {
+        &self.paths
+    }
+
+    fn spread_paths(token_graph: &TokenGraph) -> Result<Vec<SwapPath>> {
+        let wmnt_node = token_graph.token_index.get(&WMNT).ok_or_else(|| eyre!("WMNT token not found in graph"))?;
+        let wmnt_token = token_graph.tokens.get(&WMNT).ok_or_else(|| eyre!("WMNT token not found in tokens map"))?;
+
+        let mut paths = Vec::new();
+        for edge in token_graph.graph.edges(*wmnt_node) {
+            let other_node = if edge.source() == *wmnt_node { edge.target() } else { edge.source() };
+            let other_token = &token_graph.graph[other_node].token;
+            let pools: Vec<_> = edge.weight().values().filter(|pool_edge| pool_edge.is_active).map(|pool_edge| &pool_edge.inner).collect();
+
+            for buy_pool in pools.iter() {
+                for sell_pool in pools.iter().filter(|sell_pool| sell_pool.get_pool_id() != buy_pool.get_pool_id()) {
+                    paths.push(SwapPath::new(
+                        vec![wmnt_token.clone(), other_token.clone(), wmnt_token.clone()],
+                        vec![(*buy_pool).clone(), (*sell_pool).clone()],
+                    ));
+                }
+            }
+        }
+        Ok(paths)
+    }
+}
+
+impl ArbitrageStrategy for CrossDexSpreadStrategy {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn init(&mut self, token_graph: &TokenGraph) -> Result<()> {
+        let paths = Self::spread_paths(token_graph)?;
+        self.paths = CompiledPathSet::compile(token_graph, paths)?;
+        info!("跨 DEX 价差策略初始化完成，路径数量: {}", self.paths.len());
+        Ok(())
+    }
+
+    fn on_snapshot(&self, market_snapshot: &MarketSnapshot) -> Result<Vec<ArbitrageOpportunity>> {
+        let results = self.calculator.calculate_profits_parallel(&self.paths, market_snapshot);
+        Ok(profitable_opportunities(&results, &self.paths, self.min_profit_threshold_mnt_wei))
+    }
+
+    fn on_pool_change(&mut self, token_graph: &TokenGraph, _change: PoolChange) -> Result<()> {
+        // Spread paths are cheap to enumerate, rebuild them from the updated graph
+        self.init(token_graph)
+    }
+
+    fn on_config_change(&mut self, config: &ArbitrageConfig) -> Result<()> {
+        let inventory = self.calculator.inventory().cloned();
+        self.min_profit_threshold_mnt_wei = config.min_profit_threshold_mnt_wei;
+        self.calculator = ProfitCalculator::new(config.clone());
+        self.calculator.set_inventory(inventory);
+        Ok(())
+    }
+
+    fn compiled_paths(&self) -> Option<&CompiledPathSet> {
+        Some(&self.paths)
+    }
+
+    fn set_inventory(&mut self, inventory: Option<InventoryView>) {
+        self.calculator.set_inventory(inventory);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockPool, PoolWrapper, Token};
+    use alloy_primitives::Address;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_cross_dex_spread_strategy() -> Result<()> {
+        let mut token_graph = TokenGraph::new();
+        token_graph.add_or_get_token_idx_by_token(Arc::new(Token::new_with_data(WMNT, Some("WMNT".to_string()), None, Some(18))));
+        token_graph.add_or_get_token_idx_by_token(Arc::new(Token::repeat_byte(1)));
+        let cheap = PoolWrapper::from(MockPool::new(WMNT, Address::repeat_byte(1), Address::repeat_byte(10)));
+        let expensive = PoolWrapper::from(MockPool::new(WMNT, Address::repeat_byte(1), Address::repeat_byte(11)));
+        token_graph.add_pool(cheap.clone())?;
+        token_graph.add_pool(expensive.clone())?;
+
+        let mut strategy = CrossDexSpreadStrategy::new(ArbitrageConfig::default());
+        strategy.init(&token_graph)?;
+        assert_eq!(strategy.paths().len(), 2);
+
+        // TOKEN1 is token0: it is cheaper in the first pool
+        let eth = |amount: u64| U256::from(amount) * U256::from(1_000_000_000_000_000_000u64);
+        let mut snapshot = MarketSnapshot::new(1);
+        snapshot.set_pool_reserves(cheap.get_pool_id(), eth(1_200), eth(1_000));
+        snapshot.set_pool_reserves(expensive.get_pool_id(), eth(1_000), eth(1_000));
+
+        let opportunities = strategy.on_snapshot(&snapshot)?;
+        assert_eq!(opportunities.len(), 1);
+        assert_eq!(opportunities[0].path.pools[0].get_pool_id(), cheap.get_pool_id());
+
+        // A third pool adds four more ordered pairs
+        token_graph.add_pool(PoolWrapper::from(MockPool::new(WMNT, Address::repeat_byte(1), Address::repeat_byte(12))))?;
+        strategy.on_pool_change(&token_graph, PoolChange::Added(PoolId::Address(Address::repeat_byte(12))))?;
+        assert_eq!(strategy.paths().len(), 6);
+
+        // A raised threshold applies to the next snapshot
+        let config = ArbitrageConfig { min_profit_threshold_mnt_wei: eth(1_000), ..ArbitrageConfig::default() };
+        strategy.on_config_change(&config)?;
+        assert!(strategy.on_snapshot(&snapshot)?.is_empty());
+
+        Ok(())
+    }
+}
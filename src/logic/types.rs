@@ -1,7 +1,12 @@
-use super::graph::SwapPath;
-use super::pools::PoolId;
-use alloy_primitives::U256;
+use super::graph::{CompiledPathSet, SwapPath, SwapPathHash};
+use super::opportunity_selector::SelectionMode;
+use super::pools::{CalculationError, CalculationErrorKind, DensePoolIndex, PoolBitSet, PoolId};
+use crate::utils::StateCache;
+use alloy_primitives::{Address, U256};
+use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 
 /// Represents a profitable arbitrage opportunity discovered by the engine
@@ -23,6 +28,14 @@ pub struct ArbitrageOpportunity {
     pub discovered_at: Instant,
     /// Expected return in the output token (usually WMNT)
     pub expected_output_amount: U256,
+    /// Per-hop breakdown at the optimal input amount, one entry per pool of the path
+    pub hops: Vec<HopTrace>,
+    /// Financing, inventory cap and worst-case output of the execution
+    pub execution: ExecutionDetails,
+    /// Name of the strategy that found the opportunity
+    pub strategy: Option<String>,
+    /// Found in a snapshot whose evaluation was cut short, better opportunities may exist
+    pub truncated: bool,
 }
 
 impl ArbitrageOpportunity {
@@ -56,54 +69,332 @@ impl ArbitrageOpportunity {
             profit_margin_percent,
             discovered_at: Instant::now(),
             expected_output_amount,
+            hops: Vec::new(),
+            execution: ExecutionDetails::exact(optimal_input_amount, expected_output_amount, gas_cost_mnt_wei),
+            strategy: None,
+            truncated: false,
         }
     }
 
+    /// Mark the opportunity as found by an evaluation that did not cover every path
+    pub fn with_truncation(mut self) -> Self {
+        self.truncated = true;
+        self
+    }
+
+    /// Attribute the opportunity to a strategy
+    pub fn with_strategy(mut self, strategy: impl Into<String>) -> Self {
+        self.strategy = Some(strategy.into());
+        self
+    }
+
+    /// Attach the per-hop breakdown
+    pub fn with_hops(mut self, hops: Vec<HopTrace>) -> Self {
+        self.hops = hops;
+        self
+    }
+
+    /// Attach how the opportunity is executed
+    pub fn with_execution(mut self, execution: ExecutionDetails) -> Self {
+        self.execution = execution;
+        self
+    }
+
     pub fn is_profitable(&self, min_profit_threshold_mnt_wei: U256) -> bool {
         self.net_profit_mnt_wei > min_profit_threshold_mnt_wei
     }
+
+    /// Returns true if the opportunity clears the threshold even in the worst case under the slippage tolerance
+    pub fn is_robustly_profitable(&self, min_profit_threshold_mnt_wei: U256) -> bool {
+        self.execution.robust_net_profit_mnt_wei > min_profit_threshold_mnt_wei
+    }
+}
+
+/// How an opportunity is executed: how the input is financed, whether the inventory capped it
+/// and the output it must at least return
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionDetails {
+    /// Minimum final output to enforce on execution (worst case under the slippage tolerance)
+    pub min_amount_out: U256,
+    /// Net profit at `min_amount_out`, after the flash fee and gas
+    pub robust_net_profit_mnt_wei: U256,
+    /// Fee for flash-borrowing the input from the first pool and repaying it from the last hop,
+    /// `None` if the input comes from the inventory
+    pub flash_fee_mnt_wei: Option<U256>,
+    /// The optimum the input was capped from because it exceeded the executor's inventory
+    pub inventory_cap: Option<InventoryCap>,
+}
+
+/// The optimum of a path before its input was capped to the inventory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InventoryCap {
+    /// Optimal input amount ignoring the inventory
+    pub uncapped_optimal_input_amount: U256,
+    /// Net profit at the uncapped optimal input amount
+    pub uncapped_net_profit_mnt_wei: U256,
+}
+
+impl ExecutionDetails {
+    /// Execute `amount_in` for at least `min_amount_out`
+    pub fn new(
+        amount_in: U256,
+        min_amount_out: U256,
+        gas_cost_mnt_wei: U256,
+        flash_fee_mnt_wei: Option<U256>,
+        inventory_cap: Option<InventoryCap>,
+    ) -> Self {
+        let cost = amount_in + flash_fee_mnt_wei.unwrap_or_default();
+        Self {
+            min_amount_out,
+            robust_net_profit_mnt_wei: min_amount_out.saturating_sub(cost).saturating_sub(gas_cost_mnt_wei),
+            flash_fee_mnt_wei,
+            inventory_cap,
+        }
+    }
+
+    /// Execute from the inventory without any slippage tolerance
+    pub fn exact(amount_in: U256, amount_out: U256, gas_cost_mnt_wei: U256) -> Self {
+        Self::new(amount_in, amount_out, gas_cost_mnt_wei, None, None)
+    }
+
+    pub fn is_flash_financed(&self) -> bool {
+        self.flash_fee_mnt_wei.is_some()
+    }
+
+    pub fn is_inventory_capped(&self) -> bool {
+        self.inventory_cap.is_some()
+    }
+}
+
+/// Breakdown of a single hop at a given input amount
+///
+/// Spot prices are quoted in raw token units (output token per input token) and are not
+/// adjusted for decimals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HopTrace {
+    pub pool_id: PoolId,
+    /// True if the hop swaps token0 for token1
+    pub zero_for_one: bool,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    /// Fee paid to the pool, in the input token
+    pub fee_paid: U256,
+    /// Relative shortfall of the execution price (after fees) against the pre-trade spot price
+    pub price_impact: f64,
+    /// Spot price before the trade
+    pub spot_price_before: f64,
+    /// Spot price after the trade
+    pub spot_price_after: f64,
+    /// Minimum output to enforce for this hop (worst case under the slippage tolerance)
+    pub min_amount_out: U256,
+}
+
+/// Result of simulating a path backwards from a target output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExactOutQuote {
+    /// Smallest input that yields at least `amount_out`
+    pub amount_in: U256,
+    /// Target output of the final token
+    pub amount_out: U256,
+    /// Amount of every token of the path, from the required input to the target output
+    pub amounts: Vec<U256>,
+}
+
+impl ExactOutQuote {
+    /// What is left after paying the input, meaningful for cycles where input and output are the same token
+    pub fn surplus(&self) -> U256 {
+        self.amount_out.saturating_sub(self.amount_in)
+    }
+}
+
+/// Reserve state of a single pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolState {
+    pub reserve0: U256,
+    pub reserve1: U256,
+}
+
+impl PoolState {
+    pub fn new(reserve0: U256, reserve1: U256) -> Self {
+        Self { reserve0, reserve1 }
+    }
+
+    pub fn reserves(&self) -> (U256, U256) {
+        (self.reserve0, self.reserve1)
+    }
+}
+
+/// Token balances of the executor, read from the [StateCache] the data layer refreshes every block
+#[derive(Debug, Clone)]
+pub struct InventoryView {
+    pub owner: Address,
+    pub cache: Arc<StateCache>,
+}
+
+impl InventoryView {
+    pub fn new(owner: Address, cache: Arc<StateCache>) -> Self {
+        Self { owner, cache }
+    }
+
+    /// Balance of `token` available to trade, `None` when the balance is missing or expired
+    pub fn available(&self, token: Address) -> Option<U256> {
+        self.cache.get_balance(self.owner, token)
+    }
 }
 
 /// Market data snapshot containing pool reserves and other market information
+///
+/// Pool states are stored in a contiguous vector addressed by the dense pool index of
+/// `pool_index`, which is normally shared with the market that produced the snapshot.
+/// A shared index is never copied to add a pool, the states of pools missing from it are kept
+/// in `overflow_states` instead. The `PoolId` keyed accessors cover both and remain for
+/// compatibility.
 #[derive(Debug, Clone, Default)]
 pub struct MarketSnapshot {
-    /// Pool reserves: pool_id -> (reserve0, reserve1)
-    pub pool_reserves: HashMap<PoolId, (U256, U256)>,
+    /// Dense pool index layout of `pool_states` and the bitsets
+    pub pool_index: Arc<DensePoolIndex>,
+    /// Pool states indexed by dense pool index
+    pub pool_states: Vec<PoolState>,
+    /// Pools in `pool_states` that carry reserve data for this block
+    pub pool_data_bits: PoolBitSet,
+    /// Enabled pools (optimization to avoid repeated MarketWithoutLock queries)
+    pub enabled_pool_bits: PoolBitSet,
+    /// States of pools missing from a shared `pool_index`
+    pub overflow_states: HashMap<PoolId, PoolState>,
+    /// Enabled pools missing from a shared `pool_index`
+    pub overflow_enabled: HashSet<PoolId>,
     /// Timestamp when this snapshot was taken
     pub timestamp: u64,
     /// Block number from which this data comes
     pub block_number: u64,
-    /// Set of enabled pools (optimization to avoid repeated MarketWithoutLock queries)
-    pub enabled_pools: std::collections::HashSet<PoolId>,
     /// Total number of pools in the market (for statistics)
     pub total_pools_count: usize,
+    /// Whether the block was backfilled after a newer head had already arrived, its state is
+    /// outdated and only kept for continuity
+    pub backfill: bool,
 }
 
 impl MarketSnapshot {
+    /// Create an empty snapshot with a private pool index that grows as reserves are set
     pub fn new(block_number: u64) -> Self {
+        Self::with_pool_index(block_number, Arc::new(DensePoolIndex::new()))
+    }
+
+    /// Create an empty snapshot laid out by the given dense pool index (usually the market's)
+    pub fn with_pool_index(block_number: u64, pool_index: Arc<DensePoolIndex>) -> Self {
+        let pools_len = pool_index.len();
         Self {
-            pool_reserves: HashMap::new(),
+            pool_index,
+            pool_states: vec![PoolState::default(); pools_len],
+            pool_data_bits: PoolBitSet::with_len(pools_len),
+            enabled_pool_bits: PoolBitSet::with_len(pools_len),
+            overflow_states: HashMap::new(),
+            overflow_enabled: HashSet::new(),
             timestamp: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
             block_number,
-            enabled_pools: HashSet::new(),
             total_pools_count: 0,
+            backfill: false,
+        }
+    }
+
+    /// Get the dense index of a pool, an unknown pool is only added to a private pool index
+    fn dense_index(&mut self, pool_id: PoolId) -> Option<usize> {
+        if let Some(index) = self.pool_index.get(&pool_id) {
+            return Some(index);
         }
+        let index = Arc::get_mut(&mut self.pool_index)?.insert(pool_id);
+        if self.pool_states.len() <= index {
+            self.pool_states.resize(index + 1, PoolState::default());
+        }
+        Some(index)
     }
 
     pub fn set_pool_reserves(&mut self, pool_id: PoolId, reserve0: U256, reserve1: U256) {
-        self.pool_reserves.insert(pool_id, (reserve0, reserve1));
+        let state = PoolState::new(reserve0, reserve1);
+        match self.dense_index(pool_id) {
+            Some(index) => self.set_pool_state(index, state),
+            None => {
+                self.overflow_states.insert(pool_id, state);
+            }
+        }
     }
 
     pub fn get_pool_reserves(&self, pool_id: &PoolId) -> Option<(U256, U256)> {
-        self.pool_reserves.get(pool_id).copied()
+        self.pool_state(pool_id).map(|state| state.reserves())
+    }
+
+    /// Get the state of a pool by id, if the snapshot has data for it
+    pub fn pool_state(&self, pool_id: &PoolId) -> Option<&PoolState> {
+        match self.pool_index.get(pool_id) {
+            Some(index) => self.get_pool_state(index),
+            None => self.overflow_states.get(pool_id),
+        }
+    }
+
+    /// Reserves of all pools with data, keyed by pool id
+    #[deprecated(note = "the reserves are no longer stored by pool id, use `iter_pool_reserves` or `get_pool_reserves`")]
+    pub fn pool_reserves(&self) -> HashMap<PoolId, (U256, U256)> {
+        self.iter_pool_reserves().collect()
+    }
+
+    /// Set the state of a pool by dense index. The index must be part of `pool_index`.
+    pub fn set_pool_state(&mut self, index: usize, state: PoolState) {
+        if self.pool_states.len() <= index {
+            self.pool_states.resize(index + 1, PoolState::default());
+        }
+        self.pool_states[index] = state;
+        self.pool_data_bits.insert(index);
+    }
+
+    /// Get the state of a pool by dense index, if the snapshot has data for it
+    #[inline]
+    pub fn get_pool_state(&self, index: usize) -> Option<&PoolState> {
+        if self.pool_data_bits.contains(index) { self.pool_states.get(index) } else { None }
+    }
+
+    /// Iterate over all pools with reserve data as (pool_id, (reserve0, reserve1))
+    pub fn iter_pool_reserves(&self) -> impl Iterator<Item = (PoolId, (U256, U256))> + '_ {
+        self.pool_data_bits
+            .iter()
+            .filter_map(|index| Some((self.pool_index.pool_id(index)?, self.pool_states[index].reserves())))
+            .chain(self.overflow_states.iter().map(|(pool_id, state)| (*pool_id, state.reserves())))
+    }
+
+    /// Number of pools with reserve data
+    pub fn pools_with_data_count(&self) -> usize {
+        self.pool_data_bits.count() + self.overflow_states.len()
     }
 
     /// Set the enabled pools for this snapshot (optimization method)
     pub fn set_enabled_pools(&mut self, enabled_pools: HashSet<PoolId>) {
-        self.enabled_pools = enabled_pools;
+        self.enabled_pool_bits.clear();
+        self.overflow_enabled.clear();
+        for pool_id in enabled_pools {
+            match self.dense_index(pool_id) {
+                Some(index) => self.enabled_pool_bits.insert(index),
+                None => {
+                    self.overflow_enabled.insert(pool_id);
+                }
+            }
+        }
+    }
+
+    /// Get the ids of all enabled pools
+    #[deprecated(note = "the enabled pools are no longer stored by pool id, use `is_pool_enabled` or `enabled_pool_bits`")]
+    pub fn enabled_pools(&self) -> HashSet<PoolId> {
+        self.enabled_pool_bits
+            .iter()
+            .filter_map(|index| self.pool_index.pool_id(index))
+            .chain(self.overflow_enabled.iter().copied())
+            .collect()
+    }
+
+    /// Number of enabled pools
+    pub fn enabled_pools_count(&self) -> usize {
+        self.enabled_pool_bits.count() + self.overflow_enabled.len()
     }
 
     /// Set the total pools count for this snapshot
@@ -113,47 +404,61 @@ impl MarketSnapshot {
 
     /// Get pools that are enabled and have sufficient liquidity
     pub fn get_liquid_enabled_pools(&self, min_liquidity: U256) -> Vec<PoolId> {
-        self.pool_reserves
+        let is_liquid = |state: &PoolState| state.reserve0 > min_liquidity && state.reserve1 > min_liquidity;
+        self.pool_data_bits
             .iter()
-            .filter(|(pool_id, (reserve0, reserve1))| {
-                self.enabled_pools.contains(pool_id) && 
-                *reserve0 > min_liquidity && 
-                *reserve1 > min_liquidity
-            })
-            .map(|(pool_id, _)| *pool_id)
+            .filter(|index| self.enabled_pool_bits.contains(*index) && is_liquid(&self.pool_states[*index]))
+            .filter_map(|index| self.pool_index.pool_id(index))
+            .chain(
+                self.overflow_states
+                    .iter()
+                    .filter(|(pool_id, state)| self.overflow_enabled.contains(*pool_id) && is_liquid(state))
+                    .map(|(pool_id, _)| *pool_id),
+            )
             .collect()
     }
 
     /// Check if a pool is enabled in this snapshot
     pub fn is_pool_enabled(&self, pool_id: &PoolId) -> bool {
-        self.enabled_pools.contains(pool_id)
+        match self.pool_index.get(pool_id) {
+            Some(index) => self.enabled_pool_bits.contains(index),
+            None => self.overflow_enabled.contains(pool_id),
+        }
     }
 
     /// Get the number of enabled pools with reserves data
     pub fn enabled_pools_with_data_count(&self) -> usize {
-        self.pool_reserves
-            .keys()
-            .filter(|pool_id| self.enabled_pools.contains(pool_id))
-            .count()
+        let indexed = self.pool_data_bits.iter().filter(|index| self.enabled_pool_bits.contains(*index)).count();
+        let overflow = self.overflow_states.keys().filter(|pool_id| self.overflow_enabled.contains(*pool_id)).count();
+        indexed + overflow
     }
 }
 
 /// Result of profit calculation for a single path
+///
+/// The path is referenced by its index in the [CompiledPathSet] it was evaluated from
+/// and by its hash, so results can be produced for every path without cloning it.
 #[derive(Debug, Clone)]
 pub struct ProfitCalculationResult {
-    pub path: SwapPath,
+    pub path_index: usize,
+    pub swap_path_hash: SwapPathHash,
     pub optimal_input_amount: U256,
     pub expected_output_amount: U256,
     pub gross_profit_mnt_wei: U256,
     pub gas_cost_mnt_wei: U256,
     pub net_profit_mnt_wei: U256,
     pub calculation_successful: bool,
-    pub error_message: Option<String>,
+    pub error: Option<CalculationFailure>,
+    /// Per-hop breakdown at the optimal input amount, empty for failed calculations
+    pub hops: Vec<HopTrace>,
+    /// Financing, inventory cap and worst-case output, default for failed calculations
+    pub execution: ExecutionDetails,
 }
 
 impl ProfitCalculationResult {
     pub fn success(
-        path: SwapPath,
+        path_index: usize,
+        swap_path_hash: SwapPathHash,
         optimal_input_amount: U256,
         expected_output_amount: U256,
         gross_profit_mnt_wei: U256,
@@ -166,47 +471,252 @@ impl ProfitCalculationResult {
         };
         
         Self {
-            path,
+            path_index,
+            swap_path_hash,
             optimal_input_amount,
             expected_output_amount,
             gross_profit_mnt_wei,
             gas_cost_mnt_wei,
             net_profit_mnt_wei,
             calculation_successful: true,
-            error_message: None,
+            error: None,
+            hops: Vec::new(),
+            execution: ExecutionDetails::exact(optimal_input_amount, expected_output_amount, gas_cost_mnt_wei),
         }
     }
 
-    pub fn failure(path: SwapPath, error_message: String) -> Self {
+    /// Attach the per-hop breakdown
+    pub fn with_hops(mut self, hops: Vec<HopTrace>) -> Self {
+        self.hops = hops;
+        self
+    }
+
+    /// Attach how the opportunity is executed
+    pub fn with_execution(mut self, execution: ExecutionDetails) -> Self {
+        self.execution = execution;
+        self
+    }
+
+    pub fn failure(path_index: usize, swap_path_hash: SwapPathHash, error: &CalculationError) -> Self {
         Self {
-            path,
+            path_index,
+            swap_path_hash,
             optimal_input_amount: U256::ZERO,
             expected_output_amount: U256::ZERO,
             gross_profit_mnt_wei: U256::ZERO,
             gas_cost_mnt_wei: U256::ZERO,
             net_profit_mnt_wei: U256::ZERO,
             calculation_successful: false,
-            error_message: Some(error_message),
+            error: Some(CalculationFailure::from(error)),
+            hops: Vec::new(),
+            execution: ExecutionDetails::default(),
         }
     }
 
-    pub fn to_opportunity(&self) -> Option<ArbitrageOpportunity> {
+    /// The error message of a failed calculation
+    pub fn error_message(&self) -> Option<&str> {
+        self.error.as_ref().map(|error| error.message.as_str())
+    }
+
+    /// Materialise the opportunity, cloning the full path from the set the result was calculated on
+    pub fn to_opportunity(&self, paths: &CompiledPathSet) -> Option<ArbitrageOpportunity> {
         if self.calculation_successful && !self.net_profit_mnt_wei.is_zero() {
-            Some(ArbitrageOpportunity::new(
-                self.path.clone(),
+            let opportunity = ArbitrageOpportunity::new(
+                paths.swap_path(self.path_index)?.clone(),
                 self.optimal_input_amount,
                 self.expected_output_amount,
                 self.gross_profit_mnt_wei,
                 self.gas_cost_mnt_wei,
-            ))
+            )
+            .with_hops(self.hops.clone())
+            .with_execution(self.execution.clone());
+            Some(opportunity)
         } else {
             None
         }
     }
 }
 
+/// Why the profit calculation of a path failed
+///
+/// A cloneable record of a [CalculationError], kept in [ProfitCalculationResult].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalculationFailure {
+    pub kind: CalculationErrorKind,
+    /// The pool that caused the failure, if the error is specific to one pool
+    pub pool_id: Option<PoolId>,
+    pub message: String,
+}
+
+impl From<&CalculationError> for CalculationFailure {
+    fn from(error: &CalculationError) -> Self {
+        Self { kind: error.kind(), pool_id: error.pool_id(), message: error.to_string() }
+    }
+}
+
+/// Why the evaluation of a snapshot stopped before every path was evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruncationReason {
+    /// The evaluation deadline passed
+    DeadlineExceeded,
+    /// A snapshot of a newer block is already waiting
+    NewerBlock { latest_block: u64 },
+}
+
+/// Conditions under which the evaluation of a snapshot is abandoned
+///
+/// The profit calculator checks them between work chunks, so a chunk that already started is
+/// finished. Without any condition the evaluation always runs to completion.
+#[derive(Debug, Clone, Default)]
+pub struct CalculationCutoff {
+    deadline: Option<Instant>,
+    latest_block: Option<Arc<AtomicU64>>,
+}
+
+impl CalculationCutoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop once `latest_block` holds a block number above the evaluated one
+    pub fn with_latest_block(mut self, latest_block: Arc<AtomicU64>) -> Self {
+        self.latest_block = Some(latest_block);
+        self
+    }
+
+    /// Whether the evaluation of `block_number` should stop now
+    pub fn check(&self, block_number: u64) -> Option<TruncationReason> {
+        if let Some(latest_block) = &self.latest_block {
+            let latest_block = latest_block.load(Ordering::Acquire);
+            if latest_block > block_number {
+                return Some(TruncationReason::NewerBlock { latest_block });
+            }
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Some(TruncationReason::DeadlineExceeded),
+            _ => None,
+        }
+    }
+}
+
+/// A block identified by its number and hash
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockRef {
+    pub number: u64,
+    pub hash: String,
+}
+
+/// A chain reorganization detected by the data layer
+///
+/// Snapshots and results of the orphaned blocks describe a fork that is no longer canonical.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReorgEvent {
+    /// Last block shared by both forks, unknown when the reorg is deeper than the tracked blocks
+    pub common_ancestor: Option<u64>,
+    /// Blocks of the abandoned fork, oldest first
+    pub orphaned: Vec<BlockRef>,
+    /// Head of the new canonical fork
+    pub new_head: BlockRef,
+}
+
+impl ReorgEvent {
+    /// Number of orphaned blocks
+    pub fn depth(&self) -> usize {
+        self.orphaned.len()
+    }
+
+    /// Results of this block number and later ones may come from the orphaned fork
+    pub fn first_orphaned_block(&self) -> u64 {
+        match self.common_ancestor {
+            Some(common_ancestor) => common_ancestor + 1,
+            None => self.orphaned.iter().map(|block| block.number).min().unwrap_or(self.new_head.number),
+        }
+    }
+}
+
+/// Profit results of a snapshot, possibly of only part of its paths
+#[derive(Debug, Clone, Default)]
+pub struct ProfitCalculationBatch {
+    /// Results of the evaluated paths, by path index
+    pub results: Vec<ProfitCalculationResult>,
+    /// Number of paths that were to be evaluated
+    pub paths_count: usize,
+    pub truncation: Option<TruncationReason>,
+}
+
+impl ProfitCalculationBatch {
+    pub fn is_truncated(&self) -> bool {
+        self.truncation.is_some()
+    }
+}
+
+/// Profit calculation failures of one block, counted by error kind and by pool
+#[derive(Debug, Clone, Default)]
+pub struct CalculationFailureStats {
+    pub block_number: u64,
+    pub paths_count: usize,
+    pub failed_paths_count: usize,
+    pub by_kind: HashMap<CalculationErrorKind, usize>,
+    pub by_pool: HashMap<PoolId, HashMap<CalculationErrorKind, usize>>,
+    /// Set when the evaluation stopped before every path was evaluated
+    pub truncation: Option<TruncationReason>,
+}
+
+impl CalculationFailureStats {
+    pub fn from_results(block_number: u64, results: &[ProfitCalculationResult]) -> Self {
+        let mut stats = Self { block_number, paths_count: results.len(), ..Self::default() };
+        for failure in results.iter().filter_map(|result| result.error.as_ref()) {
+            stats.failed_paths_count += 1;
+            *stats.by_kind.entry(failure.kind).or_default() += 1;
+            if let Some(pool_id) = failure.pool_id {
+                *stats.by_pool.entry(pool_id).or_default().entry(failure.kind).or_default() += 1;
+            }
+        }
+        stats
+    }
+
+    /// Number of failed paths of the given kind
+    pub fn count(&self, kind: CalculationErrorKind) -> usize {
+        self.by_kind.get(&kind).copied().unwrap_or_default()
+    }
+
+    /// Number of failed paths of the given kind caused by a pool
+    pub fn pool_count(&self, pool_id: &PoolId, kind: CalculationErrorKind) -> usize {
+        self.by_pool.get(pool_id).and_then(|counts| counts.get(&kind)).copied().unwrap_or_default()
+    }
+
+    /// Number of paths that failed because of bad or missing market data
+    pub fn data_error_count(&self) -> usize {
+        self.by_kind.iter().filter(|(kind, _)| kind.is_data_error()).map(|(_, count)| count).sum()
+    }
+
+    /// Number of paths that were evaluated correctly but are not profitable
+    pub fn unprofitable_count(&self) -> usize {
+        self.count(CalculationErrorKind::Unprofitable)
+    }
+
+    /// Pools causing the most failures, as (pool_id, failed paths), descending
+    pub fn top_failing_pools(&self, limit: usize) -> Vec<(PoolId, usize)> {
+        let mut pools: Vec<(PoolId, usize)> =
+            self.by_pool.iter().map(|(pool_id, counts)| (*pool_id, counts.values().sum())).collect();
+        pools.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        pools.truncate(limit);
+        pools
+    }
+}
+
 /// Configuration for the arbitrage engine
-#[derive(Debug, Clone)]
+///
+/// Loadable from the `[arbitrage]` section of a TOML file, missing keys keep their default.
+/// `min_profit_threshold_mnt_wei` is written as a decimal or `0x` string since TOML integers
+/// stop at `i64`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ArbitrageConfig {
     /// Minimum profit threshold in MNT Wei to consider an opportunity
     pub min_profit_threshold_mnt_wei: U256,
@@ -220,6 +730,23 @@ pub struct ArbitrageConfig {
     pub max_precomputed_paths: usize,
     /// Enable parallel profit calculation
     pub enable_parallel_calculation: bool,
+    /// Slippage tolerance in basis points. Opportunities are re-simulated with every pool
+    /// price moved against the trade by this much, and must stay above the profit threshold.
+    pub slippage_tolerance_bps: u32,
+    /// Borrow the input from the first pool of a path when it supports flash swaps
+    pub enable_flash_swaps: bool,
+    /// Fee charged for flash-borrowed input, in basis points of the borrowed amount
+    pub flash_swap_fee_bps: u32,
+    /// Additional gas used by the flash swap callback
+    pub flash_swap_callback_gas: u64,
+    /// How the execution plan of a block is selected from its opportunities
+    pub selection_mode: SelectionMode,
+    /// Maximum number of opportunities followed across blocks
+    pub max_tracked_opportunities: usize,
+    /// Stop evaluating a snapshot after this many milliseconds and keep the partial results
+    pub max_evaluation_time_ms: Option<u64>,
+    /// Stop evaluating a snapshot as soon as a newer block arrived on the market data channel
+    pub cancel_on_newer_block: bool,
 }
 
 impl Default for ArbitrageConfig {
@@ -232,6 +759,14 @@ impl Default for ArbitrageConfig {
             gas_per_transaction: 700_000_000, // 700M gas as mentioned by user
             max_precomputed_paths: 10_000,
             enable_parallel_calculation: true,
+            slippage_tolerance_bps: 50, // 0.5%
+            enable_flash_swaps: false,
+            flash_swap_fee_bps: 0, // UniswapV2 style pools only charge the regular swap fee
+            flash_swap_callback_gas: 50_000_000,
+            selection_mode: SelectionMode::Greedy,
+            max_tracked_opportunities: 10_000,
+            max_evaluation_time_ms: None,
+            cancel_on_newer_block: true,
         }
     }
 }
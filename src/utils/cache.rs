@@ -98,6 +98,11 @@ impl StateCache {
         self.balances.insert(key, item);
     }
 
+    /// 删除余额
+    pub fn remove_balance(&self, address: Address, token: Address) {
+        self.balances.remove(&(address, token));
+    }
+
     /// 获取存储
     pub fn get_storage(&self, address: Address, slot: U256) -> Option<U256> {
         let key = (address, slot);
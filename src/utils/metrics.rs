@@ -0,0 +1,365 @@
+use eyre::Result;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+const LATENCY_BUCKETS_SECONDS: &[f64] = &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const COUNT_BUCKETS: &[f64] = &[0.0, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0];
+
+/// Pipeline stage a metric is labelled with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    /// From receiving a block header to a validated market snapshot
+    BlockToSnapshot,
+    /// One multicall batch of reserve queries
+    MulticallBatch,
+    /// Executor balance refresh after the reserves
+    BalanceRefresh,
+    /// Profit calculation over the precomputed paths
+    ProfitCalculation,
+    /// Registered strategies run on a snapshot
+    Strategies,
+    /// Hand-over of snapshots from the data layer to the engine
+    Delivery,
+    /// Fetching the `Sync` logs of a block in event-driven reserve sync
+    SyncLogs,
+    /// Full multicall resync of all monitored pools in event-driven reserve sync
+    FullResync,
+    /// Chain reorganizations seen by the block tracker, counted in orphaned blocks
+    Reorg,
+    /// Missed blocks whose snapshots are built from historical state
+    Backfill,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::BlockToSnapshot => "block_to_snapshot",
+            Stage::MulticallBatch => "multicall_batch",
+            Stage::BalanceRefresh => "balance_refresh",
+            Stage::ProfitCalculation => "profit_calculation",
+            Stage::Strategies => "strategies",
+            Stage::Delivery => "delivery",
+            Stage::SyncLogs => "sync_logs",
+            Stage::FullResync => "full_resync",
+            Stage::Reorg => "reorg",
+            Stage::Backfill => "backfill",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Histogram,
+}
+
+/// The metrics of the pipeline, each one is labelled by [Stage]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Metric {
+    StageDurationSeconds,
+    StageFailuresTotal,
+    BlocksTotal,
+    BlocksSkippedTotal,
+    TruncatedEvaluationsTotal,
+    PathsEvaluatedTotal,
+    OpportunitiesFoundTotal,
+    OpportunitiesPerBlock,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 8] = [
+        Metric::StageDurationSeconds,
+        Metric::StageFailuresTotal,
+        Metric::BlocksTotal,
+        Metric::BlocksSkippedTotal,
+        Metric::TruncatedEvaluationsTotal,
+        Metric::PathsEvaluatedTotal,
+        Metric::OpportunitiesFoundTotal,
+        Metric::OpportunitiesPerBlock,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::StageDurationSeconds => "swap_path_stage_duration_seconds",
+            Metric::StageFailuresTotal => "swap_path_stage_failures_total",
+            Metric::BlocksTotal => "swap_path_blocks_total",
+            Metric::BlocksSkippedTotal => "swap_path_blocks_skipped_total",
+            Metric::TruncatedEvaluationsTotal => "swap_path_truncated_evaluations_total",
+            Metric::PathsEvaluatedTotal => "swap_path_paths_evaluated_total",
+            Metric::OpportunitiesFoundTotal => "swap_path_opportunities_found_total",
+            Metric::OpportunitiesPerBlock => "swap_path_opportunities_per_block",
+        }
+    }
+
+    pub fn help(&self) -> &'static str {
+        match self {
+            Metric::StageDurationSeconds => "Time spent in a pipeline stage",
+            Metric::StageFailuresTotal => "Failed executions of a pipeline stage",
+            Metric::BlocksTotal => "Blocks that went through a pipeline stage",
+            Metric::BlocksSkippedTotal => "Blocks a pipeline stage never saw because a newer block superseded them",
+            Metric::TruncatedEvaluationsTotal => "Blocks whose evaluation stopped early on a deadline or a newer block",
+            Metric::PathsEvaluatedTotal => "Paths whose profit was evaluated",
+            Metric::OpportunitiesFoundTotal => "Opportunities above the profit threshold",
+            Metric::OpportunitiesPerBlock => "Opportunities above the profit threshold in a single block",
+        }
+    }
+
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            Metric::StageDurationSeconds | Metric::OpportunitiesPerBlock => MetricKind::Histogram,
+            _ => MetricKind::Counter,
+        }
+    }
+
+    fn buckets(&self) -> &'static [f64] {
+        match self {
+            Metric::StageDurationSeconds => LATENCY_BUCKETS_SECONDS,
+            Metric::OpportunitiesPerBlock => COUNT_BUCKETS,
+            _ => &[],
+        }
+    }
+}
+
+/// Observations of a histogram, bucket counts are not cumulative
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    pub bounds: &'static [f64],
+    pub bucket_counts: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl HistogramSnapshot {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self { bounds, bucket_counts: vec![0; bounds.len()], count: 0, sum: 0.0 }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.bucket_counts[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Series {
+    Counter(u64),
+    Histogram(HistogramSnapshot),
+}
+
+/// Counters and histograms of the pipeline, rendered in the Prometheus text format
+///
+/// Components record into a shared registry, see e.g. `DataSyncService::with_metrics` and
+/// `ArbitrageEngineBuilder::with_metrics`. A series only appears once it was recorded.
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    series: Mutex<BTreeMap<(Metric, Stage), Series>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `value` to a counter
+    pub fn increment(&self, metric: Metric, stage: Stage, value: u64) {
+        debug_assert_eq!(metric.kind(), MetricKind::Counter);
+        if let Ok(mut series) = self.series.lock()
+            && let Series::Counter(counter) = series.entry((metric, stage)).or_insert(Series::Counter(0))
+        {
+            *counter += value;
+        }
+    }
+
+    /// Record a value in a histogram
+    pub fn observe(&self, metric: Metric, stage: Stage, value: f64) {
+        debug_assert_eq!(metric.kind(), MetricKind::Histogram);
+        if let Ok(mut series) = self.series.lock()
+            && let Series::Histogram(histogram) =
+                series.entry((metric, stage)).or_insert_with(|| Series::Histogram(HistogramSnapshot::new(metric.buckets())))
+        {
+            histogram.observe(value);
+        }
+    }
+
+    /// Record the duration of a stage
+    pub fn observe_duration(&self, stage: Stage, duration: Duration) {
+        self.observe(Metric::StageDurationSeconds, stage, duration.as_secs_f64());
+    }
+
+    pub fn counter(&self, metric: Metric, stage: Stage) -> u64 {
+        match self.series.lock().ok().and_then(|series| series.get(&(metric, stage)).cloned()) {
+            Some(Series::Counter(counter)) => counter,
+            _ => 0,
+        }
+    }
+
+    pub fn histogram(&self, metric: Metric, stage: Stage) -> Option<HistogramSnapshot> {
+        match self.series.lock().ok()?.get(&(metric, stage)) {
+            Some(Series::Histogram(histogram)) => Some(histogram.clone()),
+            _ => None,
+        }
+    }
+
+    /// All recorded series in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let Ok(series) = self.series.lock() else {
+            return String::new();
+        };
+
+        let mut output = String::new();
+        for metric in Metric::ALL {
+            let mut recorded = series.iter().filter(|((recorded_metric, _), _)| *recorded_metric == metric).peekable();
+            if recorded.peek().is_none() {
+                continue;
+            }
+
+            let name = metric.name();
+            let kind = match metric.kind() {
+                MetricKind::Counter => "counter",
+                MetricKind::Histogram => "histogram",
+            };
+            let _ = writeln!(output, "# HELP {} {}", name, metric.help());
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+
+            for ((_, stage), value) in recorded {
+                let stage = stage.as_str();
+                match value {
+                    Series::Counter(counter) => {
+                        let _ = writeln!(output, "{}{{stage=\"{}\"}} {}", name, stage, counter);
+                    }
+                    Series::Histogram(histogram) => {
+                        let mut cumulative = 0;
+                        for (bound, bucket_count) in histogram.bounds.iter().zip(&histogram.bucket_counts) {
+                            cumulative += bucket_count;
+                            let _ = writeln!(output, "{}_bucket{{stage=\"{}\",le=\"{}\"}} {}", name, stage, bound, cumulative);
+                        }
+                        let _ = writeln!(output, "{}_bucket{{stage=\"{}\",le=\"+Inf\"}} {}", name, stage, histogram.count);
+                        let _ = writeln!(output, "{}_sum{{stage=\"{}\"}} {}", name, stage, histogram.sum);
+                        let _ = writeln!(output, "{}_count{{stage=\"{}\"}} {}", name, stage, histogram.count);
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Serve `GET /metrics` on `addr` in the background, returns the bound address
+///
+/// Meant for a local scraper: every connection gets a single response and is closed.
+pub async fn spawn_metrics_server(addr: &str, registry: Arc<MetricsRegistry>) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    info!("Serving metrics on http://{}/metrics", local_addr);
+
+    let task = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let registry = Arc::clone(&registry);
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_metrics_request(stream, &registry).await {
+                            debug!("Metrics request from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept metrics connection: {}", e),
+            }
+        }
+    });
+
+    Ok((local_addr, task))
+}
+
+async fn handle_metrics_request(mut stream: TcpStream, registry: &MetricsRegistry) -> Result<()> {
+    // Only the request line matters, read until the end of the headers
+    let mut request = Vec::with_capacity(1024);
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8192 {
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buffer)).await??;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (status, content_type, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4", registry.render()),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "method not allowed\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let registry = MetricsRegistry::new();
+        registry.increment(Metric::PathsEvaluatedTotal, Stage::ProfitCalculation, 120);
+        registry.increment(Metric::PathsEvaluatedTotal, Stage::ProfitCalculation, 30);
+        registry.observe_duration(Stage::MulticallBatch, Duration::from_millis(3));
+        registry.observe_duration(Stage::MulticallBatch, Duration::from_millis(40));
+
+        assert_eq!(registry.counter(Metric::PathsEvaluatedTotal, Stage::ProfitCalculation), 150);
+        let histogram = registry.histogram(Metric::StageDurationSeconds, Stage::MulticallBatch).unwrap();
+        assert_eq!(histogram.count, 2);
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE swap_path_paths_evaluated_total counter\n"));
+        assert!(text.contains("swap_path_paths_evaluated_total{stage=\"profit_calculation\"} 150\n"));
+        assert!(text.contains("# TYPE swap_path_stage_duration_seconds histogram\n"));
+        assert!(text.contains("swap_path_stage_duration_seconds_bucket{stage=\"multicall_batch\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("swap_path_stage_duration_seconds_bucket{stage=\"multicall_batch\",le=\"0.05\"} 2\n"));
+        assert!(text.contains("swap_path_stage_duration_seconds_count{stage=\"multicall_batch\"} 2\n"));
+        // Nothing recorded, nothing rendered
+        assert!(!text.contains("swap_path_blocks_total"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() -> Result<()> {
+        let registry = Arc::new(MetricsRegistry::new());
+        registry.increment(Metric::BlocksTotal, Stage::BlockToSnapshot, 1);
+        let (addr, task) = spawn_metrics_server("127.0.0.1:0", Arc::clone(&registry)).await?;
+
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("swap_path_blocks_total{stage=\"block_to_snapshot\"} 1\n"));
+
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        assert!(response.starts_with("HTTP/1.1 404"));
+
+        task.abort();
+        Ok(())
+    }
+}
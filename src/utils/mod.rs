@@ -2,8 +2,10 @@ pub mod token;
 pub mod constants;
 pub mod config_loader;
 pub mod cache;
+pub mod metrics;
 
 pub use token::{Token, TokenWrapper};
 pub use constants::*;
 pub use config_loader::*;
 pub use cache::{StateCache, CachedStateProvider, CacheStats, CacheManager};
+pub use metrics::{HistogramSnapshot, Metric, MetricKind, MetricsRegistry, Stage, spawn_metrics_server};